@@ -0,0 +1,448 @@
+//! Alerts - price alerts notify when a quote crosses a threshold, keyword alerts when a new
+//! item of a command contains every word. After an alert fires, further ones for the same
+//! chat and source or keyword wait for a single follow-up at the end of the burst window.
+
+use crate::consts::{find_source, limits, Source};
+use crate::logic::{fetch_target, matches_words, query_words, routes, FetchOptions};
+use crate::network::{NewsItem, Quote};
+use crate::outgoing::is_chat_gone;
+use crate::state::{AppState, Direction, KeywordAlert, PriceAlert, SettingsError};
+use crate::subscriptions::Seen;
+use crate::utils::fingerprint;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use teloxide::prelude::*;
+
+/// Alerts that fired while a window was open, for the follow-up
+struct Burst {
+    /// "Gold alerts", "items matched 'strike' in /war"
+    label: String,
+    ends: Instant,
+    more: usize,
+    /// The first `BURST_SAMPLE_LINES` of them
+    sample: Vec<String>,
+}
+
+impl Burst {
+    fn follow_up(&self) -> String {
+        let mut text = format!("🔔 +{} more {}", self.more, self.label);
+        for line in &self.sample {
+            text.push('\n');
+            text.push_str(line);
+        }
+        if self.more > self.sample.len() {
+            text.push_str("\n…");
+        }
+        text
+    }
+}
+
+/// Open alert windows per chat and key. The first alert goes out at once and opens a window;
+/// alerts inside it are collected into one follow-up sent when it ends. A window that
+/// collected something starts again, so a steady stream gets one message per window.
+pub struct Bursts {
+    window: Duration,
+    open: Mutex<HashMap<(ChatId, String), Burst>>,
+}
+
+impl Bursts {
+    pub fn new(window: Duration) -> Self {
+        Self { window, open: Mutex::new(HashMap::new()) }
+    }
+
+    /// True when `line` should be sent now; false when it was held for the follow-up
+    pub fn offer(&self, chat: ChatId, key: &str, label: &str, line: String, now: Instant) -> bool {
+        let mut open = self.open.lock().unwrap();
+        match open.get_mut(&(chat, key.to_string())) {
+            Some(burst) if now < burst.ends => {
+                burst.more += 1;
+                if burst.sample.len() < limits::BURST_SAMPLE_LINES {
+                    burst.sample.push(line);
+                }
+                false
+            }
+            _ => {
+                let burst = Burst { label: label.to_string(), ends: now + self.window, more: 0, sample: Vec::new() };
+                open.insert((chat, key.to_string()), burst);
+                true
+            }
+        }
+    }
+
+    /// Follow-ups of the windows ended by `now`; quiet windows are closed
+    pub fn due(&self, now: Instant) -> Vec<(ChatId, String)> {
+        let mut due = Vec::new();
+        self.open.lock().unwrap().retain(|(chat, _), burst| {
+            if now < burst.ends {
+                return true;
+            }
+            if burst.more == 0 {
+                return false;
+            }
+            due.push((*chat, burst.follow_up()));
+            *burst = Burst { label: std::mem::take(&mut burst.label), ends: now + self.window, more: 0, sample: Vec::new() };
+            true
+        });
+        due
+    }
+
+    /// Every pending follow-up, closing all windows; for shutdown
+    pub fn drain(&self) -> Vec<(ChatId, String)> {
+        self.open
+            .lock()
+            .unwrap()
+            .drain()
+            .filter(|(_, burst)| burst.more > 0)
+            .map(|((chat, _), burst)| (chat, burst.follow_up()))
+            .collect()
+    }
+}
+
+/// Runs for the life of the bot, sending follow-ups as their windows end
+pub async fn run_bursts(bot: Bot, bursts: Arc<Bursts>) {
+    let mut tick = tokio::time::interval(Duration::from_secs(limits::BURST_TICK_SECS));
+    loop {
+        tick.tick().await;
+        send_follow_ups(&bot, bursts.due(Instant::now())).await;
+    }
+}
+
+/// Send what is still collected, so a shutdown does not swallow it
+pub async fn flush_bursts(bot: &Bot, bursts: &Bursts) {
+    send_follow_ups(bot, bursts.drain()).await;
+}
+
+async fn send_follow_ups(bot: &Bot, follow_ups: Vec<(ChatId, String)>) {
+    for (chat_id, text) in follow_ups {
+        if let Err(e) = bot.send_message(chat_id, text).await {
+            log::warn!("Alert follow-up to {} failed: {}", chat_id, e);
+        }
+    }
+}
+
+/// Whether a move from `previous` to `current` crosses `threshold` in `direction`.
+/// Without a previous reading there is nothing to cross yet.
+pub fn crossed(direction: Direction, threshold: f64, previous: Option<f64>, current: f64) -> bool {
+    let Some(previous) = previous else { return false };
+    match direction {
+        Direction::Above => previous < threshold && current >= threshold,
+        Direction::Below => previous > threshold && current <= threshold,
+    }
+}
+
+/// Runs for the life of the bot. Each alert fires once and is then removed; a price already
+/// past the threshold when the alert is set has to come back and cross it.
+pub async fn run(bot: Bot, state: AppState) {
+    // Last parsed price per source, the "previous" side of a crossing
+    let mut last: HashMap<&'static str, f64> = HashMap::new();
+    let mut tick = tokio::time::interval(Duration::from_secs(limits::ALERT_POLL_SECS));
+    loop {
+        tick.tick().await;
+        let alerts = state.settings.alerts();
+        if alerts.is_empty() || state.maintenance.is_on() {
+            continue;
+        }
+
+        let mut quotes: HashMap<&'static str, Quote> = HashMap::new();
+        for (_, alert) in &alerts {
+            let Some(source) = find_source(&alert.source) else { continue };
+            if quotes.contains_key(source.name) {
+                continue;
+            }
+            if let Some(quote) = current_quote(&state, source).await {
+                quotes.insert(source.name, quote);
+            }
+        }
+
+        for (chat_id, alert) in alerts {
+            let Some(source) = find_source(&alert.source) else { continue };
+            let Some(quote) = quotes.get(source.name) else { continue };
+            let Some(value) = quote.value else { continue };
+            if !crossed(alert.direction, alert.threshold, last.get(source.name).copied(), value) {
+                continue;
+            }
+
+            let change = quote.change.as_deref().map(|c| format!(" ({})", c)).unwrap_or_default();
+            let line = format!("{} is {} {}: {}{}", source.name, alert.direction, alert.threshold, quote.price, change);
+            let sent = match state.bursts.offer(chat_id, source.name, &format!("{} alerts", source.name), line.clone(), Instant::now()) {
+                true => bot.send_message(chat_id, format!("🔔 {}", line)).await.map(drop),
+                // Held for the follow-up, which counts as delivered
+                false => Ok(()),
+            };
+            // A transient failure keeps the alert for the next crossing
+            match sent {
+                Ok(()) => {
+                    let _ = state.settings.update(chat_id, |s| {
+                        s.alerts.retain(|a| *a != alert);
+                        Ok::<_, SettingsError>(())
+                    });
+                }
+                Err(e) if is_chat_gone(&e) => {
+                    log::info!("Chat {} is gone, dropping its alerts", chat_id);
+                    let _ = state.settings.update(chat_id, |s| {
+                        s.alerts.clear();
+                        Ok::<_, SettingsError>(())
+                    });
+                }
+                Err(e) => log::warn!("Price alert to {} failed, keeping it: {}", chat_id, e),
+            }
+        }
+
+        for (name, quote) in quotes {
+            if let Some(value) = quote.value {
+                last.insert(name, value);
+            }
+        }
+    }
+}
+
+/// The source's quote from the response cache when fresh, otherwise from a new fetch
+async fn current_quote(state: &AppState, source: &'static Source) -> Option<Quote> {
+    if state.engine.is_paused(source) {
+        return None;
+    }
+    let items = match state.engine.cached(source, limits::MAX_ITEMS_PER_SOURCE).await {
+        Some(items) => items,
+        None => {
+            let started = Instant::now();
+            let (outcome, _) = state.engine.fetch_with_retry(source, limits::MAX_ITEMS_PER_SOURCE, limits::fetch_attempts()).await;
+            state.engine.record_status(source, &outcome, started.elapsed());
+            outcome.map_err(|e| log::warn!("Price alert fetch of {} failed: {}", source.name, e)).ok()?
+        }
+    };
+    items.into_iter().find_map(|item| item.quote)
+}
+
+/// An item with the name of its source
+type SourceItem = (&'static str, NewsItem);
+
+/// Runs for the life of the bot. Each command with keyword alerts is fetched once per poll
+/// and language; like subscriptions, the first poll of an alert only records what is there.
+pub async fn run_keywords(bot: Bot, state: AppState) {
+    let mut seen: HashMap<(ChatId, KeywordAlert), Seen> = HashMap::new();
+    let mut tick = tokio::time::interval(Duration::from_secs(limits::ALERT_POLL_SECS));
+    loop {
+        tick.tick().await;
+        let alerts = state.settings.keyword_alerts();
+        let active: HashSet<&(ChatId, KeywordAlert)> = alerts.iter().collect();
+        seen.retain(|key, _| active.contains(key));
+        if alerts.is_empty() || state.maintenance.is_on() {
+            continue;
+        }
+
+        // Items per command in the chat's language, which is what the words are matched against
+        let mut fetched: HashMap<(String, Option<&'static str>), Vec<SourceItem>> = HashMap::new();
+        let mut gone: HashSet<ChatId> = HashSet::new();
+        for (chat_id, alert) in alerts {
+            if gone.contains(&chat_id) {
+                continue;
+            }
+            let lang = state.settings.get(chat_id).lang();
+            let fetch_key = (alert.command.clone(), lang);
+            if !fetched.contains_key(&fetch_key) {
+                let Some(target) = routes::resolve_command(&alert.command) else { continue };
+                let options = FetchOptions { lang, ..FetchOptions::default() };
+                let Some(news) = fetch_target(Arc::clone(&state.engine), target, options).result().await else { continue };
+                let items = news
+                    .results
+                    .into_iter()
+                    .filter_map(|r| Some((r.source.name, r.outcome.ok()?)))
+                    .flat_map(|(name, items)| items.into_iter().map(move |item| (name, item)))
+                    .filter(|(_, item)| item.quote.is_none())
+                    .collect();
+                fetched.insert(fetch_key.clone(), items);
+            }
+
+            let words = query_words(&alert.words);
+            let key = (chat_id, alert);
+            let first_poll = !seen.contains_key(&key);
+            let delivered = seen.entry(key.clone()).or_default();
+            let matches: Vec<&SourceItem> =
+                fetched[&fetch_key].iter().filter(|(_, item)| matches_words(item, &words) && delivered.insert(fingerprint(item))).collect();
+            if first_poll {
+                continue;
+            }
+
+            let (_, alert) = &key;
+            let burst_key = format!("keyword {}", alert);
+            let label = format!("items matched {}", alert);
+            for (name, item) in matches {
+                let line = format!("[{}] {}", name, item.title);
+                if !state.bursts.offer(chat_id, &burst_key, &label, line.clone(), Instant::now()) {
+                    continue;
+                }
+                let link = item.link.as_deref().map(|l| format!("\n{}", l)).unwrap_or_default();
+                match bot.send_message(chat_id, format!("🔔 {}\n{}{}", alert, line, link)).await {
+                    Ok(_) => {}
+                    Err(e) if is_chat_gone(&e) => {
+                        log::info!("Chat {} is gone, dropping its keyword alerts", chat_id);
+                        let _ = state.settings.update(chat_id, |s| {
+                            s.keyword_alerts.clear();
+                            Ok::<_, SettingsError>(())
+                        });
+                        gone.insert(chat_id);
+                        break;
+                    }
+                    Err(e) => log::warn!("Keyword alert to {} failed: {}", chat_id, e),
+                }
+            }
+        }
+    }
+}
+
+/// "/alert war strike" as a keyword alert. None when the arguments are not one - the first word
+/// is not a command, is a price source, or is followed by a direction - so /alert can fall back
+/// to `parse_alert` and its usage message.
+pub fn parse_keyword_alert(args: &str) -> Option<KeywordAlert> {
+    let mut parts = args.split_whitespace();
+    let command = parts.next()?.to_lowercase();
+    routes::resolve_command(&command)?;
+    if find_source(&command).is_some_and(|s| s.extractor.is_some()) {
+        return None;
+    }
+    let words: Vec<&str> = parts.collect();
+    if words.is_empty() || ["above", "below", ">", "<"].contains(&words[0].to_lowercase().as_str()) {
+        return None;
+    }
+    Some(KeywordAlert { command, words: words.join(" ").to_lowercase() })
+}
+
+/// "/alert gold above 2700" as an alert on the canonical source name
+pub fn parse_alert(args: &str) -> Result<PriceAlert, String> {
+    const USAGE: &str = "Usage: /alert <gold|oil|silver|natgas> above|below <price> or /alert <command> <words>";
+    let [name, direction, threshold] = args.split_whitespace().collect::<Vec<_>>()[..] else {
+        return Err(USAGE.to_string());
+    };
+    let source = find_source(name)
+        .filter(|s| s.extractor.is_some())
+        .ok_or_else(|| format!("❌ /{} has no price to watch", name.to_lowercase()))?;
+    let direction = match direction.to_lowercase().as_str() {
+        "above" | ">" => Direction::Above,
+        "below" | "<" => Direction::Below,
+        _ => return Err(USAGE.to_string()),
+    };
+    let threshold = crate::utils::parse_number(threshold, '.').filter(|t| *t > 0.0).ok_or_else(|| USAGE.to_string())?;
+    Ok(PriceAlert { source: source.name.to_string(), direction, threshold })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossing_needs_a_previous_reading() {
+        assert!(!crossed(Direction::Above, 100.0, None, 150.0));
+        assert!(!crossed(Direction::Below, 100.0, None, 50.0));
+    }
+
+    #[test]
+    fn crossing_in_each_direction() {
+        assert!(crossed(Direction::Above, 100.0, Some(99.5), 100.5));
+        assert!(!crossed(Direction::Above, 100.0, Some(100.5), 99.5));
+        assert!(!crossed(Direction::Above, 100.0, Some(101.0), 102.0));
+        assert!(crossed(Direction::Below, 100.0, Some(100.5), 99.5));
+        assert!(!crossed(Direction::Below, 100.0, Some(99.5), 100.5));
+        assert!(!crossed(Direction::Below, 100.0, Some(98.0), 97.0));
+    }
+
+    #[test]
+    fn reaching_the_threshold_exactly_counts_once() {
+        assert!(crossed(Direction::Above, 100.0, Some(99.0), 100.0));
+        assert!(!crossed(Direction::Above, 100.0, Some(100.0), 101.0));
+        assert!(crossed(Direction::Below, 100.0, Some(101.0), 100.0));
+        assert!(!crossed(Direction::Below, 100.0, Some(100.0), 99.0));
+    }
+
+    const WINDOW: Duration = Duration::from_secs(600);
+
+    fn strike(bursts: &Bursts, title: &str, at: Instant) -> bool {
+        bursts.offer(ChatId(1), "keyword 'strike' in /war", "items matched 'strike' in /war", title.to_string(), at)
+    }
+
+    #[test]
+    fn a_burst_sends_the_first_alert_and_one_follow_up() {
+        let bursts = Bursts::new(WINDOW);
+        let start = Instant::now();
+        assert!(strike(&bursts, "First", start));
+        for i in 1..=6 {
+            assert!(!strike(&bursts, &format!("Strike {}", i), start + Duration::from_secs(i * 60)));
+        }
+        assert!(bursts.due(start + WINDOW - Duration::from_secs(1)).is_empty());
+
+        let due = bursts.due(start + WINDOW);
+        assert_eq!(due.len(), 1);
+        let (chat, text) = &due[0];
+        assert_eq!(*chat, ChatId(1));
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "🔔 +6 more items matched 'strike' in /war");
+        // Samples are bounded, the rest only counted
+        assert_eq!(lines[1..=limits::BURST_SAMPLE_LINES], ["Strike 1", "Strike 2", "Strike 3", "Strike 4", "Strike 5"]);
+        assert_eq!(lines.last(), Some(&"…"));
+    }
+
+    #[test]
+    fn a_busy_window_restarts_and_a_quiet_one_closes() {
+        let bursts = Bursts::new(WINDOW);
+        let start = Instant::now();
+        assert!(strike(&bursts, "First", start));
+        assert!(!strike(&bursts, "Second", start + Duration::from_secs(60)));
+        assert_eq!(bursts.due(start + WINDOW).len(), 1);
+
+        // Still inside the restarted window
+        assert!(!strike(&bursts, "Third", start + WINDOW + Duration::from_secs(60)));
+        let due = bursts.due(start + WINDOW * 2);
+        assert_eq!(due[0].1, "🔔 +1 more items matched 'strike' in /war\nThird");
+
+        // Nothing collected: no follow-up, and the next match goes out at once
+        assert!(bursts.due(start + WINDOW * 3).is_empty());
+        assert!(strike(&bursts, "Fourth", start + WINDOW * 3));
+    }
+
+    #[test]
+    fn bursts_are_per_chat_and_key() {
+        let bursts = Bursts::new(WINDOW);
+        let now = Instant::now();
+        assert!(strike(&bursts, "First", now));
+        assert!(bursts.offer(ChatId(2), "keyword 'strike' in /war", "items", "First".into(), now));
+        assert!(bursts.offer(ChatId(1), "Gold", "Gold alerts", "Gold is above 2700".into(), now));
+        assert!(!bursts.offer(ChatId(1), "Gold", "Gold alerts", "Gold is above 2750".into(), now));
+    }
+
+    #[test]
+    fn draining_sends_only_what_was_held() {
+        let bursts = Bursts::new(WINDOW);
+        let now = Instant::now();
+        assert!(strike(&bursts, "First", now));
+        assert!(bursts.offer(ChatId(2), "Gold", "Gold alerts", "Gold is above 2700".into(), now));
+        assert!(!bursts.offer(ChatId(2), "Gold", "Gold alerts", "Gold is above 2750".into(), now));
+
+        assert_eq!(bursts.drain(), [(ChatId(2), "🔔 +1 more Gold alerts\nGold is above 2750".to_string())]);
+        assert!(bursts.drain().is_empty());
+        assert!(strike(&bursts, "Next", now));
+    }
+
+    #[test]
+    fn keyword_alerts_parse_from_arguments() {
+        assert_eq!(parse_keyword_alert("WAR Strike"), Some(KeywordAlert { command: "war".into(), words: "strike".into() }));
+        assert_eq!(parse_keyword_alert("market rate  cut"), Some(KeywordAlert { command: "market".into(), words: "rate cut".into() }));
+        assert_eq!(parse_keyword_alert("tass kremlin").map(|a| a.words), Some("kremlin".into()));
+        // Price alerts, unknown commands and missing words are left to parse_alert
+        for not_keyword in ["", "war", "gold above 2700", "gold strike", "war above 5", "nowhere strike"] {
+            assert_eq!(parse_keyword_alert(not_keyword), None, "{:?}", not_keyword);
+        }
+    }
+
+    #[test]
+    fn alerts_parse_from_arguments() {
+        let alert = parse_alert("GOLD above 2,700").unwrap();
+        assert_eq!(alert, PriceAlert { source: "Gold".into(), direction: Direction::Above, threshold: 2700.0 });
+        let alert = parse_alert("oil < 65.5").unwrap();
+        assert_eq!((alert.source.as_str(), alert.direction, alert.threshold), ("Oil", Direction::Below, 65.5));
+
+        assert!(parse_alert("tass above 10").unwrap_err().contains("/tass has no price"));
+        for usage in ["", "gold above", "gold sideways 10", "gold above 0", "gold above -5", "gold above lots", "gold above 1 2"] {
+            assert!(parse_alert(usage).unwrap_err().starts_with("Usage"), "{:?}", usage);
+        }
+    }
+}
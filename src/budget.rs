@@ -0,0 +1,133 @@
+//! Daily request budget for metered APIs (NewsData free tier)
+
+use crate::storage::{load_json, save_json};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Share of the budget after which usage is logged as a warning
+const WARN_RATIO: f64 = 0.8;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BudgetState {
+    day: NaiveDate,
+    used: u32,
+}
+
+/// Persisted per-UTC-day counter; survives restarts mid-day
+pub struct DailyBudget {
+    file: &'static str,
+    limit: u32,
+    state: Mutex<BudgetState>,
+}
+
+impl DailyBudget {
+    pub fn load(file: &'static str, limit: u32) -> Self {
+        let state = load_json(file).unwrap_or(BudgetState { day: Utc::now().date_naive(), used: 0 });
+        Self { file, limit, state: Mutex::new(state) }
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.remaining_at(Utc::now())
+    }
+
+    pub fn remaining_at(&self, now: DateTime<Utc>) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        roll_over(&mut state, now);
+        self.limit.saturating_sub(state.used)
+    }
+
+    /// Count one request. Past the limit only privileged callers get through;
+    /// everyone else gets the time until the UTC-midnight reset.
+    pub fn try_spend(&self, privileged: bool) -> Result<(), Duration> {
+        self.try_spend_at(Utc::now(), privileged)
+    }
+
+    pub fn try_spend_at(&self, now: DateTime<Utc>, privileged: bool) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        roll_over(&mut state, now);
+        if state.used >= self.limit && !privileged {
+            return Err(until_reset(now));
+        }
+        state.used += 1;
+        if state.used == (self.limit as f64 * WARN_RATIO).ceil() as u32 {
+            log::warn!("{}: {}/{} daily requests used", self.file, state.used, self.limit);
+        }
+        save_json(self.file, &*state);
+        Ok(())
+    }
+
+    pub fn resets_in(&self) -> Duration {
+        until_reset(Utc::now())
+    }
+}
+
+fn roll_over(state: &mut BudgetState, now: DateTime<Utc>) {
+    let today = now.date_naive();
+    if state.day != today {
+        state.day = today;
+        state.used = 0;
+    }
+}
+
+fn until_reset(now: DateTime<Utc>) -> Duration {
+    let midnight = (now.date_naive() + chrono::Days::new(1)).and_hms_opt(0, 0, 0).unwrap().and_utc();
+    (midnight - now).to_std().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2030, 1, day, h, m, s).unwrap()
+    }
+
+    #[test]
+    fn spends_up_to_the_limit_unless_privileged() {
+        let budget = DailyBudget::load("budget_limit.json", 3);
+        let now = at(10, 12, 0, 0);
+        for _ in 0..3 {
+            assert_eq!(budget.try_spend_at(now, false), Ok(()));
+        }
+        assert_eq!(budget.remaining_at(now), 0);
+        assert_eq!(budget.try_spend_at(now, false), Err(Duration::from_secs(12 * 3600)));
+        assert_eq!(budget.try_spend_at(now, true), Ok(()));
+        assert_eq!(budget.remaining_at(now), 0);
+    }
+
+    #[test]
+    fn rolls_over_at_utc_midnight() {
+        let budget = DailyBudget::load("budget_midnight.json", 2);
+        let last_second = at(10, 23, 59, 59);
+        budget.try_spend_at(last_second, false).unwrap();
+        budget.try_spend_at(last_second, false).unwrap();
+        assert_eq!(budget.try_spend_at(last_second, false), Err(Duration::from_secs(1)));
+
+        let midnight = at(11, 0, 0, 0);
+        assert_eq!(budget.remaining_at(midnight), 2);
+        assert_eq!(until_reset(midnight), Duration::from_secs(24 * 3600));
+        assert_eq!(budget.try_spend_at(midnight, false), Ok(()));
+        assert_eq!(budget.remaining_at(midnight), 1);
+    }
+
+    #[test]
+    fn reloads_todays_usage_and_resets_an_earlier_day() {
+        let now = at(10, 9, 30, 0);
+        let budget = DailyBudget::load("budget_restart.json", 5);
+        budget.try_spend_at(now, false).unwrap();
+        budget.try_spend_at(now, false).unwrap();
+        let restarted = DailyBudget::load("budget_restart.json", 5);
+        assert_eq!(restarted.remaining_at(now), 3);
+
+        save_json("budget_stale.json", &BudgetState { day: at(9, 0, 0, 0).date_naive(), used: 5 });
+        let stale = DailyBudget::load("budget_stale.json", 5);
+        assert_eq!(stale.remaining_at(now), 5);
+    }
+}
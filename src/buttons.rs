@@ -0,0 +1,184 @@
+//! Buttons under command results - "🔄 Refresh", "➕ More" and a row of categories - and
+//! their callback data
+
+use crate::consts::{find_source, limits, Category};
+use crate::logic::{routes, Target};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// Telegram rejects callback data longer than this
+const MAX_CALLBACK_BYTES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Fetch the same target again
+    Refresh,
+    /// Fetch it again with more items per source
+    More,
+    /// Fetch another target with the chat's default item count
+    Open,
+}
+
+/// What a button re-runs, encoded as "r:5:war" - action, items per source, then the route
+#[derive(Debug, Clone)]
+pub struct ButtonData {
+    pub action: Action,
+    /// Unused by `Action::Open`, encoded as 0
+    pub items: usize,
+    route: String,
+}
+
+impl ButtonData {
+    fn encode(&self) -> String {
+        let action = match self.action {
+            Action::Refresh => "r",
+            Action::More => "m",
+            Action::Open => "o",
+        };
+        // The route goes last so a search query may contain ':'
+        format!("{}:{}:{}", action, self.items, self.route)
+    }
+
+    pub fn decode(data: &str) -> Option<Self> {
+        let mut parts = data.splitn(3, ':');
+        let action = match parts.next()? {
+            "r" => Action::Refresh,
+            "m" => Action::More,
+            "o" => Action::Open,
+            _ => return None,
+        };
+        let items = parts
+            .next()?
+            .parse()
+            .ok()
+            .filter(|n| action == Action::Open || (1..=limits::MAX_ITEMS_CAP).contains(n))?;
+        Some(Self { action, items, route: parts.next()?.to_string() })
+    }
+
+    /// The target to fetch, `None` when its source no longer exists
+    pub fn target(&self) -> Option<Target> {
+        match self.route.split_at_checked(1) {
+            Some(("~", category)) => routes::resolve_latest(category),
+            Some(("?", query)) => Some(Target::Search(query.to_string())),
+            _ => routes::resolve_command(&self.route),
+        }
+    }
+}
+
+/// Compact code a target is re-resolved from: the command name, "~war" for /latest war
+/// and "?words" for a search
+fn route(target: &Target) -> Option<String> {
+    Some(match target {
+        Target::Category(cat) => cat.command().to_string(),
+        Target::Subcategory { command, .. } => command.to_string(),
+        Target::Source(name) => find_source(name)?.command(),
+        Target::Latest(cat) => format!("~{}", cat.command()),
+        Target::Search(query) => format!("?{}", query),
+    })
+}
+
+/// Buttons for a response fetched at `items` per source; `None` when the callback data
+/// would not fit, e.g. a long search query
+pub fn keyboard(target: &Target, items: usize) -> Option<InlineKeyboardMarkup> {
+    let route = route(target)?;
+    let refresh = ButtonData { action: Action::Refresh, items, route: route.clone() }.encode();
+    let more_items = (items + limits::MAX_ITEMS_PER_SOURCE).min(limits::MAX_ITEMS_CAP);
+    let more = ButtonData { action: Action::More, items: more_items, route }.encode();
+    if more.len() > MAX_CALLBACK_BYTES {
+        return None;
+    }
+
+    let mut row = vec![InlineKeyboardButton::callback("🔄 Refresh", refresh)];
+    if more_items > items {
+        row.push(InlineKeyboardButton::callback("➕ More", more));
+    }
+    Some(InlineKeyboardMarkup::new([row, category_row(target)]))
+}
+
+/// One button per category, leaving out the one on screen
+fn category_row(target: &Target) -> Vec<InlineKeyboardButton> {
+    Category::ALL
+        .into_iter()
+        .filter(|cat| !matches!(target, Target::Category(current) if current == cat))
+        .map(|cat| {
+            let data = ButtonData { action: Action::Open, items: 0, route: cat.command().to_string() };
+            InlineKeyboardButton::callback(cat.to_string(), data.encode())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::SOURCES;
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    fn callback_data(keyboard: &InlineKeyboardMarkup) -> Vec<(String, String)> {
+        keyboard
+            .inline_keyboard
+            .iter()
+            .flatten()
+            .map(|button| match &button.kind {
+                InlineKeyboardButtonKind::CallbackData(data) => (button.text.clone(), data.clone()),
+                other => panic!("unexpected button {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn callback_data_fits_for_every_target() {
+        let mut targets: Vec<Target> = Category::ALL.into_iter().flat_map(|c| [Target::Category(c), Target::Latest(c)]).collect();
+        targets.extend(SOURCES.iter().map(|s| Target::Source(s.name)));
+        targets.push(routes::resolve_command("ukraine").unwrap());
+        for target in targets {
+            let markup = keyboard(&target, limits::MAX_ITEMS_PER_SOURCE).unwrap_or_else(|| panic!("no keyboard for {:?}", target));
+            for (_, data) in callback_data(&markup) {
+                assert!(data.len() <= MAX_CALLBACK_BYTES, "{:?}: {}", target, data);
+                let decoded = ButtonData::decode(&data).unwrap_or_else(|| panic!("{} does not decode", data));
+                let resolved = decoded.target().unwrap_or_else(|| panic!("{} does not resolve", data));
+                assert_eq!(route(&resolved).as_deref(), Some(decoded.route.as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn refresh_and_more_buttons() {
+        let markup = keyboard(&Target::Category(Category::War), 5).unwrap();
+        let buttons = callback_data(&markup);
+        assert_eq!(buttons[0], ("🔄 Refresh".to_string(), "r:5:war".to_string()));
+        assert_eq!(buttons[1], ("➕ More".to_string(), "m:10:war".to_string()));
+
+        // Already at the cap: nothing more to show
+        let at_cap = keyboard(&Target::Category(Category::War), limits::MAX_ITEMS_CAP).unwrap();
+        assert!(!callback_data(&at_cap).iter().any(|(text, _)| text == "➕ More"));
+
+        assert!(ButtonData::decode("x:5:war").is_none());
+        assert!(ButtonData::decode(&format!("m:{}:war", limits::MAX_ITEMS_CAP + 1)).is_none());
+        let search = ButtonData::decode("r:5:?fed: rates").unwrap();
+        assert!(matches!(search.target(), Some(Target::Search(q)) if q == "fed: rates"));
+
+        let long_query = Target::Search("x".repeat(MAX_CALLBACK_BYTES));
+        assert!(keyboard(&long_query, 5).is_none());
+    }
+
+    #[test]
+    fn payloads_round_trip() {
+        for (action, items, route) in [(Action::Refresh, 5, "war"), (Action::More, limits::MAX_ITEMS_CAP, "~market"), (Action::Open, 0, "global"), (Action::Refresh, 3, "?oil: brent")] {
+            let data = ButtonData { action, items, route: route.to_string() }.encode();
+            let decoded = ButtonData::decode(&data).unwrap();
+            assert_eq!((decoded.action, decoded.items, decoded.route.as_str()), (action, items, route));
+        }
+        for bad in ["", "r", "r:5", "r:zero:war", "r:0:war", "o:x:war"] {
+            assert!(ButtonData::decode(bad).is_none(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn category_row_leaves_out_the_current_category() {
+        let row = |target: &Target| callback_data(&keyboard(target, 5).unwrap()).split_off(2);
+        let on_war = row(&Target::Category(Category::War));
+        assert_eq!(on_war.len(), Category::ALL.len() - 1);
+        assert!(on_war.iter().all(|(_, data)| data.starts_with("o:0:") && data != "o:0:war"));
+        // Anything but a category page offers all of them
+        assert_eq!(row(&Target::Latest(Category::War)).len(), Category::ALL.len());
+    }
+}
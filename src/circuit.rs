@@ -0,0 +1,170 @@
+//! Per-source circuit breaker: sources failing repeatedly are skipped for a cooldown
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default, Clone)]
+struct Circuit {
+    /// Consecutive failed fetches
+    failures: u32,
+    /// Set while open; each cooldown lets one probe fetch through and restarts the clock
+    opened_at: Option<Instant>,
+    /// Times the circuit opened since startup
+    trips: u32,
+    /// Fetches refused while open
+    skipped: u64,
+}
+
+/// Snapshot of one source's breaker, for /sources
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitStatus {
+    pub failures: u32,
+    /// Time until the next probe while open
+    pub open_for: Option<Duration>,
+    pub trips: u32,
+    pub skipped: u64,
+}
+
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    circuits: Mutex<HashMap<&'static str, Circuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self { threshold, cooldown, circuits: Mutex::new(HashMap::new()) }
+    }
+
+    /// Whether a fetch may go out; refusals carry the time until the next probe
+    pub fn check(&self, source: &'static str) -> Result<(), Duration> {
+        self.check_at(source, Instant::now())
+    }
+
+    pub fn check_at(&self, source: &'static str, now: Instant) -> Result<(), Duration> {
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(source) else { return Ok(()) };
+        let Some(opened_at) = circuit.opened_at else { return Ok(()) };
+        let elapsed = now.saturating_duration_since(opened_at);
+        if elapsed >= self.cooldown {
+            // Refuse others while the probe is out; a probe that never reports just expires
+            circuit.opened_at = Some(now);
+            log::info!("{}: cooldown over, probing", source);
+            return Ok(());
+        }
+        circuit.skipped += 1;
+        Err(self.cooldown.saturating_sub(elapsed))
+    }
+
+    /// Count a fetch outcome; a success closes the circuit, a failed probe reopens it
+    pub fn record(&self, source: &'static str, success: bool) {
+        self.record_at(source, success, Instant::now())
+    }
+
+    pub fn record_at(&self, source: &'static str, success: bool, now: Instant) {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(source).or_default();
+        if success {
+            if circuit.opened_at.is_some() {
+                log::info!("{}: recovered, circuit closed", source);
+            }
+            circuit.failures = 0;
+            circuit.opened_at = None;
+            return;
+        }
+        circuit.failures += 1;
+        if circuit.opened_at.is_some() {
+            // Failed probe: wait out another cooldown
+            circuit.opened_at = Some(now);
+        } else if circuit.failures >= self.threshold {
+            circuit.trips += 1;
+            circuit.opened_at = Some(now);
+            log::warn!("{}: {} failures in a row, skipping for {}s", source, circuit.failures, self.cooldown.as_secs());
+        }
+    }
+
+    pub fn status(&self, source: &'static str) -> Option<CircuitStatus> {
+        self.status_at(source, Instant::now())
+    }
+
+    pub fn status_at(&self, source: &'static str, now: Instant) -> Option<CircuitStatus> {
+        let circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.get(source)?;
+        let open_for = circuit
+            .opened_at
+            .map(|at| self.cooldown.saturating_sub(now.saturating_duration_since(at)));
+        Some(CircuitStatus { failures: circuit.failures, open_for, trips: circuit.trips, skipped: circuit.skipped })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_secs(600);
+
+    fn tripped(start: Instant) -> CircuitBreaker {
+        let breaker = CircuitBreaker::new(3, COOLDOWN);
+        for _ in 0..3 {
+            breaker.record_at("src", false, start);
+        }
+        breaker
+    }
+
+    #[test]
+    fn trips_after_threshold_failures_in_a_row() {
+        let start = Instant::now();
+        let breaker = CircuitBreaker::new(3, COOLDOWN);
+        breaker.record_at("src", false, start);
+        breaker.record_at("src", false, start);
+        // A success in between resets the count
+        breaker.record_at("src", true, start);
+        breaker.record_at("src", false, start);
+        breaker.record_at("src", false, start);
+        assert!(breaker.check_at("src", start).is_ok());
+        breaker.record_at("src", false, start);
+        assert_eq!(breaker.check_at("src", start), Err(COOLDOWN));
+        assert!(breaker.check_at("other", start).is_ok());
+
+        let status = breaker.status_at("src", start).unwrap();
+        assert_eq!((status.failures, status.trips, status.skipped), (3, 1, 1));
+    }
+
+    #[test]
+    fn refusals_carry_the_remaining_cooldown() {
+        let start = Instant::now();
+        let breaker = tripped(start);
+        assert_eq!(breaker.check_at("src", start + Duration::from_secs(200)), Err(Duration::from_secs(400)));
+        assert_eq!(breaker.status_at("src", start + Duration::from_secs(590)).unwrap().open_for, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn one_probe_after_the_cooldown() {
+        let start = Instant::now();
+        let breaker = tripped(start);
+        let after = start + COOLDOWN;
+        assert!(breaker.check_at("src", after).is_ok());
+        // The probe is out: everyone else waits another cooldown
+        assert_eq!(breaker.check_at("src", after + Duration::from_secs(1)), Err(COOLDOWN - Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn failed_probe_reopens_and_success_closes() {
+        let start = Instant::now();
+        let breaker = tripped(start);
+        let probe = start + COOLDOWN;
+        assert!(breaker.check_at("src", probe).is_ok());
+        breaker.record_at("src", false, probe + Duration::from_secs(5));
+        assert_eq!(breaker.check_at("src", probe + Duration::from_secs(5)), Err(COOLDOWN));
+        // A failed probe is not a new trip
+        assert_eq!(breaker.status_at("src", probe).unwrap().trips, 1);
+
+        let probe = probe + Duration::from_secs(5) + COOLDOWN;
+        assert!(breaker.check_at("src", probe).is_ok());
+        breaker.record_at("src", true, probe);
+        assert!(breaker.check_at("src", probe).is_ok());
+        let status = breaker.status_at("src", probe).unwrap();
+        assert_eq!((status.failures, status.open_for), (0, None));
+    }
+}
@@ -1,13 +1,83 @@
 //! Static source configuration.
 
 use std::fmt;
+use std::sync::{Mutex, RwLock};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum SourceType { Rss, TelegramHtml, NewsData, Html }
+pub enum SourceType {
+    Rss,
+    TelegramHtml,
+    NewsData,
+    Html,
+    /// X account mirrored through a Nitter instance's RSS; the url is the handle
+    Nitter,
+    /// Hot posts of a subreddit; the url is the subreddit name
+    Reddit,
+}
+
+impl SourceType {
+    /// Short lowercase name for operator listings
+    pub fn label(&self) -> &'static str {
+        match self {
+            SourceType::Rss => "rss",
+            SourceType::TelegramHtml => "telegram",
+            SourceType::NewsData => "newsdata",
+            SourceType::Html => "html",
+            SourceType::Nitter => "nitter",
+            SourceType::Reddit => "reddit",
+        }
+    }
+
+    /// Accept header sent when fetching this kind of source
+    pub fn accept(&self) -> &'static str {
+        match self {
+            SourceType::Rss | SourceType::Nitter => headers::ACCEPT_RSS,
+            SourceType::TelegramHtml | SourceType::Html => headers::ACCEPT_HTML,
+            SourceType::NewsData | SourceType::Reddit => headers::ACCEPT_JSON,
+        }
+    }
+
+    /// Reddit rejects browser-like agents from scripts and wants a descriptive one
+    pub fn user_agent(&self) -> &'static str {
+        match self {
+            SourceType::Reddit => headers::BOT_USER_AGENT,
+            _ => headers::random_user_agent(),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Category { Global, War, Market, Commodities }
 
+impl Category {
+    pub const ALL: [Category; 4] = [Category::Global, Category::War, Category::Market, Category::Commodities];
+
+    /// Bot command that fetches this category
+    pub fn command(&self) -> &'static str {
+        match self {
+            Category::Global => "global",
+            Category::War => "war",
+            Category::Market => "market",
+            Category::Commodities => "commodities",
+        }
+    }
+
+    /// Thumbnail shown next to inline results from this category
+    pub fn icon_url(&self) -> &'static str {
+        match self {
+            Category::Global => icons::GLOBAL,
+            Category::War => icons::WAR,
+            Category::Market => icons::MARKET,
+            Category::Commodities => icons::COMMODITIES,
+        }
+    }
+
+    /// Whether items from this category are translated; price quotes are numbers and stay as is
+    pub fn translates(&self) -> bool {
+        !matches!(self, Category::Commodities)
+    }
+}
+
 impl fmt::Display for Category {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -19,6 +89,89 @@ impl fmt::Display for Category {
     }
 }
 
+/// Credentials for a private feed, read from the named env vars at request time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Auth {
+    /// HTTP Basic with user and password env vars
+    Basic { user_env: &'static str, pass_env: &'static str },
+    /// Authorization: Bearer <token>
+    Bearer { token_env: &'static str },
+    /// A fixed query parameter, e.g. ?key=...
+    Query { param: &'static str, value_env: &'static str },
+}
+
+impl Auth {
+    /// Parse "basic:USER_ENV:PASS_ENV", "bearer:TOKEN_ENV" or "query:param:VALUE_ENV", as
+    /// given to /addsource. Names are leaked, so parse only specs that get registered.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let env = |name: &str| {
+            let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+            valid.then(|| &*Box::leak(name.to_string().into_boxed_str()))
+        };
+        let param = |name: &str| {
+            let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            valid.then(|| &*Box::leak(name.to_string().into_boxed_str()))
+        };
+        match spec.split(':').collect::<Vec<_>>()[..] {
+            ["basic", user, pass] => Some(Auth::Basic { user_env: env(user)?, pass_env: env(pass)? }),
+            ["bearer", token] => Some(Auth::Bearer { token_env: env(token)? }),
+            ["query", name, value] => Some(Auth::Query { param: param(name)?, value_env: env(value)? }),
+            _ => None,
+        }
+    }
+
+    /// The spec `parse` reads back; names env vars only, never their values
+    pub fn spec(&self) -> String {
+        match self {
+            Auth::Basic { user_env, pass_env } => format!("basic:{}:{}", user_env, pass_env),
+            Auth::Bearer { token_env } => format!("bearer:{}", token_env),
+            Auth::Query { param, value_env } => format!("query:{}:{}", param, value_env),
+        }
+    }
+}
+
+/// How to read a price quote out of an instrument page (SourceType::Html)
+#[derive(Debug)]
+pub struct PriceExtractor {
+    /// First capture group is the price
+    pub price: &'static str,
+    /// Tried when `price` finds nothing, e.g. after a layout change
+    pub fallback: Option<&'static str>,
+    /// First capture group is the change, "+0.52%" or "(-1.1%)"
+    pub change: Option<&'static str>,
+    /// Prepended to the price, e.g. "$"
+    pub currency: &'static str,
+    /// Decimal separator of the page's numbers: ',' on ru.investing.com ("2.654,30")
+    pub decimal: char,
+}
+
+/// Extractors for the commodity pages in SOURCES
+pub mod extractors {
+    use super::PriceExtractor;
+
+    /// ru.investing.com instrument pages share one layout
+    const INVESTING_PRICE: &str = r#"data-test="instrument-price-last"[^>]*>([\d\.,]+)"#;
+    /// Handles (+0.12%) or +0.12%: the tag, optional whitespace/parens, then the number+percent
+    const INVESTING_CHANGE: &str = r#"data-test="instrument-price-change-percent"[^>]*>\s*\(?\s*([+\-]?[\d\.,]+%?)\s*\)?"#;
+
+    pub static INVESTING: PriceExtractor = PriceExtractor {
+        price: INVESTING_PRICE,
+        fallback: None,
+        change: Some(INVESTING_CHANGE),
+        currency: "$",
+        decimal: ',',
+    };
+
+    /// oilprice.com/futures/wti
+    pub static OILPRICE_WTI: PriceExtractor = PriceExtractor {
+        price: r#"(?i)class="last_price"[^>]*>([\d,]+\.\d+)"#,
+        fallback: Some(r#"(?s)WTI Crude.*?class="value"[^>]*>([\d,]+\.\d+)"#),
+        change: Some(r#"(?i)class="change_percent[^"]*"[^>]*>\s*([+\-]?[\d\.,]+%?)"#),
+        currency: "$",
+        decimal: '.',
+    };
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Source {
     pub name: &'static str,
@@ -26,6 +179,18 @@ pub struct Source {
     pub source_type: SourceType,
     pub category: Category,
     pub language: &'static str,
+    /// HEAD-check item links before formatting and drop dead ones
+    pub verify_links: bool,
+    /// Former names, so references to them keep resolving after a rename
+    pub renamed_from: &'static [&'static str],
+    /// Fewer surviving items than this triggers one deeper fetch, then counts as quiet
+    pub min_items: usize,
+    /// Inline result thumbnail, overriding the category icon
+    pub thumbnail: Option<&'static str>,
+    /// Applied to this source's requests only
+    pub auth: Option<Auth>,
+    /// Price scraping rules, required for SourceType::Html
+    pub extractor: Option<&'static PriceExtractor>,
 }
 
 impl Source {
@@ -36,35 +201,164 @@ impl Source {
         category: Category,
         language: &'static str,
     ) -> Self {
-        Self { name, url, source_type, category, language }
+        Self { name, url, source_type, category, language, verify_links: false, renamed_from: &[], min_items: 1, thumbnail: None, auth: None, extractor: None }
+    }
+
+    pub const fn renamed_from(mut self, old_names: &'static [&'static str]) -> Self {
+        self.renamed_from = old_names;
+        self
+    }
+
+    pub const fn with_link_check(mut self) -> Self {
+        self.verify_links = true;
+        self
+    }
+
+    /// Bot command that fetches just this source, e.g. "yahoopolitics"
+    pub fn command(&self) -> String {
+        self.name.to_lowercase()
+    }
+
+    pub const fn with_min_items(mut self, min_items: usize) -> Self {
+        self.min_items = min_items;
+        self
+    }
+
+    pub const fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub const fn with_extractor(mut self, extractor: &'static PriceExtractor) -> Self {
+        self.extractor = Some(extractor);
+        self
+    }
+
+    pub const fn with_thumbnail(mut self, url: &'static str) -> Self {
+        self.thumbnail = Some(url);
+        self
+    }
+
+    /// Own thumbnail, or the category icon when none is set
+    pub fn thumbnail_url(&self) -> &'static str {
+        self.thumbnail.unwrap_or_else(|| self.category.icon_url())
+    }
+
+    /// Translated category, not already published in `target_lang`
+    pub fn needs_translation(&self, target_lang: &str) -> bool {
+        self.category.translates() && self.language != target_lang
     }
 }
 
 pub static SOURCES: &[Source] = &[
     // Global
-    Source::new("Reuters", "reuters", SourceType::NewsData, Category::Global, "en"),
-    Source::new("YahooPolitics", "https://news.yahoo.com/rss/politics", SourceType::Rss, Category::Global, "en"),
+    Source::new("Reuters", "reuters", SourceType::NewsData, Category::Global, "en").renamed_from(&["RBC"]),
+    Source::new("YahooPolitics", "https://news.yahoo.com/rss/politics", SourceType::Rss, Category::Global, "en")
+        .renamed_from(&["Yahoo"]),
     Source::new("Kommersant", "https://t.me/s/kommersant", SourceType::TelegramHtml, Category::Global, "ru"),
     Source::new("AlJazeera", "https://www.aljazeera.com/xml/rss/all.xml", SourceType::Rss, Category::Global, "en"),
 
     // War
     Source::new("DeepState", "https://t.me/s/DeepStateUA", SourceType::TelegramHtml, Category::War, "ru"),
     Source::new("TASS", "https://t.me/s/tass_agency", SourceType::TelegramHtml, Category::War, "ru"),
-    Source::new("Liveuamap", "https://t.me/s/liveuamap", SourceType::TelegramHtml, Category::War, "en"),
+    Source::new("Liveuamap", "https://t.me/s/liveuamap", SourceType::TelegramHtml, Category::War, "en")
+        .renamed_from(&["UkraineNow"])
+        .with_link_check(),
+    Source::new("Suspilne", "https://suspilne.media/rss/all.rss", SourceType::Rss, Category::War, "uk"),
+    Source::new("SuspilneTG", "https://t.me/s/suspilnenews", SourceType::TelegramHtml, Category::War, "uk"),
+    Source::new("UkrPravda", "https://t.me/s/ukrpravda_news", SourceType::TelegramHtml, Category::War, "uk"),
 
     // Market
     Source::new("Bloomberg", "https://t.me/s/bbbreaking", SourceType::TelegramHtml, Category::Market, "en"),
-    Source::new("MarketTwits", "https://t.me/s/markettwits", SourceType::TelegramHtml, Category::Market, "ru"),
+    Source::new("MarketTwits", "https://t.me/s/markettwits", SourceType::TelegramHtml, Category::Market, "ru")
+        .with_min_items(2),
     Source::new("Tree", "https://t.me/s/TreeNewsFeed", SourceType::TelegramHtml, Category::Market, "en"),
+    Source::new("MarketTwitsX", "markettwits", SourceType::Nitter, Category::Market, "ru"),
+    Source::new("TreeX", "TreeNewsFeed", SourceType::Nitter, Category::Market, "en"),
+    Source::new("WallStreetBets", "wallstreetbets", SourceType::Reddit, Category::Market, "en"),
 
     // Commodities - Direct HTML Scraping
-    Source::new("Gold", "https://ru.investing.com/commodities/gold", SourceType::Html, Category::Commodities, "ru"),
-    Source::new("Oil", "https://oilprice.com/futures/wti", SourceType::Html, Category::Commodities, "en"),
+    Source::new("Gold", "https://ru.investing.com/commodities/gold", SourceType::Html, Category::Commodities, "ru")
+        .with_extractor(&extractors::INVESTING)
+        .with_thumbnail(icons::GOLD),
+    Source::new("Oil", "https://oilprice.com/futures/wti", SourceType::Html, Category::Commodities, "en")
+        .with_extractor(&extractors::OILPRICE_WTI)
+        .with_thumbnail(icons::OIL),
+    Source::new("Silver", "https://ru.investing.com/commodities/silver", SourceType::Html, Category::Commodities, "ru")
+        .with_extractor(&extractors::INVESTING),
+    Source::new("NatGas", "https://ru.investing.com/commodities/natural-gas", SourceType::Html, Category::Commodities, "ru")
+        .with_extractor(&extractors::INVESTING),
 ];
 
+/// Per-category fallbacks, fetched only when every primary source in the category fails
+pub static CATEGORY_FALLBACKS: &[Source] = &[
+    Source::new("GoogleNews World", "https://news.google.com/rss/search?q=world+news&hl=en-US&gl=US&ceid=US:en", SourceType::Rss, Category::Global, "en"),
+    Source::new("GoogleNews War", "https://news.google.com/rss/search?q=ukraine+war&hl=en-US&gl=US&ceid=US:en", SourceType::Rss, Category::War, "en"),
+    Source::new("GoogleNews Markets", "https://news.google.com/rss/search?q=stock+market&hl=en-US&gl=US&ceid=US:en", SourceType::Rss, Category::Market, "en"),
+    Source::new("GoogleNews Commodities", "https://news.google.com/rss/search?q=gold+oil+prices&hl=en-US&gl=US&ceid=US:en", SourceType::Rss, Category::Commodities, "en"),
+];
+
+#[inline]
+pub fn fallback_for(category: Category) -> Option<&'static Source> {
+    CATEGORY_FALLBACKS.iter().find(|s| s.category == category)
+}
+
+/// Feeds added at runtime with /addsource; leaked so they share the registry's 'static lifetime
+static CUSTOM_SOURCES: RwLock<Vec<&'static Source>> = RwLock::new(Vec::new());
+
+pub fn custom_sources() -> Vec<&'static Source> {
+    CUSTOM_SOURCES.read().unwrap().clone()
+}
+
+/// Register a validated RSS feed for the rest of the process lifetime.
+/// Callers check the name is free; the count is capped by MAX_CUSTOM_SOURCES.
+pub fn register_custom_source(name: &str, url: &str, auth: Option<Auth>) -> Option<&'static Source> {
+    let mut custom = CUSTOM_SOURCES.write().unwrap();
+    if custom.len() >= limits::MAX_CUSTOM_SOURCES {
+        return None;
+    }
+    let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+    let url: &'static str = Box::leak(url.to_string().into_boxed_str());
+    let mut source = Source::new(name, url, SourceType::Rss, Category::Global, "auto");
+    if let Some(auth) = auth {
+        source = source.with_auth(auth);
+    }
+    let source: &'static Source = Box::leak(Box::new(source));
+    custom.push(source);
+    Some(source)
+}
+
+/// Look a source up by current name, falling back to names it was renamed from
+/// and then to runtime-added feeds
 #[inline]
 pub fn find_source(name: &str) -> Option<&'static Source> {
-    SOURCES.iter().find(|s| s.name.eq_ignore_ascii_case(name))
+    SOURCES
+        .iter()
+        .find(|s| s.name.eq_ignore_ascii_case(name))
+        .or_else(|| find_renamed(name))
+        .or_else(|| custom_sources().into_iter().find(|s| s.name.eq_ignore_ascii_case(name)))
+}
+
+/// Old names already logged by `find_renamed`
+static RENAMES_LOGGED: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// Source that used to be called `old_name`, for migrating stored references. Each alias is
+/// logged the first time it is used, not on every lookup.
+pub fn find_renamed(old_name: &str) -> Option<&'static Source> {
+    let (source, alias) = SOURCES
+        .iter()
+        .find_map(|s| Some((s, *s.renamed_from.iter().find(|old| old.eq_ignore_ascii_case(old_name))?)))?;
+    let mut logged = RENAMES_LOGGED.lock().unwrap();
+    if !logged.contains(&alias) {
+        logged.push(alias);
+        log::info!("Source {} was renamed to {}", alias, source.name);
+    }
+    Some(source)
+}
+
+#[inline]
+pub fn sources_by_language(category: Category, language: &'static str) -> impl Iterator<Item = &'static Source> {
+    sources_by_category(category).filter(move |s| s.language == language)
 }
 
 #[inline]
@@ -72,19 +366,430 @@ pub fn sources_by_category(category: Category) -> impl Iterator<Item = &'static
     SOURCES.iter().filter(move |s| s.category == category)
 }
 
+/// Request headers. Accept-Encoding is left to reqwest, which offers exactly the codecs its
+/// gzip, brotli and deflate features decode.
 pub mod headers {
-    pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+    /// Client default, for requests that don't pick from the pool (translation, link checks)
+    pub const USER_AGENT: &str = USER_AGENTS[0];
+
+    /// Current desktop browsers; each source fetch picks one at random
+    pub const USER_AGENTS: &[&str] = &[
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+        "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:121.0) Gecko/20100101 Firefox/121.0",
+        "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.2 Safari/605.1.15",
+        "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0",
+    ];
+
+    pub const ACCEPT_HTML: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+    pub const ACCEPT_RSS: &str = "application/rss+xml,application/atom+xml,application/xml;q=0.9,text/xml;q=0.8,*/*;q=0.5";
+    pub const ACCEPT_JSON: &str = "application/json";
+    pub const ACCEPT_LANG: &str = "en-US,en;q=0.9,ru;q=0.8,uk;q=0.7";
+
+    /// Honest agent for APIs that ask clients to identify themselves
+    pub const BOT_USER_AGENT: &str = concat!("logos_bot/", env!("CARGO_PKG_VERSION"), " (Telegram news aggregator)");
+
+    pub fn random_user_agent() -> &'static str {
+        USER_AGENTS[rand::random::<usize>() % USER_AGENTS.len()]
+    }
+}
+
+/// Static hosted icons for inline results (Twemoji PNGs)
+pub mod icons {
+    pub const GLOBAL: &str = "https://cdn.jsdelivr.net/gh/twitter/twemoji@14.0.2/assets/72x72/1f5a4.png";
+    pub const WAR: &str = "https://cdn.jsdelivr.net/gh/twitter/twemoji@14.0.2/assets/72x72/1f90d.png";
+    pub const MARKET: &str = "https://cdn.jsdelivr.net/gh/twitter/twemoji@14.0.2/assets/72x72/1f3f4.png";
+    pub const COMMODITIES: &str = "https://cdn.jsdelivr.net/gh/twitter/twemoji@14.0.2/assets/72x72/1fa99.png";
+    pub const GOLD: &str = "https://cdn.jsdelivr.net/gh/twitter/twemoji@14.0.2/assets/72x72/1f4b0.png";
+    pub const OIL: &str = "https://cdn.jsdelivr.net/gh/twitter/twemoji@14.0.2/assets/72x72/1f6e2.png";
+}
+
+/// Junk filter heuristics used by `utils::is_junk`
+pub mod junk {
+    use std::sync::OnceLock;
+
+    /// Titles shorter than this (in chars) carry no news
+    pub const MIN_TITLE_CHARS: usize = 8;
+
+    /// Title length floor, overridable with MIN_TITLE_CHARS
+    pub fn min_title_chars() -> usize {
+        static VALUE: OnceLock<usize> = OnceLock::new();
+        *VALUE.get_or_init(|| {
+            std::env::var("MIN_TITLE_CHARS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(MIN_TITLE_CHARS)
+        })
+    }
+
+    /// Telegram system messages
+    pub const SYSTEM: &[&str] = &["channel created", "account created"];
+
+    /// Entertainment noise: shows, series, sport. Words that also turn up in hard news
+    /// ("a series of strikes", "star witness", "match funding") are deliberately absent.
+    pub const ENTERTAINMENT: &[&str] = &[
+        "football", "soccer", "sport", "premier league", "netflix", "celebrity",
+        "футбол", "спорт", "сериал", "шоу",
+    ];
+
+    /// Ads and promo posts
+    pub const PROMO: &[&str] = &[
+        "реклама", "#реклама", "18+", "розыгрыш", "промокод", "подписывайтесь",
+        "subscribe", "giveaway", "promo code", "sponsored", "advertisement",
+    ];
+
+    /// Built-in patterns plus comma-separated extras from JUNK_PATTERNS, lowercased. Each one
+    /// matches whole words, in order, on a title's first line.
+    pub fn patterns() -> &'static [String] {
+        static VALUE: OnceLock<Vec<String>> = OnceLock::new();
+        VALUE.get_or_init(|| {
+            let extra = std::env::var("JUNK_PATTERNS").unwrap_or_default();
+            SYSTEM
+                .iter()
+                .chain(ENTERTAINMENT)
+                .chain(PROMO)
+                .map(|p| p.to_string())
+                .chain(extra.split(',').map(|p| p.trim().to_lowercase()).filter(|p| !p.is_empty()))
+                .collect()
+        })
+    }
 }
 
 pub mod selectors {
     pub const TG_MESSAGE_WRAP: &str = ".tgme_widget_message_wrap";
     pub const TG_MESSAGE_TEXT: &str = ".tgme_widget_message_text";
     pub const TG_MESSAGE_DATE: &str = ".tgme_widget_message_date";
+    pub const TG_MESSAGE_VIEWS: &str = ".tgme_widget_message_views";
+    /// Carries data-post="channel/123", used to page further back
+    pub const TG_MESSAGE: &str = ".tgme_widget_message";
+    /// <time datetime="2024-05-01T12:34:56+00:00"> inside the date link
+    pub const TG_MESSAGE_TIME: &str = "time[datetime]";
 }
 
 pub mod limits {
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    /// Default items per source; commands may ask for up to MAX_ITEMS_CAP
     pub const MAX_ITEMS_PER_SOURCE: usize = 5;
-    pub const MAX_TEXT_LENGTH: usize = 280;
+    pub const MAX_ITEMS_CAP: usize = 20;
     pub const REQUEST_TIMEOUT_SECS: u64 = 15;
+    /// Whole-source budget inside an aggregation, retries included; kept under the HTTP timeout
+    pub const SOURCE_BUDGET_SECS: u64 = 10;
     pub const BASE_DELAY_MS: u64 = 500;
-}
\ No newline at end of file
+    /// Items younger than this show as "25m ago" / "2h ago" instead of a clock time
+    pub const RELATIVE_AGE_HOURS: i64 = 6;
+    /// Items shown by a merged timeline (/latest, /search) across all its sources
+    pub const TIMELINE_MAX_ITEMS: usize = 15;
+    /// Runtime-added feeds kept per process
+    pub const MAX_CUSTOM_SOURCES: usize = 20;
+    /// /subscribe polling interval bounds and default, in minutes
+    pub const SUBSCRIPTION_MIN_MINUTES: u32 = 15;
+    pub const SUBSCRIPTION_MAX_MINUTES: u32 = 1440;
+    pub const SUBSCRIPTION_DEFAULT_MINUTES: u32 = 60;
+    /// Interval subscriptions and daily digests together
+    pub const MAX_SUBSCRIPTIONS_PER_CHAT: usize = 5;
+    /// /profile entries per chat
+    pub const MAX_PROFILES_PER_CHAT: usize = 5;
+    /// A digest sent more than this after its slot is labeled "(delayed)"
+    pub const DIGEST_GRACE_MINUTES: i64 = 5;
+    /// A digest missed by more than this (bot down at the time) waits for the next day
+    pub const DIGEST_CATCHUP_MINUTES: i64 = 120;
+    /// Digests held back during maintenance, sent once it is lifted; the rest fall back to catch-up
+    pub const DIGEST_QUEUE_CAP: usize = 200;
+    /// How often the subscription task looks for due subscriptions
+    pub const SUBSCRIPTION_TICK_SECS: u64 = 60;
+    /// How often price alerts are checked; quotes come from the response cache when fresh
+    pub const ALERT_POLL_SECS: u64 = 300;
+    pub const MAX_ALERTS_PER_CHAT: usize = 10;
+    /// Default window after an alert in which further matches wait for one follow-up, see `burst_window`
+    pub const BURST_WINDOW_MINUTES: u64 = 10;
+    /// Matches listed in a follow-up; the rest are only counted
+    pub const BURST_SAMPLE_LINES: usize = 5;
+    /// How often ended alert windows are checked for follow-ups
+    pub const BURST_TICK_SECS: u64 = 30;
+    /// Item fingerprints remembered per chat for 🆕 marks
+    pub const SEEN_PER_CHAT: usize = 500;
+    /// A seen item counts as new again after this long
+    pub const SEEN_TTL_HOURS: i64 = 7 * 24;
+    /// How often changed seen items are written to disk
+    pub const SEEN_FLUSH_SECS: u64 = 30;
+    /// Default response cache lifetime, see `cache_ttl`
+    pub const CACHE_TTL_SECS: u64 = 60;
+    /// Feed entries scanned past filtered ones, as a multiple of the item cap
+    pub const DEEPEN_SCAN_FACTOR: usize = 4;
+    /// A deeper pass is only started this early into a fetch
+    pub const DEEPEN_CUTOFF_MS: u64 = 6000;
+    /// Default attempts per source fetch including the first, see `fetch_attempts`
+    pub const FETCH_ATTEMPTS: u32 = 2;
+    /// Default backoff unit between retries, see `retry_base_delay_ms`
+    pub const RETRY_BASE_DELAY_MS: u64 = 800;
+    /// Default consecutive failures that open a source's circuit, see `circuit_threshold`
+    pub const CIRCUIT_THRESHOLD: u32 = 5;
+    /// Default time an open circuit skips its source, see `circuit_cooldown`
+    pub const CIRCUIT_COOLDOWN_SECS: u64 = 600;
+    /// Default number of sources fetched at once for a single command, see `fetch_concurrency`
+    pub const MAX_CONCURRENT_FETCHES: usize = 4;
+    /// After startup all fetches share a stricter global pace for this long
+    pub const WARMUP_SECS: u64 = 300;
+    pub const WARMUP_INTERVAL_MS: u64 = 1500;
+    /// Default Nitter instance, see `nitter_base`
+    pub const NITTER_BASE: &str = "https://nitter.net";
+    /// Minimum gap between request starts to one host, unless HOST_INTERVALS says otherwise
+    pub const HOST_INTERVAL_MS: u64 = 500;
+    /// Per-host overrides of HOST_INTERVAL_MS; t.me throttles scrapers hardest
+    pub const HOST_INTERVALS: &[(&str, u64)] = &[("t.me", 2000), ("news.google.com", 250), ("www.reddit.com", 2000)];
+    /// Posts requested from a subreddit listing; stickied and removed ones are dropped after
+    pub const REDDIT_SCAN: usize = 25;
+    /// Tries at a subreddit listing before a 429 fails the source
+    pub const REDDIT_ATTEMPTS: u32 = 3;
+    /// NewsData free tier requests per UTC day; each result page is one request
+    pub const NEWSDATA_DAILY_BUDGET: u32 = 200;
+    /// NewsData result pages followed per fetch, the first included, each spending from the budget
+    pub const NEWSDATA_MAX_PAGES: usize = 3;
+    /// Per-link HEAD timeout and overall budget for the dead-link pass
+    pub const LINK_CHECK_TIMEOUT_MS: u64 = 1200;
+    pub const LINK_CHECK_BUDGET_MS: u64 = 1500;
+    pub const LINK_CHECK_CACHE_SECS: u64 = 3600;
+    /// Title and description caps inside a rendered item
+    pub const TITLE_MAX_CHARS: usize = 150;
+    pub const DESC_MAX_CHARS: usize = 200;
+    /// Default cap on one rendered item's text, see `item_max_chars`
+    pub const ITEM_MAX_CHARS: usize = 800;
+    /// Title token overlap (Jaccard) at which items from different sources count as one story
+    pub const DEDUP_SIMILARITY: f64 = 0.6;
+    /// Default language fetched items are translated into, see `translate_target`
+    pub const TRANSLATE_TARGET_LANG: &str = "ru";
+    /// Translation requests in flight per source
+    pub const TRANSLATE_CONCURRENCY: usize = 4;
+    /// Tries per translation request, for network and server errors only
+    pub const TRANSLATE_ATTEMPTS: u32 = 2;
+    /// Translated texts remembered so repeated headlines skip the round trip
+    pub const TRANSLATE_CACHE_CAPACITY: usize = 2000;
+    /// Articles returned for one inline query
+    pub const INLINE_MAX_RESULTS: usize = 10;
+    /// How long Telegram may cache an inline answer
+    pub const INLINE_CACHE_SECS: u32 = 60;
+    /// Longest an inline query waits for its aggregation
+    pub const INLINE_ANSWER_SECS: u64 = 10;
+    /// /readyz passes without fetching while any source succeeded this recently
+    pub const READY_FRESH_SECS: i64 = 300;
+    /// Budget for the probe fetch /readyz falls back to
+    pub const READY_PROBE_SECS: u64 = 5;
+    /// Clients get this long to send their request line to the health endpoint
+    pub const HEALTH_READ_SECS: u64 = 5;
+    /// Hard Telegram limit for a single message
+    pub const TELEGRAM_MESSAGE_LEN: usize = 4096;
+    /// Target chunk size when splitting, leaving room for markup fixes
+    pub const MESSAGE_CHUNK_LEN: usize = 4000;
+
+    /// Item text cap, overridable with ITEM_MAX_CHARS
+    pub fn item_max_chars() -> usize {
+        static VALUE: OnceLock<usize> = OnceLock::new();
+        *VALUE.get_or_init(|| {
+            std::env::var("ITEM_MAX_CHARS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(ITEM_MAX_CHARS)
+        })
+    }
+
+    /// How long fetched items are served from cache, overridable with CACHE_TTL_SECS
+    pub fn cache_ttl() -> std::time::Duration {
+        static VALUE: OnceLock<u64> = OnceLock::new();
+        let secs = *VALUE.get_or_init(|| {
+            std::env::var("CACHE_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(CACHE_TTL_SECS)
+        });
+        std::time::Duration::from_secs(secs)
+    }
+
+    /// Timezone for displayed times, from DISPLAY_UTC_OFFSET in hours ("3", "-5", "5.5"); UTC by default
+    pub fn display_offset() -> chrono::FixedOffset {
+        static VALUE: OnceLock<chrono::FixedOffset> = OnceLock::new();
+        *VALUE.get_or_init(|| {
+            std::env::var("DISPLAY_UTC_OFFSET")
+                .ok()
+                .and_then(|v| v.trim().trim_start_matches('+').parse::<f64>().ok())
+                .and_then(|hours| chrono::FixedOffset::east_opt((hours * 3600.0) as i32))
+                .unwrap_or_else(|| chrono::FixedOffset::east_opt(0).unwrap())
+        })
+    }
+
+    /// Concurrent fetches per command, overridable with FETCH_CONCURRENCY
+    pub fn fetch_concurrency() -> usize {
+        static VALUE: OnceLock<usize> = OnceLock::new();
+        *VALUE.get_or_init(|| {
+            std::env::var("FETCH_CONCURRENCY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(MAX_CONCURRENT_FETCHES)
+        })
+    }
+
+    /// Gap enforced between requests to `host`
+    pub fn host_interval(host: &str) -> Duration {
+        let ms = HOST_INTERVALS
+            .iter()
+            .find(|(h, _)| *h == host)
+            .map_or(HOST_INTERVAL_MS, |(_, ms)| *ms);
+        Duration::from_millis(ms)
+    }
+
+    /// Nitter instance serving X sources, overridable with NITTER_URL as instances come and go
+    pub fn nitter_base() -> &'static str {
+        static VALUE: OnceLock<String> = OnceLock::new();
+        VALUE.get_or_init(|| {
+            std::env::var("NITTER_URL")
+                .ok()
+                .map(|v| v.trim().trim_end_matches('/').to_string())
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| NITTER_BASE.to_string())
+        })
+    }
+
+    /// Tries per source fetch, overridable with FETCH_ATTEMPTS; 1 disables retries
+    pub fn fetch_attempts() -> u32 {
+        static VALUE: OnceLock<u32> = OnceLock::new();
+        *VALUE.get_or_init(|| {
+            std::env::var("FETCH_ATTEMPTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(FETCH_ATTEMPTS)
+        })
+    }
+
+    /// Backoff unit between retries, overridable with RETRY_BASE_DELAY_MS
+    pub fn retry_base_delay_ms() -> u64 {
+        static VALUE: OnceLock<u64> = OnceLock::new();
+        *VALUE.get_or_init(|| {
+            std::env::var("RETRY_BASE_DELAY_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(RETRY_BASE_DELAY_MS)
+        })
+    }
+
+    /// Failures in a row before a source is skipped, overridable with CIRCUIT_THRESHOLD
+    pub fn circuit_threshold() -> u32 {
+        static VALUE: OnceLock<u32> = OnceLock::new();
+        *VALUE.get_or_init(|| {
+            std::env::var("CIRCUIT_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(CIRCUIT_THRESHOLD)
+        })
+    }
+
+    /// How long a tripped source is skipped, overridable with CIRCUIT_COOLDOWN_SECS
+    pub fn circuit_cooldown() -> Duration {
+        static VALUE: OnceLock<u64> = OnceLock::new();
+        Duration::from_secs(*VALUE.get_or_init(|| {
+            std::env::var("CIRCUIT_COOLDOWN_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(CIRCUIT_COOLDOWN_SECS)
+        }))
+    }
+
+    /// Catch-up window for missed digests, overridable with DIGEST_CATCHUP_MINUTES
+    pub fn digest_catchup() -> chrono::Duration {
+        static VALUE: OnceLock<i64> = OnceLock::new();
+        chrono::Duration::minutes(*VALUE.get_or_init(|| {
+            std::env::var("DIGEST_CATCHUP_MINUTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v| *v >= 0)
+                .unwrap_or(DIGEST_CATCHUP_MINUTES)
+        }))
+    }
+
+    /// Alert burst window, overridable with BURST_WINDOW_MINUTES
+    pub fn burst_window() -> Duration {
+        static VALUE: OnceLock<u64> = OnceLock::new();
+        Duration::from_secs(60 * *VALUE.get_or_init(|| {
+            std::env::var("BURST_WINDOW_MINUTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(BURST_WINDOW_MINUTES)
+        }))
+    }
+
+    /// Translation target language, overridable with TRANSLATE_TARGET ("en", "uk", ...)
+    pub fn translate_target() -> &'static str {
+        static VALUE: OnceLock<String> = OnceLock::new();
+        VALUE.get_or_init(|| {
+            std::env::var("TRANSLATE_TARGET")
+                .ok()
+                .map(|v| v.trim().to_lowercase())
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| TRANSLATE_TARGET_LANG.to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renamed_sources_resolve_and_are_logged_once() {
+        for _ in 0..3 {
+            assert_eq!(find_source("rbc").map(|s| s.name), Some("Reuters"));
+            assert_eq!(find_renamed("YAHOO").map(|s| s.name), Some("YahooPolitics"));
+        }
+        assert!(find_renamed("Reuters").is_none());
+        let logged = RENAMES_LOGGED.lock().unwrap();
+        assert_eq!(logged.iter().filter(|a| **a == "RBC").count(), 1);
+    }
+
+    const ALL_TYPES: [SourceType; 6] = [
+        SourceType::Rss,
+        SourceType::TelegramHtml,
+        SourceType::NewsData,
+        SourceType::Html,
+        SourceType::Nitter,
+        SourceType::Reddit,
+    ];
+
+    #[test]
+    fn every_source_type_builds_a_source() {
+        for kind in ALL_TYPES {
+            let source = Source::new("Probe", "probe", kind, Category::Global, "en");
+            // No wildcard arm: a new variant fails to compile until it's added to ALL_TYPES
+            match source.source_type {
+                SourceType::Rss | SourceType::TelegramHtml | SourceType::NewsData | SourceType::Html
+                | SourceType::Nitter | SourceType::Reddit => {}
+            }
+            assert!(!source.source_type.label().is_empty());
+        }
+    }
+
+    #[test]
+    fn registry_lookups_cover_every_type() {
+        for kind in ALL_TYPES {
+            let registered = SOURCES.iter().find(|s| s.source_type == kind).unwrap_or_else(|| panic!("no {:?} source", kind));
+            assert_eq!(find_source(&registered.command()).map(|s| s.name), Some(registered.name));
+            assert!(sources_by_category(registered.category).any(|s| s.name == registered.name));
+        }
+        assert_eq!(find_source("gold").map(|s| s.source_type), Some(SourceType::Html));
+    }
+
+    #[test]
+    fn auth_specs_round_trip() {
+        for spec in ["basic:FEED_USER:FEED_PASS", "bearer:FEED_TOKEN", "query:api-key:FEED_KEY"] {
+            assert_eq!(Auth::parse(spec).map(|a| a.spec()).as_deref(), Some(spec));
+        }
+        assert_eq!(Auth::parse("bearer:FEED_TOKEN"), Some(Auth::Bearer { token_env: "FEED_TOKEN" }));
+        for bad in ["", "bearer", "bearer:", "bearer:feed_token", "basic:USER", "query:key", "query:a b:KEY", "digest:USER:PASS"] {
+            assert_eq!(Auth::parse(bad), None, "{:?}", bad);
+        }
+    }
+}
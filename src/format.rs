@@ -0,0 +1,536 @@
+//! Formatter layer - renders structured fetch results into Telegram HTML
+
+use crate::consts::{custom_sources, limits, Category, Source, SOURCES};
+use crate::logic::{AggregatedNews, Layout, SourceResult};
+use crate::network::{FetchError, NewsEngine, NewsItem, Quote, SourceStatus};
+use crate::utils::{compact_count, compact_duration, display_time, fingerprint, truncate_text};
+use chrono::{FixedOffset, Utc};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Per-chat presentation choices, from /settings
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub descriptions: bool,
+    /// Fingerprints the chat has already seen; `None` renders without 🆕 marks
+    pub seen: Option<Arc<HashSet<u64>>>,
+    /// Collapse seen items into a count instead of listing them
+    pub hide_seen: bool,
+    /// Timezone for item times
+    pub offset: FixedOffset,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self { descriptions: true, seen: None, hide_seen: false, offset: limits::display_offset() }
+    }
+}
+
+impl RenderOptions {
+    pub fn with_seen(mut self, seen: Option<HashSet<u64>>) -> Self {
+        self.seen = seen.map(Arc::new);
+        self
+    }
+
+    fn is_seen(&self, item: &NewsItem) -> bool {
+        self.seen.as_ref().is_some_and(|seen| seen.contains(&fingerprint(item)))
+    }
+
+    fn is_new(&self, item: &NewsItem) -> bool {
+        self.seen.is_some() && !self.is_seen(item)
+    }
+
+    fn is_hidden(&self, item: &NewsItem) -> bool {
+        self.hide_seen && self.is_seen(item)
+    }
+}
+
+/// A response as blocks that must not be split across messages: the header with the
+/// price snapshot, each item (a source's title travels with its first item), and each
+/// quiet or error line. The summary line is left to the caller.
+pub fn render_blocks(news: &AggregatedNews, view: &RenderOptions) -> Vec<String> {
+    let mut head = format!("<b>{}</b>\n\n", escape_html(&news.header));
+    if news.results.is_empty() {
+        head.push_str("🕸 No sources found");
+    }
+
+    let quoted: Vec<&SourceResult> = news.results.iter().filter(|r| is_quote_result(r)).collect();
+    if !quoted.is_empty() {
+        head.push_str(&format_snapshot(&quoted));
+        head.push('\n');
+    }
+    let mut blocks = vec![head];
+
+    let rest: Vec<&SourceResult> = news.results.iter().filter(|r| !is_quote_result(r)).collect();
+    match news.layout {
+        Layout::Grouped => {
+            for result in &rest {
+                blocks.extend(source_blocks(result, view));
+            }
+        }
+        Layout::Timeline => {
+            blocks.extend(timeline_blocks(&rest, view));
+            for result in rest.iter().filter(|r| r.outcome.is_err()) {
+                blocks.extend(source_blocks(result, view));
+            }
+        }
+    }
+
+    if let Some(fallback) = &news.fallback {
+        let mut fallback_blocks = source_blocks(fallback, view);
+        fallback_blocks[0].insert_str(0, "\n⚠️ <i>primary sources unavailable — fallback:</i>\n");
+        blocks.extend(fallback_blocks);
+    }
+
+    blocks
+}
+
+/// A source's title and items, a quiet one-liner, or an error line
+fn source_blocks(result: &SourceResult, view: &RenderOptions) -> Vec<String> {
+    if let Some(reason) = result.empty_reason {
+        return vec![format!("<i>— {}: {}</i>\n", escape_html(result.source.name), reason.describe())];
+    }
+    match &result.outcome {
+        Ok(items) => {
+            let mut blocks = result_blocks(result.source.name, items, view);
+            push_last(&mut blocks, "\n");
+            blocks
+        }
+        Err(e) => vec![format_error(result.source.name, e)],
+    }
+}
+
+/// Append to the last block, or start one when there are none
+fn push_last(blocks: &mut Vec<String>, text: &str) {
+    match blocks.last_mut() {
+        Some(last) => last.push_str(text),
+        None => blocks.push(text.to_string()),
+    }
+}
+
+/// Price sources that produced quotes are rendered in the snapshot, not as blocks
+fn is_quote_result(result: &SourceResult) -> bool {
+    result.outcome.as_ref().is_ok_and(|items| items.iter().any(|i| i.quote.is_some()))
+}
+
+/// Pinned one-line-per-instrument price header
+fn format_snapshot(results: &[&SourceResult]) -> String {
+    let mut output = String::from("<b>✟ Snapshot</b>\n");
+    for result in results {
+        let Ok(items) = &result.outcome else { continue };
+        for item in items {
+            if let Some(quote) = &item.quote {
+                output.push_str(&format_quote_line(result.source.name, item.link.as_deref(), quote));
+            }
+        }
+    }
+    output
+}
+
+fn format_quote_line(name: &str, link: Option<&str>, quote: &Quote) -> String {
+    let name = match link {
+        Some(link) => format!("<a href=\"{}\">{}</a>", escape_html(link), escape_html(name)),
+        None => escape_html(name),
+    };
+    // Direction from the parsed number when there is one, "(-1.1%)" included
+    let falling = |c: &str| quote.change_percent.map_or(c.starts_with('-'), |p| p < 0.0);
+    let change = match quote.change.as_deref() {
+        Some(c) if falling(c) => format!(" ▼ {}", escape_html(c)),
+        Some(c) => format!(" ▲ {}", escape_html(c)),
+        None => String::new(),
+    };
+    format!("💰 <b>{}</b> {}{}\n", name, escape_html(&quote.price), change)
+}
+
+/// Admin-only per-source timing line: "Reuters 180ms (cache) · TASS 1.4s (retry×1)"
+pub fn format_fetch_report(news: &AggregatedNews) -> String {
+    let entries: Vec<String> = news
+        .results
+        .iter()
+        .map(|r| {
+            let ms = r.meta.elapsed.as_millis();
+            let mut entry = if ms < 1000 {
+                format!("{} {}ms", escape_html(r.source.name), ms)
+            } else {
+                format!("{} {:.1}s", escape_html(r.source.name), ms as f64 / 1000.0)
+            };
+            if r.meta.cache_hit {
+                entry.push_str(" (cache)");
+            }
+            if r.meta.attempts > 1 {
+                entry.push_str(&format!(" (retry×{})", r.meta.attempts - 1));
+            }
+            if r.outcome.is_err() {
+                entry.push_str(" ✗");
+            }
+            entry
+        })
+        .collect();
+    format!("\n<code>⏱ {}</code>", entries.join(" · "))
+}
+
+/// Source health for /sources: one aligned table per category with each source's last fetch
+pub fn format_sources_report(engine: &NewsEngine) -> String {
+    let all: Vec<&'static Source> = SOURCES.iter().chain(custom_sources()).collect();
+    let width = all.iter().map(|s| s.name.chars().count()).max().unwrap_or(0);
+    let now = Utc::now();
+    let ago = |at| display_time(at, now, limits::display_offset(), limits::RELATIVE_AGE_HOURS);
+
+    let mut report = String::from("<b>📡 Sources</b>\n");
+    for category in Category::ALL {
+        let rows: Vec<String> = all
+            .iter()
+            .filter(|s| s.category == category)
+            .map(|s| {
+                let circuit = engine.circuits.status(s.name);
+                let status = match engine.status(s) {
+                    _ if engine.is_paused(s) => "⏸ disabled".to_string(),
+                    _ if let Some(open_for) = circuit.and_then(|c| c.open_for) => {
+                        format!("🔌 skipped · probe in {}m", open_for.as_secs().div_ceil(60))
+                    }
+                    Some(SourceStatus::Ok { items, at, .. }) => format!("✅ {} · {}", items, ago(at)),
+                    Some(SourceStatus::Failed { error, at, .. }) => format!("❌ {} · {}", truncate_text(&error, 40), ago(at)),
+                    None => "— not fetched yet".to_string(),
+                };
+                let status = match circuit {
+                    Some(c) if c.trips > 0 => format!("{} · tripped {}× ({} skipped)", status, c.trips, c.skipped),
+                    Some(c) if c.failures > 1 => format!("{} · {} failures in a row", status, c.failures),
+                    _ => status,
+                };
+                format!("{:<width$} {:<8} {}", s.name, s.source_type.label(), status, width = width)
+            })
+            .collect();
+        if !rows.is_empty() {
+            report.push_str(&format!("\n<b>{}</b>\n<pre>{}</pre>", category, escape_html(&rows.join("\n"))));
+        }
+    }
+    report
+}
+
+/// Per-source health for /status from recorded telemetry; fetches nothing
+pub fn format_status_report(engine: &NewsEngine, cache_ages: &HashMap<&'static str, Duration>) -> String {
+    let all: Vec<&'static Source> = SOURCES.iter().chain(custom_sources()).collect();
+    let width = all.iter().map(|s| s.name.chars().count()).max().unwrap_or(0);
+    let now = Utc::now();
+    let ago = |at| display_time(at, now, limits::display_offset(), limits::RELATIVE_AGE_HOURS);
+
+    let mut report = format!(
+        "<b>🩺 Status</b> v{} · up {}\n",
+        env!("CARGO_PKG_VERSION"),
+        compact_duration(engine.uptime())
+    );
+    for category in Category::ALL {
+        let rows: Vec<String> = all
+            .iter()
+            .filter(|s| s.category == category)
+            .map(|s| {
+                let mut row = match engine.status(s) {
+                    Some(SourceStatus::Ok { items, at, elapsed }) => {
+                        format!("✅ {} in {:.1}s, {}", items, elapsed.as_secs_f32(), ago(at))
+                    }
+                    Some(SourceStatus::Failed { error, at, elapsed }) => {
+                        format!("❌ {} in {:.1}s, {}", truncate_text(&error, 28), elapsed.as_secs_f32(), ago(at))
+                    }
+                    None => "— not fetched".to_string(),
+                };
+                if let Some(age) = cache_ages.get(s.name) {
+                    row.push_str(&format!(" · 📦 {}", compact_duration(*age)));
+                }
+                match engine.circuits.status(s.name) {
+                    Some(c) if c.open_for.is_some() => row.push_str(&format!(" · 🔌 open, {} skipped", c.skipped)),
+                    Some(c) if c.failures > 0 => row.push_str(&format!(" · {} fails", c.failures)),
+                    _ => {}
+                }
+                if engine.is_paused(s) {
+                    row.push_str(" · ⏸");
+                }
+                format!("{:<width$} {}", s.name, row, width = width)
+            })
+            .collect();
+        if !rows.is_empty() {
+            report.push_str(&format!("\n<b>{}</b>\n<pre>{}</pre>", category, escape_html(&rows.join("\n"))));
+        }
+    }
+    report
+}
+
+/// Fingerprints of the news items `render_blocks` lists, quotes and timeline overflow excluded
+pub fn shown_fingerprints(news: &AggregatedNews) -> Vec<u64> {
+    fn items(r: &SourceResult) -> &[NewsItem] {
+        r.outcome.as_deref().unwrap_or_default()
+    }
+    let mut shown: Vec<&NewsItem> = news.results.iter().flat_map(items).filter(|i| i.quote.is_none()).collect();
+    if news.layout == Layout::Timeline {
+        shown.sort_by_key(|item| std::cmp::Reverse(item.timestamp));
+        shown.truncate(limits::TIMELINE_MAX_ITEMS);
+    }
+    shown.extend(news.fallback.iter().flat_map(items));
+    shown.into_iter().map(fingerprint).collect()
+}
+
+/// One block per item, the source title joined to the first
+fn result_blocks(source_name: &str, items: &[NewsItem], view: &RenderOptions) -> Vec<String> {
+    let mut blocks = vec![format!("<b>🏴 {}</b>\n", escape_html(source_name))];
+    for item in items.iter().filter(|i| !view.is_hidden(i)) {
+        let mut block = String::new();
+        if item.quote.is_some() {
+            block.push_str(&format!("\n💰 <b>{}</b>", escape_html(&item.title)));
+            if let Some(link) = &item.link {
+                block.push_str(&format!("\n   └ <a href=\"{}\">Chart</a>", escape_html(link)));
+            }
+        } else {
+            block.push_str(&format_item(source_name, item, "", view));
+        }
+        block.push('\n');
+        blocks.push(block);
+    }
+    push_last(&mut blocks, &hidden_note(items.iter(), view));
+    // The title travels with the first item so it is never left alone at a message's end
+    if blocks.len() > 1 {
+        let title = blocks.remove(0);
+        blocks[0].insert_str(0, &title);
+    }
+    blocks
+}
+
+/// "…and 4 items you've already seen", empty when nothing was collapsed
+fn hidden_note<'a>(items: impl Iterator<Item = &'a NewsItem>, view: &RenderOptions) -> String {
+    match items.filter(|i| view.is_hidden(i)).count() {
+        0 => String::new(),
+        1 => "<i>…and 1 item you've already seen</i>\n".to_string(),
+        n => format!("<i>…and {} items you've already seen</i>\n", n),
+    }
+}
+
+/// One news item: title, description, time, views, link and byline; `prefix` goes before the title
+fn format_item(source_name: &str, item: &NewsItem, prefix: &str, view: &RenderOptions) -> String {
+    let mut output = String::new();
+    // Cap the item as a whole so one long post can't dominate the response
+    let item_budget = limits::item_max_chars();
+    let title_cap = limits::TITLE_MAX_CHARS.min(item_budget);
+    let title_clean = truncate_text(&item.title, title_cap);
+    let mut truncated = item.title.chars().count() > title_cap;
+    let marker = if view.is_new(item) { "🆕 " } else { "" };
+    output.push_str(&format!("\n▪️ {}{}<b>{}</b>", marker, prefix, escape_html(&title_clean)));
+
+    if let Some(d) = item.description.as_ref().filter(|_| view.descriptions) {
+        let desc_budget = limits::DESC_MAX_CHARS.min(item_budget.saturating_sub(title_clean.chars().count()));
+        let desc_clean = truncate_text(d, desc_budget);
+        truncated |= d.chars().count() > desc_budget;
+        if desc_budget > 0 && !desc_clean.is_empty() && desc_clean != title_clean {
+            output.push_str(&format!("\n   <i>{}</i>", escape_html(&desc_clean)));
+        }
+    }
+    if let (true, Some(link)) = (truncated, &item.link) {
+        output.push_str(&format!(" <a href=\"{}\">… ➡️ full text</a>", escape_html(link)));
+    }
+    output.push_str(&format!("\n   └ <code>{}</code>", escape_html(&item_time(item, view.offset))));
+    if let Some(views) = item.views {
+        output.push_str(&format!(" 👁 {}", compact_count(views)));
+    }
+    if let Some(link) = &item.link {
+        output.push_str(&format!(" <a href=\"{}\">[Link]</a>", escape_html(link)));
+    }
+    if let Some(byline) = byline(source_name, item) {
+        output.push_str(&format!(" — {}", escape_html(&byline)));
+    }
+    if !item.also_on.is_empty() {
+        output.push_str(&format!("\n   <i>also on: {}</i>", escape_html(&item.also_on.join(", "))));
+    }
+    output
+}
+
+/// Items of every source merged newest first, each tagged with its source, one block each
+fn timeline_blocks(results: &[&SourceResult], view: &RenderOptions) -> Vec<String> {
+    let mut items: Vec<(&str, &NewsItem)> = results
+        .iter()
+        .filter_map(|r| Some((r.source.name, r.outcome.as_ref().ok()?)))
+        .flat_map(|(name, items)| items.iter().map(move |item| (name, item)))
+        .collect();
+    items.sort_by_key(|(_, item)| std::cmp::Reverse(item.timestamp));
+
+    if items.is_empty() {
+        return vec!["<i>Nothing to show</i>\n".to_string()];
+    }
+    let shown = &items[..items.len().min(limits::TIMELINE_MAX_ITEMS)];
+    let mut blocks: Vec<String> = shown
+        .iter()
+        .filter(|(_, i)| !view.is_hidden(i))
+        .map(|(name, item)| format_item(name, item, &format!("[{}] ", escape_html(name)), view) + "\n")
+        .collect();
+    let note = hidden_note(shown.iter().map(|(_, i)| *i), view);
+    if !note.is_empty() {
+        push_last(&mut blocks, &note);
+    }
+    blocks
+}
+
+/// A subscription push: `title`, then the new items newest first, each tagged with its source
+pub fn render_updates(title: &str, items: &[(&str, &NewsItem)], view: &RenderOptions) -> String {
+    let mut output = format!("<b>{}</b>\n", escape_html(title));
+    for (name, item) in items.iter().take(limits::TIMELINE_MAX_ITEMS) {
+        output.push_str(&format_item(name, item, &format!("[{}] ", escape_html(name)), view));
+        output.push('\n');
+    }
+    output
+}
+
+/// Parsed publication time in the `offset` timezone, or the source's own time text
+pub fn item_time(item: &NewsItem, offset: FixedOffset) -> String {
+    match item.timestamp {
+        Some(ts) => display_time(ts, Utc::now(), offset, limits::RELATIVE_AGE_HOURS),
+        None => item.time_str.clone(),
+    }
+}
+
+/// Short "Feed / Author" byline, omitting parts that just repeat the source name
+fn byline(source_name: &str, item: &NewsItem) -> Option<String> {
+    let parts: Vec<&str> = [item.feed_title.as_deref(), item.author.as_deref()]
+        .into_iter()
+        .flatten()
+        .filter(|p| !p.eq_ignore_ascii_case(source_name))
+        .collect();
+    if parts.is_empty() {
+        return None;
+    }
+    Some(truncate_text(&parts.join(" / "), 40))
+}
+
+pub fn format_error(source_name: &str, error: &FetchError) -> String {
+    match error {
+        FetchError::Tls { host } => format!(
+            "<b>🕸 {}:</b> 🔒 TLS failure contacting {}\n",
+            escape_html(source_name),
+            escape_html(host)
+        ),
+        FetchError::CircuitOpen { .. } => {
+            format!("<b>🕸 {}:</b> temporarily disabled after repeated failures\n", escape_html(source_name))
+        }
+        _ => format!("<b>🕸 {}:</b> {}\n", escape_html(source_name), escape_html(&error.to_string())),
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::FetchMeta;
+
+    fn item(title: &str, description: Option<&str>, link: Option<&str>) -> NewsItem {
+        NewsItem {
+            title: title.to_string(),
+            description: description.map(str::to_string),
+            link: link.map(str::to_string),
+            time_str: "09:15".to_string(),
+            author: None,
+            feed_title: None,
+            views: None,
+            quote: None,
+            timestamp: None,
+            also_on: Vec::new(),
+        }
+    }
+
+    fn grouped(header: &str, items: Vec<NewsItem>) -> AggregatedNews {
+        let results = vec![SourceResult::new(&SOURCES[0], Ok(items), FetchMeta::default())];
+        AggregatedNews { header: header.to_string(), layout: Layout::Grouped, results, fallback: None, paused: Vec::new() }
+    }
+
+    /// What Telegram displays for a message in HTML parse mode: tags dropped, entities decoded
+    fn displayed(html: &str) -> String {
+        let text = regex::Regex::new(r#"</?(b|i|code|pre|a)( href="[^"<>]*")?>"#).unwrap().replace_all(html, "");
+        assert!(!text.contains('<') && !text.contains('>'), "stray markup in {}", text);
+        text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&amp;", "&")
+    }
+
+    #[test]
+    fn titles_with_markup_characters_survive_html_mode() {
+        let title = "Q&A: <b>rates</b> stay_put *again* [update] > \"expected\"";
+        let news = grouped("Oil & <Gas>", vec![item(title, Some("S&P_500 *up* [1]"), Some("https://example.com/a?x=1&y=<2>"))]);
+        let html = render_blocks(&news, &RenderOptions::default()).concat();
+        assert!(html.contains("<b>Q&amp;A: &lt;b&gt;rates&lt;/b&gt; stay_put *again* [update] &gt; &quot;expected&quot;</b>"), "{}", html);
+        assert!(html.contains("<a href=\"https://example.com/a?x=1&amp;y=&lt;2&gt;\">[Link]</a>"), "{}", html);
+        let text = displayed(&html);
+        assert!(text.starts_with("Oil & <Gas>\n"), "{}", text);
+        assert!(text.contains(title), "{}", text);
+        assert!(text.contains("S&P_500 *up* [1]"), "{}", text);
+    }
+
+    #[test]
+    fn long_items_are_capped_with_a_full_text_link() {
+        let view = RenderOptions::default();
+        let full_text = " <a href=\"https://t.me/c/1\">… ➡️ full text</a>";
+        let title = "T".repeat(limits::TITLE_MAX_CHARS);
+        let desc = "d".repeat(limits::DESC_MAX_CHARS);
+
+        // Exactly at the caps: shown whole, no full text link
+        let at_cap = format_item("Mock", &item(&title, Some(&desc), Some("https://t.me/c/1")), "", &view);
+        assert!(at_cap.contains(&format!("<b>{}</b>\n   <i>{}</i>\n", title, desc)), "{}", at_cap);
+        assert!(!at_cap.contains("full text"));
+
+        // A 6000-character essay is cut to the caps and points to the original
+        let essay = "Слово ".repeat(1000);
+        let over = format_item("Mock", &item(&essay, Some(&essay), Some("https://t.me/c/1")), "", &view);
+        let shown_title = truncate_text(&essay, limits::TITLE_MAX_CHARS);
+        let shown_desc = truncate_text(&essay, limits::DESC_MAX_CHARS);
+        assert!(over.contains(&format!("<b>{}</b>\n   <i>{}</i>{}\n", shown_title, shown_desc, full_text)), "{}", over);
+        assert!(over.chars().count() < limits::item_max_chars());
+
+        // Without a link there is nowhere to point, so the text is only cut
+        let unlinked = format_item("Mock", &item(&essay, Some(&essay), None), "", &view);
+        assert!(unlinked.contains(&format!("<i>{}</i>\n", shown_desc)), "{}", unlinked);
+        assert!(!unlinked.contains("full text"));
+        // A long title alone is enough to link the full text
+        let long_title = format_item("Mock", &item(&essay, None, Some("https://t.me/c/1")), "", &view);
+        assert!(long_title.contains(&format!("<b>{}</b>{}", shown_title, full_text)), "{}", long_title);
+    }
+
+    #[test]
+    fn sources_report_groups_rows_by_category() {
+        let engine = NewsEngine::new().unwrap();
+        let [ok, failed, idle] = [&SOURCES[0], &SOURCES[1], &SOURCES[2]];
+        engine.record_status(ok, &Ok(Vec::new()), Duration::from_millis(420));
+        engine.record_status(failed, &Err(FetchError::Status(502)), Duration::from_millis(1500));
+
+        let report = format_sources_report(&engine);
+        let row = |source: &Source| report.lines().find(|l| l.trim_start_matches("<pre>").starts_with(&format!("{} ", source.name))).unwrap_or_else(|| panic!("no row for {}", source.name));
+        assert!(row(ok).contains("✅ 0 · just now"), "{}", row(ok));
+        assert!(row(failed).contains("❌ HTTP 502"), "{}", row(failed));
+        assert!(row(idle).contains("— not fetched yet"), "{}", row(idle));
+        for category in Category::ALL.into_iter().filter(|c| SOURCES.iter().any(|s| s.category == *c)) {
+            assert!(report.contains(&format!("\n<b>{}</b>\n<pre>", category)), "{}", category);
+        }
+    }
+
+    #[test]
+    fn status_report_rows_per_source() {
+        let engine = NewsEngine::new().unwrap();
+        let [ok, failed, tripped, idle] = [&SOURCES[0], &SOURCES[1], &SOURCES[2], &SOURCES[3]];
+        engine.record_status(ok, &Ok(Vec::new()), Duration::from_millis(420));
+        engine.record_status(failed, &Err(FetchError::Status(502)), Duration::from_millis(1500));
+        for _ in 0..limits::circuit_threshold() {
+            engine.circuits.record(tripped.name, false);
+        }
+        engine.circuits.check(tripped.name).unwrap_err();
+        let cache_ages = HashMap::from([(ok.name, Duration::from_secs(180))]);
+
+        let report = format_status_report(&engine, &cache_ages);
+        assert!(report.starts_with(&format!("<b>🩺 Status</b> v{} · up ", env!("CARGO_PKG_VERSION"))), "{}", report);
+        assert!(report.len() <= limits::TELEGRAM_MESSAGE_LEN);
+        let row = |source: &Source| report.lines().find(|l| l.trim_start_matches("<pre>").starts_with(&format!("{} ", source.name))).unwrap_or_else(|| panic!("no row for {}", source.name));
+        assert!(row(ok).contains("✅ 0 in 0.4s, just now · 📦 3m"), "{}", row(ok));
+        assert!(row(failed).contains("❌ HTTP 502"), "{}", row(failed));
+        assert!(row(tripped).contains("🔌 open, 1 skipped"), "{}", row(tripped));
+        assert!(row(idle).contains("— not fetched"), "{}", row(idle));
+        for category in Category::ALL.into_iter().filter(|c| SOURCES.iter().any(|s| s.category == *c)) {
+            assert!(report.contains(&format!("\n<b>{}</b>\n<pre>", category)), "{}", category);
+        }
+    }
+}
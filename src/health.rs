@@ -0,0 +1,141 @@
+//! Liveness and readiness probes and metrics over plain HTTP, for container orchestration
+
+use crate::consts::{limits, SourceType, SOURCES};
+use crate::network::{FetchError, NewsEngine, SourceStatus};
+use crate::state::Maintenance;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+/// Serve /healthz, /readyz and /metrics on HEALTH_PORT until the process exits
+pub async fn run(port: u16, engine: Arc<NewsEngine>, maintenance: Arc<Maintenance>) {
+    let listener = match TcpListener::bind(("0.0.0.0", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Health endpoint failed to bind port {}: {}", port, e);
+            return;
+        }
+    };
+    log::info!("Health endpoint listening on :{}", port);
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(serve(stream, Arc::clone(&engine), Arc::clone(&maintenance)));
+            }
+            Err(e) => log::warn!("Health endpoint accept failed: {}", e),
+        }
+    }
+}
+
+/// Answer one request and close; probes only ever send a bare GET
+async fn serve(mut stream: TcpStream, engine: Arc<NewsEngine>, maintenance: Arc<Maintenance>) {
+    let mut buf = [0u8; 1024];
+    let Ok(Ok(n)) = timeout(Duration::from_secs(limits::HEALTH_READ_SECS), stream.read(&mut buf)).await else {
+        return;
+    };
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let (status, body) = respond(path, &engine, maintenance.is_on()).await;
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        log::debug!("Health response not delivered: {}", e);
+    }
+}
+
+/// Status line and body for `path`. Maintenance keeps the bot live but not ready, so
+/// orchestrators route no traffic to it until it is lifted.
+async fn respond(path: &str, engine: &NewsEngine, maintenance: bool) -> (&'static str, String) {
+    match path {
+        "/healthz" => ("200 OK", "ok\n".to_string()),
+        "/readyz" if maintenance => ("503 Service Unavailable", "not ready: maintenance mode\n".to_string()),
+        "/readyz" => match ready(engine).await {
+            Ok(source) => ("200 OK", format!("ok: {}\n", source)),
+            Err(reason) => ("503 Service Unavailable", format!("not ready: {}\n", reason)),
+        },
+        "/metrics" => ("200 OK", engine.metrics.render()),
+        _ => ("404 Not Found", "not found\n".to_string()),
+    }
+}
+
+/// Ready when some source fetched fine recently, otherwise after one quick probe fetch
+async fn ready(engine: &NewsEngine) -> Result<&'static str, String> {
+    let fresh = chrono::Duration::seconds(limits::READY_FRESH_SECS);
+    let now = Utc::now();
+    let recent = SOURCES
+        .iter()
+        .find(|s| matches!(engine.status(s), Some(SourceStatus::Ok { items, at, .. }) if items > 0 && now - at < fresh));
+    if let Some(source) = recent {
+        return Ok(source.name);
+    }
+    let source = SOURCES
+        .iter()
+        .find(|s| {
+            s.source_type == SourceType::Rss
+                && !engine.is_paused(s)
+                && engine.circuits.status(s.name).is_none_or(|c| c.open_for.is_none())
+        })
+        .ok_or("no source to probe")?;
+    let budget = Duration::from_secs(limits::READY_PROBE_SECS);
+    let started = Instant::now();
+    let (outcome, _) = timeout(budget, engine.fetch_with_retry(source, 1, 1))
+        .await
+        .unwrap_or((Err(FetchError::Timeout(budget)), 1));
+    engine.record_status(source, &outcome, started.elapsed());
+    outcome.map(|_| source.name).map_err(|e| format!("{}: {}", source.name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::NewsItem;
+
+    fn item() -> NewsItem {
+        NewsItem {
+            title: "Central bank holds rates steady amid inflation worries".to_string(),
+            description: None,
+            link: None,
+            time_str: String::new(),
+            author: None,
+            feed_title: None,
+            views: None,
+            quote: None,
+            timestamp: None,
+            also_on: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn probes_answer_by_path() {
+        let engine = NewsEngine::new().unwrap();
+        // A recent good fetch makes the bot ready without probing the network
+        let source = &SOURCES[0];
+        engine.record_status(source, &Ok(vec![item()]), Duration::from_millis(300));
+
+        assert_eq!(respond("/healthz", &engine, false).await, ("200 OK", "ok\n".to_string()));
+        assert_eq!(respond("/readyz", &engine, false).await, ("200 OK", format!("ok: {}\n", source.name)));
+        let (status, body) = respond("/metrics", &engine, false).await;
+        assert_eq!(status, "200 OK");
+        assert!(body.contains("# TYPE logos_fetch_requests_total counter"), "{}", body);
+        assert_eq!(respond("/", &engine, false).await.0, "404 Not Found");
+        assert_eq!(respond("/readyz/extra", &engine, false).await.0, "404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn maintenance_is_live_but_not_ready() {
+        let engine = NewsEngine::new().unwrap();
+        engine.record_status(&SOURCES[0], &Ok(vec![item()]), Duration::from_millis(300));
+        assert_eq!(respond("/healthz", &engine, true).await.0, "200 OK");
+        assert_eq!(
+            respond("/readyz", &engine, true).await,
+            ("503 Service Unavailable", "not ready: maintenance mode\n".to_string())
+        );
+    }
+}
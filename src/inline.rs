@@ -0,0 +1,143 @@
+//! Inline mode - turns a fetched target into article results for "@bot war"
+
+use crate::consts::limits;
+use crate::format::{escape_html, item_time, RenderOptions};
+use crate::logic::AggregatedNews;
+use crate::utils::{fingerprint, truncate_text};
+use std::collections::HashSet;
+use teloxide::types::{
+    InlineQueryResult, InlineQueryResultArticle, InlineQueryResultsButton, InlineQueryResultsButtonKind,
+    InputMessageContent, InputMessageContentText, ParseMode,
+};
+
+/// Deduplicated, capped article results plus whether items were left out
+pub fn build_results(news: &AggregatedNews, view: &RenderOptions) -> (Vec<InlineQueryResult>, bool) {
+    let mut seen = HashSet::new();
+    let mut results = Vec::new();
+    let mut has_more = false;
+
+    for result in news.results.iter().chain(news.fallback.as_ref()) {
+        let Ok(items) = &result.outcome else { continue };
+        for item in items {
+            let id = fingerprint(item);
+            if !seen.insert(id) {
+                continue;
+            }
+            if results.len() == limits::INLINE_MAX_RESULTS {
+                has_more = true;
+                break;
+            }
+
+            let mut text = format!("<b>{}</b>\n<i>{}</i>", escape_html(&item.title), escape_html(result.source.name));
+            if let Some(link) = &item.link {
+                text.push_str(&format!(" <a href=\"{}\">[Link]</a>", escape_html(link)));
+            }
+            let content = InputMessageContent::Text(InputMessageContentText::new(text).parse_mode(ParseMode::Html));
+
+            // Ids derive from the item fingerprint so Telegram can cache across queries
+            let mut article = InlineQueryResultArticle::new(format!("{:016x}", id), truncate_text(&item.title, 100), content)
+                .description(format!("{} · {}", item_time(item, view.offset), result.source.name));
+            if let Ok(url) = result.source.thumbnail_url().parse() {
+                article = article.thumbnail_url(url);
+            }
+            results.push(InlineQueryResult::Article(article));
+        }
+    }
+    (results, has_more)
+}
+
+/// Button above the results that opens the bot with the full command output
+pub fn full_output_button(command: &str) -> InlineQueryResultsButton {
+    InlineQueryResultsButton {
+        text: "📰 Full feed in chat".to_string(),
+        kind: InlineQueryResultsButtonKind::StartParameter(command.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::{Source, SOURCES};
+    use crate::logic::{FetchMeta, Layout, SourceResult};
+    use crate::network::NewsItem;
+    use crate::state::Settings;
+
+    fn item(title: &str, link: Option<&str>) -> NewsItem {
+        NewsItem {
+            title: title.to_string(),
+            description: None,
+            link: link.map(str::to_string),
+            time_str: "09:15".to_string(),
+            author: None,
+            feed_title: None,
+            views: None,
+            quote: None,
+            timestamp: None,
+            also_on: Vec::new(),
+        }
+    }
+
+    fn news(results: Vec<(&'static Source, Vec<NewsItem>)>) -> AggregatedNews {
+        let results = results.into_iter().map(|(s, items)| SourceResult::new(s, Ok(items), FetchMeta::default())).collect();
+        AggregatedNews { header: String::new(), layout: Layout::Grouped, results, fallback: None, paused: Vec::new() }
+    }
+
+    fn articles(results: &[InlineQueryResult]) -> Vec<&InlineQueryResultArticle> {
+        results.iter().map(|r| match r { InlineQueryResult::Article(a) => a, other => panic!("not an article: {:?}", other) }).collect()
+    }
+
+    #[test]
+    fn results_are_deduplicated_with_stable_ids_and_thumbnails() {
+        let own = SOURCES.iter().find(|s| s.thumbnail.is_some()).unwrap();
+        let plain = SOURCES.iter().find(|s| s.thumbnail.is_none()).unwrap();
+        let rates = item("Central bank holds rates", Some("https://example.com/rates"));
+        let news = news(vec![
+            (own, vec![rates.clone(), item("Gold & silver rally", None)]),
+            (plain, vec![rates.clone(), item("Ports reopen", None)]),
+        ]);
+        let (results, has_more) = build_results(&news, &RenderOptions::default());
+        assert!(!has_more);
+        let shown = articles(&results);
+        assert_eq!(shown.iter().map(|a| a.title.as_str()).collect::<Vec<_>>(), ["Central bank holds rates", "Gold & silver rally", "Ports reopen"]);
+
+        // Ids are the item fingerprints, the same on every query
+        assert_eq!(shown[0].id, format!("{:016x}", fingerprint(&rates)));
+        let (again, _) = build_results(&news, &RenderOptions::default());
+        assert_eq!(articles(&again).iter().map(|a| &a.id).collect::<Vec<_>>(), shown.iter().map(|a| &a.id).collect::<Vec<_>>());
+
+        assert_eq!(shown[0].thumbnail_url.as_ref().unwrap().as_str(), own.thumbnail.unwrap());
+        assert_eq!(shown[2].thumbnail_url.as_ref().unwrap().as_str(), plain.category.icon_url());
+        assert_eq!(shown[2].description.as_deref(), Some(format!("09:15 · {}", plain.name).as_str()));
+        let InputMessageContent::Text(text) = &shown[1].input_message_content else { panic!("not a text message") };
+        assert_eq!(text.message_text, format!("<b>Gold &amp; silver rally</b>\n<i>{}</i>", escape_html(own.name)));
+    }
+
+    #[test]
+    fn results_are_capped_and_flag_the_rest() {
+        let items = (0..limits::INLINE_MAX_RESULTS + 3).map(|i| item(&format!("Headline number {}", i), None)).collect();
+        let (results, has_more) = build_results(&news(vec![(&SOURCES[0], items)]), &RenderOptions::default());
+        assert_eq!(results.len(), limits::INLINE_MAX_RESULTS);
+        assert!(has_more);
+
+        let items = (0..limits::INLINE_MAX_RESULTS).map(|i| item(&format!("Headline number {}", i), None)).collect();
+        let (results, has_more) = build_results(&news(vec![(&SOURCES[0], items)]), &RenderOptions::default());
+        assert_eq!(results.len(), limits::INLINE_MAX_RESULTS);
+        assert!(!has_more);
+    }
+
+    #[test]
+    fn full_output_button_starts_the_bot_with_the_command() {
+        let button = full_output_button("war 5");
+        assert_eq!(button.text, "📰 Full feed in chat");
+        assert!(matches!(button.kind, InlineQueryResultsButtonKind::StartParameter(ref p) if p == "war 5"));
+    }
+
+    #[test]
+    fn only_settings_that_change_results_make_answers_personal() {
+        assert!(!Settings::default().is_personalized());
+        assert!(!Settings { hide_seen: true, hide_descriptions: true, ..Settings::default() }.is_personalized());
+        assert!(Settings { lang: Some("en".into()), ..Settings::default() }.is_personalized());
+        assert!(Settings { items: Some(3), ..Settings::default() }.is_personalized());
+        assert!(Settings { utc_offset_minutes: Some(180), ..Settings::default() }.is_personalized());
+    }
+}
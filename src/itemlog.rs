@@ -0,0 +1,232 @@
+//! Optional JSON-lines log of newly seen items (ITEMS_LOG_PATH)
+
+use crate::consts::Source;
+use crate::logic::AggregatedNews;
+use crate::network::NewsItem;
+use crate::utils::fingerprint;
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Rotate the log once it grows past this size
+const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+/// Records buffered for the writer before new ones are dropped
+const QUEUE_CAPACITY: usize = 1024;
+/// Fingerprints remembered for duplicate suppression
+const SEEN_CAPACITY: usize = 10_000;
+
+#[derive(Debug, Serialize)]
+struct ItemRecord {
+    ts: String,
+    source: &'static str,
+    category: String,
+    title: String,
+    link: Option<String>,
+    fingerprint: String,
+    lang: &'static str,
+    /// Parsed quote numbers, for price sources
+    #[serde(skip_serializing_if = "Option::is_none")]
+    price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change_percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<&'static str>,
+}
+
+#[derive(Default)]
+struct SeenSet {
+    set: HashSet<u64>,
+    order: VecDeque<u64>,
+}
+
+impl SeenSet {
+    /// True the first time a fingerprint is offered
+    fn insert(&mut self, fp: u64) -> bool {
+        if !self.set.insert(fp) {
+            return false;
+        }
+        self.order.push_back(fp);
+        if self.order.len() > SEEN_CAPACITY {
+            if let Some(old) = self.order.pop_front() {
+                self.set.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+pub struct ItemLog {
+    tx: mpsc::Sender<ItemRecord>,
+    seen: Mutex<SeenSet>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl ItemLog {
+    /// Start the writer thread when ITEMS_LOG_PATH is set
+    pub fn from_env() -> Option<Arc<Self>> {
+        let path = PathBuf::from(std::env::var("ITEMS_LOG_PATH").ok().filter(|p| !p.is_empty())?);
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer_dropped = Arc::clone(&dropped);
+        tokio::task::spawn_blocking(move || run_writer(path, rx, writer_dropped));
+        Some(Arc::new(Self { tx, seen: Mutex::default(), dropped }))
+    }
+
+    /// Queue every item not logged before; never blocks the fetch path
+    pub fn record(&self, news: &AggregatedNews) {
+        let fresh: Vec<(&'static Source, &NewsItem)> = {
+            let mut seen = self.seen.lock().unwrap();
+            news.results
+                .iter()
+                .chain(news.fallback.iter())
+                .filter_map(|r| Some((r.source, r.outcome.as_ref().ok()?)))
+                .flat_map(|(source, items)| items.iter().map(move |item| (source, item)))
+                .filter(|(_, item)| seen.insert(fingerprint(item)))
+                .collect()
+        };
+        for (source, item) in fresh {
+            let record = ItemRecord {
+                ts: chrono::Utc::now().to_rfc3339(),
+                source: source.name,
+                category: format!("{:?}", source.category),
+                title: item.title.clone(),
+                link: item.link.clone(),
+                fingerprint: format!("{:016x}", fingerprint(item)),
+                lang: source.language,
+                price: item.quote.as_ref().and_then(|q| q.value),
+                change_percent: item.quote.as_ref().and_then(|q| q.change_percent),
+                currency: item.quote.as_ref().map(|q| q.currency),
+            };
+            if self.tx.try_send(record).is_err() {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+fn run_writer(path: PathBuf, mut rx: mpsc::Receiver<ItemRecord>, dropped: Arc<AtomicU64>) {
+    while let Some(record) = rx.blocking_recv() {
+        if let Err(e) = append(&path, &record) {
+            // Disk trouble must never reach the fetch path
+            if dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                log::warn!("Item log write failed, dropping records: {}", e);
+            }
+        }
+    }
+}
+
+fn append(path: &PathBuf, record: &ItemRecord) -> std::io::Result<()> {
+    if fs::metadata(path).is_ok_and(|m| m.len() > MAX_LOG_BYTES) {
+        fs::rename(path, path.with_extension("1"))?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.write_all(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::SOURCES;
+    use crate::logic::{FetchMeta, Layout, SourceResult};
+    use crate::network::Quote;
+
+    fn log_with_capacity(capacity: usize) -> (ItemLog, mpsc::Receiver<ItemRecord>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (ItemLog { tx, seen: Mutex::default(), dropped: Arc::new(AtomicU64::new(0)) }, rx)
+    }
+
+    fn news(results: Vec<(&'static Source, Vec<NewsItem>)>) -> AggregatedNews {
+        let results = results.into_iter().map(|(s, items)| SourceResult::new(s, Ok(items), FetchMeta::default())).collect();
+        AggregatedNews { header: String::new(), layout: Layout::Grouped, results, fallback: None, paused: Vec::new() }
+    }
+
+    fn item(title: &str, link: Option<&str>) -> NewsItem {
+        NewsItem {
+            title: title.to_string(),
+            description: None,
+            link: link.map(str::to_string),
+            time_str: String::new(),
+            author: None,
+            feed_title: None,
+            views: None,
+            quote: None,
+            timestamp: None,
+            also_on: Vec::new(),
+        }
+    }
+
+    fn drain(rx: &mut mpsc::Receiver<ItemRecord>) -> Vec<ItemRecord> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn items_are_logged_once() {
+        let (log, mut rx) = log_with_capacity(16);
+        let rates = item("Central bank holds rates", Some("https://example.com/rates"));
+        let oil = item("Oil climbs", None);
+        log.record(&news(vec![(&SOURCES[0], vec![rates.clone(), oil.clone()]), (&SOURCES[1], vec![rates.clone()])]));
+        let first = drain(&mut rx);
+        assert_eq!(first.iter().map(|r| (r.source, r.title.as_str())).collect::<Vec<_>>(), [
+            (SOURCES[0].name, "Central bank holds rates"),
+            (SOURCES[0].name, "Oil climbs"),
+        ]);
+        assert_eq!(first[0].fingerprint, format!("{:016x}", fingerprint(&rates)));
+
+        // The next aggregation repeats both; only the new item goes out
+        let ports = item("Ports reopen", None);
+        log.record(&news(vec![(&SOURCES[0], vec![ports, rates, oil])]));
+        assert_eq!(drain(&mut rx).iter().map(|r| r.title.as_str()).collect::<Vec<_>>(), ["Ports reopen"]);
+        assert_eq!(log.dropped(), 0);
+    }
+
+    #[test]
+    fn a_full_queue_drops_instead_of_blocking() {
+        let (log, mut rx) = log_with_capacity(1);
+        let items = ["One", "Two", "Three"].map(|t| item(t, None)).to_vec();
+        log.record(&news(vec![(&SOURCES[0], items)]));
+        assert_eq!(drain(&mut rx).len(), 1);
+        assert_eq!(log.dropped(), 2);
+    }
+
+    #[test]
+    fn records_are_written_as_json_lines() {
+        let (log, mut rx) = log_with_capacity(16);
+        let mut gold = item("Gold", None);
+        gold.quote = Some(Quote { price: "$2,654.30".into(), change: Some("+0.52%".into()), value: Some(2654.3), change_percent: Some(0.52), currency: "USD" });
+        let plain = item("Rates unchanged", Some("https://example.com/a"));
+        log.record(&news(vec![(&SOURCES[0], vec![gold, plain])]));
+        let records = drain(&mut rx);
+
+        let path = std::env::temp_dir().join(format!("logos_bot_items_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        for record in &records {
+            append(&path, record).unwrap();
+        }
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = written.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        for (line, record) in lines.iter().zip(&records) {
+            assert_eq!(*line, serde_json::to_value(record).unwrap());
+        }
+        assert_eq!(lines[0]["price"], 2654.3);
+        assert_eq!(lines[0]["change_percent"], 0.52);
+        assert_eq!(lines[0]["currency"], "USD");
+        assert_eq!(lines[1]["link"], "https://example.com/a");
+        assert_eq!(lines[1]["lang"], SOURCES[0].language);
+        // Quote fields are left out for ordinary items
+        assert!(lines[1].get("price").is_none() && lines[1].get("currency").is_none());
+        assert!(chrono::DateTime::parse_from_rfc3339(lines[1]["ts"].as_str().unwrap()).is_ok());
+    }
+}
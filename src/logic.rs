@@ -1,8 +1,18 @@
 //! Business logic layer - Target resolution and aggregation
 
-use crate::consts::{find_source, sources_by_category, Category, Source};
-use crate::network::{format_error, format_results, NewsEngine};
+use crate::consts::{fallback_for, find_source, limits, register_custom_source, sources_by_category, sources_by_language, Auth, Category, Source, SourceType, SOURCES};
+use crate::network::{FetchError, NewsEngine, NewsItem};
+use crate::store::{CustomSource, Store};
+use crate::utils::{jaccard, title_tokens};
+use futures::future::join_all;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::field::Empty;
+use tracing::Instrument;
+use tokio::sync::{watch, Semaphore};
+use tokio::task::{JoinError, JoinHandle};
 
 /// Fetch target - either a category or specific source
 #[derive(Debug, Clone)]
@@ -11,6 +21,17 @@ pub enum Target {
     Category(Category),
     /// Fetch a specific source by name
     Source(&'static str),
+    /// Fetch the sources of a category written in one language
+    Subcategory {
+        category: Category,
+        language: &'static str,
+        label: &'static str,
+        command: &'static str,
+    },
+    /// A category merged into one newest-first timeline
+    Latest(Category),
+    /// Every unmetered source, keeping items that contain all the query words
+    Search(String),
 }
 
 impl Target {
@@ -21,6 +42,20 @@ impl Target {
             Target::Source(name) => {
                 find_source(name).into_iter().collect()
             }
+            Target::Subcategory { category, language, .. } => {
+                sources_by_language(*category, language).collect()
+            }
+            Target::Latest(cat) => sources_by_category(*cat).collect(),
+            // A search would spend metered requests on items that mostly won't match
+            Target::Search(_) => SOURCES.iter().filter(|s| s.source_type != SourceType::NewsData).collect(),
+        }
+    }
+
+    /// Category whose fallback may stand in when every source fails
+    pub fn category(&self) -> Option<Category> {
+        match self {
+            Target::Category(cat) | Target::Subcategory { category: cat, .. } | Target::Latest(cat) => Some(*cat),
+            Target::Source(_) | Target::Search(_) => None,
         }
     }
 
@@ -29,97 +64,1066 @@ impl Target {
         match self {
             Target::Category(cat) => cat.to_string(),
             Target::Source(name) => format!("🕷 {}", name),
+            Target::Subcategory { label, .. } => label.to_string(),
+            Target::Latest(cat) => format!("🕒 Latest {}", cat),
+            Target::Search(query) => format!("🔎 \"{}\"", query),
         }
     }
 }
 
-/// Aggregated fetch result
+/// How items within a source block are ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortMode {
+    /// As published by the source
+    #[default]
+    Recent,
+    /// Telegram items by view count, items without counts last
+    Popular,
+}
+
+/// Per-command fetch options parsed from command arguments
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FetchOptions {
+    pub sort: SortMode,
+    /// "fresh": bypass the response cache; honored for privileged requests only
+    pub refresh: bool,
+    /// A bare number, e.g. "/tass 10": items per source, clamped to MAX_ITEMS_CAP
+    pub max_items: Option<usize>,
+    /// Requested by an admin: allowed past metered API budgets
+    pub privileged: bool,
+    /// The chat's /lang choice; the configured translation target when unset
+    pub lang: Option<&'static str>,
+}
+
+impl FetchOptions {
+    /// Parse whitespace-separated arguments, ignoring unknown words
+    pub fn parse(args: &str) -> Self {
+        let mut options = Self::default();
+        for word in args.split_whitespace() {
+            if word.eq_ignore_ascii_case("popular") {
+                options.sort = SortMode::Popular;
+            } else if word.eq_ignore_ascii_case("fresh") {
+                options.refresh = true;
+            } else if let Ok(n) = word.parse::<usize>() {
+                options.max_items = Some(n.clamp(1, limits::MAX_ITEMS_CAP));
+            }
+        }
+        options
+    }
+
+    /// Language items are translated into
+    pub fn target_lang(&self) -> &'static str {
+        self.lang.unwrap_or_else(limits::translate_target)
+    }
+
+    /// Items to fetch per source, the default when none was asked for
+    pub fn item_cap(&self) -> usize {
+        self.max_items.unwrap_or(limits::MAX_ITEMS_PER_SOURCE)
+    }
+}
+
+/// How a single source fetch went, for diagnostics
+#[derive(Debug, Clone, Default)]
+pub struct FetchMeta {
+    pub elapsed: Duration,
+    pub cache_hit: bool,
+    pub attempts: u32,
+}
+
+/// Why a source that answered fine has nothing to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyReason {
+    AllJunk,
+    /// Every item repeated a story from an earlier source
+    Duplicates,
+}
+
+impl EmptyReason {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            EmptyReason::AllJunk => "only filtered posts",
+            EmptyReason::Duplicates => "only repeats of other sources",
+        }
+    }
+}
+
+/// Outcome of fetching one source
+pub struct SourceResult {
+    pub source: &'static Source,
+    pub outcome: Result<Vec<NewsItem>, FetchError>,
+    pub meta: FetchMeta,
+    /// Set when the fetch succeeded but every item was filtered out
+    pub empty_reason: Option<EmptyReason>,
+}
+
+impl SourceResult {
+    pub fn new(source: &'static Source, outcome: Result<Vec<NewsItem>, FetchError>, meta: FetchMeta) -> Self {
+        // The engine only returns an empty Ok after junk filtering
+        let empty_reason = match &outcome {
+            Ok(items) if items.is_empty() => Some(EmptyReason::AllJunk),
+            _ => None,
+        };
+        Self { source, outcome, meta, empty_reason }
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        self.empty_reason.is_some()
+    }
+}
+
+/// How the formatter lays out an aggregation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// One block per source
+    Grouped,
+    /// All items merged newest first, tagged with their source
+    Timeline,
+}
+
+/// Aggregated fetch result, rendered by the formatter at send time
 pub struct AggregatedNews {
     pub header: String,
-    pub content: String,
-    pub success_count: usize,
-    pub error_count: usize,
-}
-
-/// Fetch news for a target with aggregation
-pub async fn fetch_target(engine: Arc<NewsEngine>, target: Target) -> AggregatedNews {
-    let sources = target.resolve();
-    let header = format!("{} Feed", target.display_name());
-
-    if sources.is_empty() {
-        return AggregatedNews {
-            header,
-            content: "🕸 No sources found".to_string(),
-            success_count: 0,
-            error_count: 1,
-        };
+    pub layout: Layout,
+    pub results: Vec<SourceResult>,
+    /// Category fallback, fetched only when every primary source failed
+    pub fallback: Option<SourceResult>,
+    /// Sources of the target skipped because an admin disabled them
+    pub paused: Vec<&'static Source>,
+}
+
+impl AggregatedNews {
+    /// Every primary source errored (quiet sources count as answering)
+    pub fn all_failed(&self) -> bool {
+        !self.results.is_empty() && self.results.iter().all(|r| r.outcome.is_err())
+    }
+
+    pub fn success_count(&self) -> usize {
+        self.results.iter().filter(|r| r.outcome.is_ok() && !r.is_quiet()).count()
+    }
+
+    /// Sources that answered but had nothing to show
+    pub fn quiet_count(&self) -> usize {
+        self.results.iter().filter(|r| r.is_quiet()).count()
+    }
+
+    /// Failed sources, timeouts excluded; a target that resolved to no sources counts as one failure
+    pub fn error_count(&self) -> usize {
+        if self.results.is_empty() {
+            return 1;
+        }
+        self.results.iter().filter(|r| r.outcome.is_err()).count() - self.timeout_count()
+    }
+
+    /// Sources cut off by the per-source budget
+    pub fn timeout_count(&self) -> usize {
+        self.results.iter().filter(|r| matches!(r.outcome, Err(FetchError::Timeout(_)))).count()
+    }
+
+    /// Link of the first item, used for the link preview
+    pub fn lead_link(&self) -> Option<String> {
+        self.results
+            .iter()
+            .filter_map(|r| r.outcome.as_ref().ok())
+            .flatten()
+            .find_map(|item| item.link.clone())
+    }
+}
+
+/// Where one source stands within a running aggregation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceState {
+    Pending,
+    Done,
+    Failed,
+}
+
+/// Per-source states of a running aggregation, in registry order
+#[derive(Debug, Clone, Default)]
+pub struct AggregationProgress {
+    pub sources: Vec<(&'static str, SourceState)>,
+}
+
+impl AggregationProgress {
+    pub fn finished(&self) -> usize {
+        self.sources.iter().filter(|(_, s)| *s != SourceState::Pending).count()
+    }
+
+    pub fn total(&self) -> usize {
+        self.sources.len()
     }
+}
+
+/// An in-flight aggregation: watch its progress, cancel it, or await the result
+pub struct AggregationHandle {
+    task: JoinHandle<AggregatedNews>,
+    pub progress: watch::Receiver<AggregationProgress>,
+}
 
-    let mut content = String::with_capacity(4096);
-    let mut success_count = 0;
-    let mut error_count = 0;
+impl AggregationHandle {
+    /// Abort outstanding source fetches; their semaphore permits drop with them
+    pub fn cancel(&self) {
+        self.task.abort();
+    }
 
-    for source in sources {
-        // Используем fetch вместо fetch_with_retry, так как мы упростили network.rs
-        match engine.fetch(source).await {
-            Ok(items) => {
-                content.push_str(&format_results(source.name, &items));
-                content.push('\n');
-                success_count += 1;
+    /// The aggregated result, or None if the aggregation was cancelled
+    pub async fn result(self) -> Option<AggregatedNews> {
+        Self::joined(self.task.await)
+    }
+
+    /// Like `result`, but cancels the aggregation once `limit` passes
+    pub async fn result_within(mut self, limit: Duration) -> Option<AggregatedNews> {
+        match tokio::time::timeout(limit, &mut self.task).await {
+            Ok(joined) => Self::joined(joined),
+            Err(_) => {
+                self.cancel();
+                None
             }
-            Err(e) => {
-                log::error!("Failed to fetch {}: {}", source.name, e);
-                content.push_str(&format_error(source.name, &e));
-                error_count += 1;
+        }
+    }
+
+    fn joined(joined: Result<AggregatedNews, JoinError>) -> Option<AggregatedNews> {
+        match joined {
+            Ok(news) => Some(news),
+            Err(e) if e.is_cancelled() => None,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+/// Start fetching news for a target in the background
+pub fn fetch_target(engine: Arc<NewsEngine>, target: Target, options: FetchOptions) -> AggregationHandle {
+    let (paused, sources): (Vec<&'static Source>, Vec<_>) = target.resolve().into_iter().partition(|s| engine.is_paused(s));
+    let fallback = target.category().and_then(fallback_for).filter(|s| !engine.is_paused(s));
+    start_aggregation(engine, target, sources, paused, fallback, options)
+}
+
+fn start_aggregation(
+    engine: Arc<NewsEngine>,
+    target: Target,
+    sources: Vec<&'static Source>,
+    paused: Vec<&'static Source>,
+    fallback: Option<&'static Source>,
+    options: FetchOptions,
+) -> AggregationHandle {
+    let (progress_tx, progress) = watch::channel(AggregationProgress {
+        sources: sources.iter().map(|s| (s.name, SourceState::Pending)).collect(),
+    });
+    let span = tracing::info_span!("aggregate", target = %target.display_name());
+    let task = tokio::spawn(aggregate(engine, target, sources, paused, fallback, options, progress_tx).instrument(span));
+    AggregationHandle { task, progress }
+}
+
+async fn aggregate(
+    engine: Arc<NewsEngine>,
+    target: Target,
+    sources: Vec<&'static Source>,
+    paused: Vec<&'static Source>,
+    fallback: Option<&'static Source>,
+    options: FetchOptions,
+    progress: watch::Sender<AggregationProgress>,
+) -> AggregatedNews {
+    let header = match &target {
+        Target::Search(_) => format!("{} in {} sources", target.display_name(), sources.len()),
+        _ => format!("{} Feed", target.display_name()),
+    };
+
+    // Fetch concurrently, capped by a semaphore; join_all keeps the registry order and
+    // each failure stays inside its own SourceResult
+    let permits = Semaphore::new(limits::fetch_concurrency());
+    let mut results = join_all(sources.into_iter().enumerate().map(|(index, source)| {
+        let (engine, permits, progress) = (&engine, &permits, &progress);
+        async move {
+            let _permit = permits.acquire().await.expect("fetch semaphore closed");
+            let result = fetch_source(engine, source, options).await;
+            let state = if result.outcome.is_ok() { SourceState::Done } else { SourceState::Failed };
+            progress.send_modify(|p| p.sources[index].1 = state);
+            result
+        }
+    }))
+    .await;
+
+    let removed = dedup_across_sources(&mut results);
+    if removed > 0 {
+        log::info!("{}: dropped {} cross-source duplicates", header, removed);
+    }
+
+    if let Target::Search(query) = &target {
+        keep_matches(&mut results, query);
+    }
+
+    let layout = match target {
+        Target::Latest(_) | Target::Search(_) => Layout::Timeline,
+        _ => Layout::Grouped,
+    };
+    let mut news = AggregatedNews { header, layout, results, fallback: None, paused };
+    if news.all_failed() {
+        if let Some(fallback) = fallback {
+            log::warn!("All {} sources failed, using fallback {}", news.header, fallback.name);
+            news.fallback = Some(fetch_source(&engine, fallback, options).await);
+        }
+    }
+    news
+}
+
+/// Drop items whose titles closely match an item from an earlier source, keeping the first
+/// and noting the dropped item's source on it. Returns how many were removed; a source left
+/// with nothing is marked as repeats.
+fn dedup_across_sources(results: &mut [SourceResult]) -> usize {
+    // (result index, item index, title tokens) of every item kept so far
+    let mut kept: Vec<(usize, usize, HashSet<String>)> = Vec::new();
+    let mut also_on: Vec<(usize, usize, &'static str)> = Vec::new();
+    let mut removed = 0;
+    for (r, result) in results.iter_mut().enumerate() {
+        let name = result.source.name;
+        let Ok(items) = &mut result.outcome else { continue };
+        if items.is_empty() {
+            continue;
+        }
+        let mut own = Vec::new();
+        items.retain(|item| {
+            let tokens = title_tokens(&item.title);
+            let original = (!tokens.is_empty())
+                .then(|| kept.iter().find(|(_, _, k)| jaccard(k, &tokens) >= limits::DEDUP_SIMILARITY))
+                .flatten();
+            match original {
+                Some((kr, ki, _)) => {
+                    also_on.push((*kr, *ki, name));
+                    removed += 1;
+                    false
+                }
+                None => {
+                    own.push(tokens);
+                    true
+                }
+            }
+        });
+        if items.is_empty() {
+            result.empty_reason = Some(EmptyReason::Duplicates);
+        }
+        kept.extend(own.into_iter().enumerate().map(|(i, tokens)| (r, i, tokens)));
+    }
+
+    for (r, i, name) in also_on {
+        if let Ok(items) = &mut results[r].outcome {
+            let item = &mut items[i];
+            if !item.also_on.contains(&name) {
+                item.also_on.push(name);
             }
         }
     }
+    removed
+}
+
+/// Keep items whose title or description contains every query word, case-insensitively
+fn keep_matches(results: &mut [SourceResult], query: &str) {
+    let words = query_words(query);
+    for result in results.iter_mut() {
+        if let Ok(items) = &mut result.outcome {
+            items.retain(|item| matches_words(item, &words));
+        }
+    }
+}
+
+/// Lowercase words of a search or keyword alert
+pub fn query_words(query: &str) -> Vec<String> {
+    query.split_whitespace().map(str::to_lowercase).collect()
+}
+
+/// Whether the title or description contains every one of `words`, see `query_words`
+pub fn matches_words(item: &NewsItem, words: &[String]) -> bool {
+    let text = format!("{} {}", item.title, item.description.as_deref().unwrap_or_default()).to_lowercase();
+    words.iter().all(|w| text.contains(w.as_str()))
+}
 
-    AggregatedNews {
-        header,
-        content,
-        success_count,
-        error_count,
+/// Why /addsource refused a feed
+#[derive(Error, Debug)]
+pub enum AddSourceError {
+    #[error("name must be 2-32 latin letters, digits or _")]
+    InvalidName,
+    #[error("/{0} is already taken")]
+    Duplicate(String),
+    #[error("URL must be http:// or https://")]
+    InvalidUrl,
+    #[error("auth must be basic:USER_ENV:PASS_ENV, bearer:TOKEN_ENV or query:param:VALUE_ENV")]
+    InvalidAuth,
+    #[error("feed check failed: {0}")]
+    Feed(FetchError),
+    #[error("custom source limit reached")]
+    Full,
+}
+
+/// Re-register the feeds added with /addsource before the last restart
+pub fn restore_custom_sources(store: &dyn Store) {
+    let saved = store.custom_sources().unwrap_or_else(|e| {
+        log::error!("Failed to load custom sources: {}", e);
+        Vec::new()
+    });
+    for entry in saved {
+        if routes::resolve_command(&entry.name).is_some() {
+            log::warn!("Skipping saved source {}: the name is taken", entry.name);
+            continue;
+        }
+        let auth = match entry.auth.as_deref().map(Auth::parse) {
+            Some(None) => {
+                log::warn!("Skipping saved source {}: unreadable auth spec", entry.name);
+                continue;
+            }
+            auth => auth.flatten(),
+        };
+        match register_custom_source(&entry.name, &entry.url, auth) {
+            Some(source) => log::info!("Restored custom source {}", source.name),
+            None => log::warn!("Skipping saved source {}: MAX_CUSTOM_SOURCES reached", entry.name),
+        }
     }
 }
 
-/// Build help message
+/// Validate and register a runtime RSS source; `/name` then fetches it. A private feed
+/// comes with an `Auth::parse` spec naming the env vars that hold its credentials.
+pub async fn add_custom_source(
+    engine: &NewsEngine,
+    store: &dyn Store,
+    name: &str,
+    url: &str,
+    auth: Option<&str>,
+) -> Result<&'static Source, AddSourceError> {
+    let valid_name = (2..=32).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        return Err(AddSourceError::InvalidName);
+    }
+    if routes::resolve_command(name).is_some() {
+        return Err(AddSourceError::Duplicate(name.to_lowercase()));
+    }
+    let scheme_ok = reqwest::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https"));
+    if !scheme_ok {
+        return Err(AddSourceError::InvalidUrl);
+    }
+    let auth = auth.map(|spec| Auth::parse(spec).ok_or(AddSourceError::InvalidAuth)).transpose()?;
+    let entries = engine.probe_feed(url, auth.as_ref()).await.map_err(AddSourceError::Feed)?;
+    let source = register_custom_source(name, url, auth).ok_or(AddSourceError::Full)?;
+    let saved = CustomSource { name: source.name.to_string(), url: source.url.to_string(), auth: auth.map(|a| a.spec()) };
+    if let Err(e) = store.save_custom_source(&saved) {
+        log::error!("Failed to persist custom source {}: {}", source.name, e);
+    }
+    log::info!("Added custom source {} ({} entries)", source.name, entries);
+    Ok(source)
+}
+
+/// Fetch one source, applying budgets and per-command ordering
+#[tracing::instrument(skip_all, fields(source = source.name, items = Empty, ms = Empty, cache_hit = Empty))]
+async fn fetch_source(engine: &NewsEngine, source: &'static Source, options: FetchOptions) -> SourceResult {
+    let started = Instant::now();
+    let cached = match options.refresh && options.privileged {
+        true => None,
+        false => engine.cached(source, options.item_cap()).await,
+    };
+    let (mut outcome, attempts, cache_hit) = match cached {
+        Some(items) => (Ok(items), 0, true),
+        None => {
+            let budget = Duration::from_secs(limits::SOURCE_BUDGET_SECS);
+            let allowed = engine
+                .circuits
+                .check(source.name)
+                .map_err(|retry_in| FetchError::CircuitOpen { retry_in })
+                .and_then(|()| spend_budget(engine, source, options.privileged));
+            let (outcome, attempts) = match allowed {
+                Ok(()) => {
+                    let fetch = engine.fetch_with_retry(source, options.item_cap(), limits::fetch_attempts());
+                    let (outcome, attempts) = tokio::time::timeout(budget, fetch).await.unwrap_or_else(|_| {
+                        // The dropped fetch never got to record itself
+                        engine.metrics.record_fetch(source.name, Some("timeout"), 1, budget);
+                        (Err(FetchError::Timeout(budget)), 1)
+                    });
+                    match &outcome {
+                        Ok(_) => engine.circuits.record(source.name, true),
+                        Err(e) if e.trips_circuit() => engine.circuits.record(source.name, false),
+                        Err(_) => {}
+                    }
+                    (outcome, attempts)
+                }
+                Err(e) => (Err(e), 0),
+            };
+            engine.record_status(source, &outcome, started.elapsed());
+            (outcome, attempts, false)
+        }
+    };
+    if let Ok(items) = outcome {
+        outcome = Ok(engine.translate_items(source, items, options.target_lang()).await);
+    }
+    let meta = FetchMeta { elapsed: started.elapsed(), cache_hit, attempts };
+    let span = tracing::Span::current();
+    span.record("ms", meta.elapsed.as_millis() as u64);
+    span.record("cache_hit", cache_hit);
+    if let Ok(items) = &outcome {
+        span.record("items", items.len());
+        tracing::debug!(attempts, "fetched");
+    }
+    match &mut outcome {
+        Ok(items) => {
+            if options.sort == SortMode::Popular && source.source_type == SourceType::TelegramHtml {
+                items.sort_by_key(|item| std::cmp::Reverse(item.views));
+            } else {
+                // Newest first; items without a parsed time sink, keeping their order
+                items.sort_by_key(|item| std::cmp::Reverse(item.timestamp));
+            }
+        }
+        Err(e) => log::error!("Failed to fetch {}: {}", source.name, e),
+    }
+    SourceResult::new(source, outcome, meta)
+}
+
+/// Metered sources spend from their daily budget before fetching
+fn spend_budget(engine: &NewsEngine, source: &Source, privileged: bool) -> Result<(), FetchError> {
+    if source.source_type != SourceType::NewsData {
+        return Ok(());
+    }
+    engine
+        .newsdata_budget
+        .try_spend(privileged)
+        .map_err(|resets_in| FetchError::QuotaExhausted { resets_in })
+}
+
+/// Build help message (HTML, like every other response)
 pub fn build_help_message() -> String {
+    let sources: Vec<String> = SOURCES.iter().map(|s| format!("/{}", s.command())).collect();
     format!(
-        "👁‍🗨 *LOGOS News Aggregator*\n\n\
-        *Categories:*\n\
+        "👁‍🗨 <b>LOGOS News Aggregator</b>\n\n\
+        <b>Categories:</b>\n\
         /global — 🖤 Global\n\
         /war — 🤍 War\n\
+        /ukraine — 🇺🇦 War (Ukrainian sources)\n\
         /market — 🏴 Market\n\
-        /commodities — ✟ ANCIENT DUST\n\n\
-        _Order out of Chaos_"
+        /commodities — ✟ ANCIENT DUST\n\
+        /latest &lt;category&gt; — 🕒 newest first across sources\n\
+        /search &lt;words&gt; — 🔎 items mentioning all words\n\n\
+        <b>Sources:</b>\n{}\n/sources — 📡 health of every feed\n\
+        /status — 🩺 latency, cache age and failures per feed\n\n\
+        /lang &lt;code&gt; — 🌐 language news is translated into\n\
+        /settings — ⚙️ items per source, descriptions, seen items, timezone\n\
+        /reset — 🆕 forget seen items, everything is marked new again\n\
+        /subscribe &lt;command&gt; [minutes] — 🔔 new items pushed to this chat\n\
+        /subscribe &lt;command&gt; HH:MM — 📰 daily digest\n\
+        /profile add night war,liveuamap 22:00 — 👤 subscriptions switched on a schedule\n\
+        /alert gold above 2700 — 💰 one-shot price alert\n\
+        /alert war strike — 💰 new items with these words\n\n\
+        <b>Inline:</b> type the bot's @name and a command, e.g. <code>war</code>\n\n\
+        <i>Order out of Chaos</i>",
+        sources.join(" ")
     )
 }
 
 /// Build summary line
 pub fn build_summary(result: &AggregatedNews) -> String {
+    let quiet = match result.quiet_count() {
+        0 => String::new(),
+        n => format!(" | 💤 {} quiet", n),
+    };
+    let slow = match result.timeout_count() {
+        0 => String::new(),
+        n => format!(" | ⌛ {} slow", n),
+    };
+    let paused: String = result.paused.iter().map(|s| format!(" | ⏸ {} (disabled)", s.name)).collect();
     format!(
-        "\n───────────────────\n👁‍🗨 {} active | 🕸 {} dead",
-        result.success_count, result.error_count
+        "\n───────────────────\n👁‍🗨 {} active{} | 🕸 {} dead{}{}",
+        result.success_count(),
+        quiet,
+        result.error_count(),
+        slow,
+        paused
     )
 }
 
+/// Longest footer we append, so it never crowds the summary
+const FOOTER_MAX_CHARS: usize = 100;
+
+/// Follow-up shortcuts for a target; every command in here resolves via routes
+pub fn build_footer(target: &Target) -> String {
+    let links = match target {
+        Target::Category(cat) => vec![
+            format!("↻ /{}", cat.command()),
+            format!("🔥 /{} popular", cat.command()),
+        ],
+        Target::Subcategory { command, category, .. } => vec![
+            format!("↻ /{}", command),
+            format!("🗂 /{}", category.command()),
+        ],
+        Target::Search(query) => vec![format!("↻ /search {}", crate::format::escape_html(query))],
+        Target::Latest(cat) => vec![
+            format!("↻ /latest {}", cat.command()),
+            format!("🗂 /{}", cat.command()),
+        ],
+        Target::Source(name) => match find_source(name) {
+            Some(source) => {
+                let command = source.command();
+                let mut links = vec![format!("↻ /{}", command), format!("🗂 /{}", source.category.command())];
+                // Price sources can be watched with a threshold alert
+                if source.extractor.is_some() {
+                    links.push(format!("🔔 /alert {} &gt; …", command));
+                }
+                links
+            }
+            None => Vec::new(),
+        },
+    };
+
+    let mut footer = String::new();
+    for link in links {
+        let candidate = if footer.is_empty() { link } else { format!("{} · {}", footer, link) };
+        if candidate.chars().count() > FOOTER_MAX_CHARS {
+            break;
+        }
+        footer = candidate;
+    }
+    if footer.is_empty() { footer } else { format!("\n{}", footer) }
+}
+
 /// Command routing table
 pub mod routes {
     use super::*;
     use crate::consts::Category;
 
+    /// "/latest war": a category as one timeline, Global when none is given
+    pub fn resolve_latest(arg: &str) -> Option<Target> {
+        match arg.split_whitespace().next() {
+            None => Some(Target::Latest(Category::Global)),
+            Some(word) => Category::ALL
+                .into_iter()
+                .find(|c| c.command().eq_ignore_ascii_case(word))
+                .map(Target::Latest),
+        }
+    }
+
     /// Map command string to target
     pub fn resolve_command(cmd: &str) -> Option<Target> {
         match cmd.to_lowercase().as_str() {
             "global" => Some(Target::Category(Category::Global)),
             "war" => Some(Target::Category(Category::War)),
+            "ukraine" => Some(Target::Subcategory {
+                category: Category::War,
+                language: "uk",
+                label: "🇺🇦 Ukraine",
+                command: "ukraine",
+            }),
             "market" => Some(Target::Category(Category::Market)),
             "commodities" => Some(Target::Category(Category::Commodities)),
-            "reuters" => Some(Target::Source("Reuters")),
-            "gold" => Some(Target::Source("Gold")),
-            "oil" => Some(Target::Source("Oil")),
-            _ => None,
+            // Every registered source is its own command
+            name => find_source(name).map(|s| Target::Source(s.name)),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ukraine() -> Target {
+        routes::resolve_command("ukraine").unwrap()
+    }
+
+    #[test]
+    fn footer_per_target_kind() {
+        assert_eq!(build_footer(&Target::Category(Category::War)), "\n↻ /war · 🔥 /war popular");
+        assert_eq!(build_footer(&ukraine()), "\n↻ /ukraine · 🗂 /war");
+        assert_eq!(build_footer(&Target::Latest(Category::Market)), "\n↻ /latest market · 🗂 /market");
+        assert_eq!(build_footer(&Target::Search("oil & gas".into())), "\n↻ /search oil &amp; gas");
+        assert_eq!(build_footer(&Target::Source("TASS")), "\n↻ /tass · 🗂 /war");
+        assert_eq!(build_footer(&Target::Source("Gold")), "\n↻ /gold · 🗂 /commodities · 🔔 /alert gold &gt; …");
+        assert_eq!(build_footer(&Target::Source("NoSuchSource")), "");
+    }
+
+    #[test]
+    fn footer_commands_resolve() {
+        let mut targets = vec![ukraine(), Target::Search("oil".into())];
+        for cat in Category::ALL {
+            targets.push(Target::Category(cat));
+            targets.push(Target::Latest(cat));
+        }
+        targets.extend(SOURCES.iter().map(|s| Target::Source(s.name)));
+
+        for target in &targets {
+            let footer = build_footer(target);
+            assert!(footer.chars().count() <= FOOTER_MAX_CHARS + 1, "{:?}: {}", target, footer);
+            for link in footer.trim().split(" · ") {
+                let (_, command) = link.split_once('/').unwrap_or_else(|| panic!("no command in {:?}", link));
+                let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+                let resolves = match name {
+                    "search" => !args.is_empty(),
+                    "latest" => routes::resolve_latest(args).is_some(),
+                    "alert" => args.split_whitespace().next().and_then(find_source).is_some_and(|s| s.extractor.is_some()),
+                    _ => routes::resolve_command(name).is_some(),
+                };
+                assert!(resolves, "{:?}: /{} doesn't resolve", target, command);
+            }
+        }
+    }
+
+    #[test]
+    fn fetch_options_from_arguments() {
+        let options = FetchOptions::parse("popular 10 fresh");
+        assert_eq!(options.sort, SortMode::Popular);
+        assert!(options.refresh);
+        assert_eq!(options.item_cap(), 10);
+
+        assert_eq!(FetchOptions::parse("100").max_items, Some(limits::MAX_ITEMS_CAP));
+        assert_eq!(FetchOptions::parse("0").max_items, Some(1));
+        let defaults = FetchOptions::parse("  POPULAR whatever -3 ");
+        assert_eq!(defaults.sort, SortMode::Popular);
+        assert_eq!(defaults.item_cap(), limits::MAX_ITEMS_PER_SOURCE);
+        assert!(!defaults.refresh);
+    }
+
+    fn headline(title: &str) -> NewsItem {
+        NewsItem {
+            title: title.to_string(),
+            description: None,
+            link: None,
+            time_str: String::new(),
+            author: None,
+            feed_title: None,
+            views: None,
+            quote: None,
+            timestamp: None,
+            also_on: Vec::new(),
+        }
+    }
+
+    fn titles(result: &SourceResult) -> Vec<&str> {
+        result.outcome.as_ref().unwrap().iter().map(|i| i.title.as_str()).collect()
+    }
+
+    fn described(title: &str, description: &str) -> NewsItem {
+        NewsItem { description: Some(description.to_string()), ..headline(title) }
+    }
+
+    #[test]
+    fn search_keeps_items_with_every_query_word() {
+        let mut results = vec![
+            SourceResult::new(&SOURCES[0], Ok(vec![
+                headline("OIL prices climb after supply cuts"),
+                described("Markets wrap", "Brent oil prices eased in late trading"),
+                headline("Oil output steady"),
+            ]), FetchMeta::default()),
+            SourceResult::new(&SOURCES[1], Ok(vec![headline("Нефть дорожает на фоне сокращения добычи")]), FetchMeta::default()),
+            SourceResult::new(&SOURCES[2], Err(FetchError::Status(503)), FetchMeta::default()),
+        ];
+        keep_matches(&mut results, "oil  Prices");
+        assert_eq!(titles(&results[0]), ["OIL prices climb after supply cuts", "Markets wrap"]);
+        assert!(titles(&results[1]).is_empty());
+        assert!(results[2].outcome.is_err());
+
+        let mut cyrillic = vec![SourceResult::new(&SOURCES[1], Ok(vec![headline("Нефть дорожает")]), FetchMeta::default())];
+        keep_matches(&mut cyrillic, "НЕФТЬ");
+        assert_eq!(titles(&cyrillic[0]), ["Нефть дорожает"]);
+    }
+
+    #[test]
+    fn search_with_no_matches_says_so() {
+        let mut results = vec![SourceResult::new(&SOURCES[0], Ok(vec![headline("Oil output steady")]), FetchMeta::default())];
+        keep_matches(&mut results, "oil gold");
+        let news = AggregatedNews { header: "🔎 \"oil gold\" in 1 sources".into(), layout: Layout::Timeline, results, fallback: None, paused: Vec::new() };
+        let text = crate::format::render_blocks(&news, &crate::format::RenderOptions::default()).concat();
+        assert!(text.contains("<i>Nothing to show</i>"), "{}", text);
+    }
+
+    #[test]
+    fn search_skips_metered_sources() {
+        let searched = Target::Search("oil".into()).resolve();
+        assert!(!searched.is_empty());
+        assert!(searched.iter().all(|s| s.source_type != SourceType::NewsData));
+        assert_eq!(searched.len(), SOURCES.iter().filter(|s| s.source_type != SourceType::NewsData).count());
+    }
+
+    #[test]
+    fn empty_reasons_and_summary_counts() {
+        let outcomes = [
+            Ok(vec![headline("Central bank holds rates steady amid inflation worries"), headline("Oil prices climb after supply cuts")]),
+            Ok(Vec::new()),
+            Ok(vec![headline("Central bank holds rates steady amid inflation worries!")]),
+            Err(FetchError::Timeout(Duration::from_secs(8))),
+            Err(FetchError::Status(503)),
+            Err(FetchError::Status(404)),
+        ];
+        let mut results: Vec<SourceResult> = outcomes
+            .into_iter()
+            .enumerate()
+            .map(|(i, outcome)| SourceResult::new(&SOURCES[i], outcome, FetchMeta::default()))
+            .collect();
+        // An empty answer is junk-filtered; repeats are only known once sources are compared
+        assert_eq!(results[1].empty_reason, Some(EmptyReason::AllJunk));
+        assert_eq!(results[2].empty_reason, None);
+        assert_eq!(dedup_across_sources(&mut results), 1);
+        assert_eq!(results[2].empty_reason, Some(EmptyReason::Duplicates));
+        assert_eq!(results[0].outcome.as_ref().unwrap()[0].also_on, [SOURCES[2].name]);
+        assert!(results[3..].iter().all(|r| r.empty_reason.is_none()));
+
+        let news = AggregatedNews { header: String::new(), layout: Layout::Grouped, results, fallback: None, paused: vec![&SOURCES[6]] };
+        assert_eq!((news.success_count(), news.quiet_count(), news.error_count(), news.timeout_count()), (1, 2, 2, 1));
+        assert!(!news.all_failed());
+        assert_eq!(
+            build_summary(&news),
+            format!("\n───────────────────\n👁‍🗨 1 active | 💤 2 quiet | 🕸 2 dead | ⌛ 1 slow | ⏸ {} (disabled)", SOURCES[6].name)
+        );
+
+        // A target with no sources at all reads as one failure
+        let nothing = AggregatedNews { header: String::new(), layout: Layout::Grouped, results: Vec::new(), fallback: None, paused: Vec::new() };
+        assert_eq!(nothing.error_count(), 1);
+        assert!(!nothing.all_failed());
+        assert_eq!(build_summary(&nothing), "\n───────────────────\n👁‍🗨 0 active | 🕸 1 dead");
+    }
+
+    /// A feed source on a local server; commodities are never translated, so nothing else is called
+    fn mock_source(name: String, url: String) -> &'static Source {
+        let name: &'static str = Box::leak(name.into_boxed_str());
+        Box::leak(Box::new(Source::new(name, Box::leak(url.into_boxed_str()), SourceType::Rss, Category::Commodities, "en")))
+    }
+
+    #[tokio::test]
+    async fn cancelling_stops_an_aggregation_mid_flight() {
+        use tokio::net::TcpListener;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        // Accept every connection and never answer
+        let (held_tx, mut held) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let _ = held_tx.send(socket);
+            }
+        });
+
+        let running = limits::fetch_concurrency();
+        let sources = (0..running + 2).map(|i| mock_source(format!("Hang{}", i), format!("{}/feed/{}", base, i))).collect();
+        let handle = start_aggregation(NewsEngine::new().unwrap(), Target::Category(Category::Global), sources, Vec::new(), None, FetchOptions::default());
+        let mut sockets = Vec::new();
+        while sockets.len() < running {
+            sockets.push(tokio::time::timeout(Duration::from_secs(15), held.recv()).await.unwrap().unwrap());
+        }
+        assert_eq!(handle.progress.borrow().finished(), 0);
+        handle.cancel();
+
+        // The fetches holding permits let go of their connections and nothing else starts
+        for mut socket in sockets {
+            let mut buf = [0; 4096];
+            loop {
+                let n = tokio::time::timeout(Duration::from_secs(2), tokio::io::AsyncReadExt::read(&mut socket, &mut buf)).await.unwrap().unwrap_or(0);
+                if n == 0 { break; }
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(limits::WARMUP_INTERVAL_MS + 1000)).await;
+        assert!(held.try_recv().is_err());
+        assert!(handle.result().await.is_none());
+    }
+
+    /// Serve `feed` at `path` and 404 elsewhere, for as many requests as come
+    async fn serve_feed(path: &'static str, feed: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut head = vec![0; 4096];
+                let n = socket.read(&mut head).await.unwrap();
+                let reply = match String::from_utf8_lossy(&head[..n]).starts_with(&format!("GET {} ", path)) {
+                    true => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", feed.len(), feed),
+                    false => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                };
+                socket.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        base
+    }
+
+    #[tokio::test]
+    async fn a_finished_aggregation_reports_every_source() {
+        const FEED: &str = r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Mock</title>
+            <item><title>Central bank holds rates steady amid inflation worries</title><link>https://example.com/a</link></item>
+            </channel></rss>"#;
+        let base = serve_feed("/ok", FEED).await;
+
+        let sources = vec![mock_source("Up".into(), format!("{}/ok", base)), mock_source("Gone".into(), format!("{}/gone", base))];
+        let handle = start_aggregation(NewsEngine::new().unwrap(), Target::Category(Category::Global), sources, Vec::new(), None, FetchOptions::default());
+        let mut progress = handle.progress.clone();
+        assert_eq!(progress.borrow().sources, [("Up", SourceState::Pending), ("Gone", SourceState::Pending)]);
+        let news = handle.result().await.unwrap();
+        assert_eq!(progress.borrow_and_update().sources, [("Up", SourceState::Done), ("Gone", SourceState::Failed)]);
+        assert_eq!(progress.borrow().finished(), 2);
+        assert_eq!(news.results.iter().map(|r| r.source.name).collect::<Vec<_>>(), ["Up", "Gone"]);
+        assert_eq!(news.results[0].outcome.as_ref().unwrap().len(), 1);
+        assert!(matches!(news.results[1].outcome, Err(FetchError::Status(404))));
+    }
+
+    #[tokio::test]
+    async fn items_are_ordered_newest_first_and_undated_ones_sink() {
+        const FEED: &str = r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Mock</title>
+            <item><title>Undated first</title><link>https://example.com/1</link></item>
+            <item><title>Oil climbs</title><link>https://example.com/2</link><pubDate>Sun, 10 Mar 2030 08:00:00 GMT</pubDate></item>
+            <item><title>Undated second</title><link>https://example.com/3</link></item>
+            <item><title>Rates unchanged</title><link>https://example.com/4</link><pubDate>Sun, 10 Mar 2030 11:15:00 +0300</pubDate></item>
+            <item><title>Ports reopen</title><link>https://example.com/5</link><pubDate>Sun, 10 Mar 2030 09:30:00 GMT</pubDate></item>
+            </channel></rss>"#;
+        let base = serve_feed("/feed", FEED).await;
+        let sources = vec![mock_source("Dated".into(), format!("{}/feed", base))];
+        let news = start_aggregation(NewsEngine::new().unwrap(), Target::Category(Category::Global), sources, Vec::new(), None, FetchOptions::default())
+            .result()
+            .await
+            .unwrap();
+        assert_eq!(titles(&news.results[0]), ["Ports reopen", "Rates unchanged", "Oil climbs", "Undated first", "Undated second"]);
+    }
+
+    #[tokio::test]
+    async fn a_category_whose_sources_all_fail_falls_back() {
+        const FEED: &str = r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Mock</title>
+            <item><title>Central bank holds rates steady amid inflation worries</title><link>https://example.com/a</link></item>
+            </channel></rss>"#;
+        let base = serve_feed("/ok", FEED).await;
+        let aggregate = |paths: [&str; 2], fallback: &str| {
+            let sources = paths.iter().enumerate().map(|(i, p)| mock_source(format!("Primary{}", i), format!("{}{}", base, p))).collect();
+            let fallback = mock_source("Fallback".into(), format!("{}{}", base, fallback));
+            start_aggregation(NewsEngine::new().unwrap(), Target::Category(Category::War), sources, Vec::new(), Some(fallback), FetchOptions::default()).result()
+        };
+
+        let news = aggregate(["/gone", "/gone"], "/ok").await.unwrap();
+        let fallback = news.fallback.as_ref().unwrap();
+        assert_eq!(fallback.source.name, "Fallback");
+        assert_eq!(fallback.outcome.as_ref().unwrap().len(), 1);
+        let text = crate::format::render_blocks(&news, &crate::format::RenderOptions::default()).concat();
+        assert!(text.contains("⚠️ <i>primary sources unavailable — fallback:</i>\n<b>🏴 Fallback</b>"), "{}", text);
+        // The primary failures are still reported
+        assert!(build_summary(&news).contains("🕸 2 dead"));
+
+        let news = aggregate(["/ok", "/gone"], "/ok").await.unwrap();
+        assert!(news.fallback.is_none());
+
+        let news = aggregate(["/gone", "/gone"], "/down").await.unwrap();
+        assert!(matches!(news.fallback.as_ref().unwrap().outcome, Err(FetchError::Status(404))));
+        let text = crate::format::render_blocks(&news, &crate::format::RenderOptions::default()).concat();
+        assert!(text.contains("fallback:</i>\n"), "{}", text);
+    }
+
+    #[test]
+    fn every_fallback_is_a_google_news_feed_of_its_category() {
+        for category in Category::ALL {
+            if let Some(fallback) = fallback_for(category) {
+                assert_eq!(fallback.category, category);
+                assert!(fallback.url.starts_with("https://news.google.com/rss/"), "{}", fallback.url);
+            }
+        }
+        assert_eq!(fallback_for(Category::War).unwrap().name, "GoogleNews War");
+    }
+
+    #[tokio::test]
+    async fn disabled_sources_are_skipped_and_reported() {
+        let source = SOURCES.iter().rev().find(|s| s.extractor.is_none()).unwrap();
+        let engine = NewsEngine::new().unwrap();
+        assert!(engine.set_paused(source, true));
+        assert!(!engine.set_paused(source, true));
+        // Persisted: a restarted engine still has it off
+        assert!(NewsEngine::new().unwrap().is_paused(source));
+
+        let news = fetch_target(Arc::clone(&engine), Target::Source(source.name), FetchOptions::default()).result().await.unwrap();
+        assert!(news.results.is_empty());
+        assert_eq!(news.paused.iter().map(|s| s.name).collect::<Vec<_>>(), [source.name]);
+        assert!(build_summary(&news).contains(&format!("⏸ {} (disabled)", source.name)));
+
+        assert!(engine.set_paused(source, false));
+        assert!(!engine.set_paused(source, false));
+        assert!(!NewsEngine::new().unwrap().is_paused(source));
+    }
+
+    #[tokio::test]
+    async fn private_feeds_are_added_with_their_auth_spec() {
+        use crate::network::EngineConfig;
+        use crate::store::MemoryStore;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        const RSS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0"><channel><title>Paywall Wire</title>
+<item><title>Central bank holds rates steady amid inflation worries</title><link>https://example.com/a</link></item>
+</channel></rss>"#;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/private.xml", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut head = [0; 2048];
+            let n = socket.read(&mut head).await.unwrap();
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", RSS.len(), RSS);
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&head[..n]).into_owned()
+        });
+        let secret = |name: &str| (name == "PAYWALL_TOKEN").then(|| "tok-789".to_string());
+        let engine = NewsEngine::with_config(EngineConfig { secret, ..EngineConfig::from_env() }).unwrap();
+        let store = MemoryStore::default();
+
+        let refused = add_custom_source(&engine, &store, "PaywallWire", &url, Some("bearer:paywall_token")).await;
+        assert!(matches!(refused, Err(AddSourceError::InvalidAuth)), "{:?}", refused.map(|s| s.name));
+
+        let source = add_custom_source(&engine, &store, "PaywallWire", &url, Some("bearer:PAYWALL_TOKEN")).await.unwrap();
+        assert_eq!(source.auth, Some(Auth::Bearer { token_env: "PAYWALL_TOKEN" }));
+        assert!(server.await.unwrap().contains("authorization: Bearer tok-789"));
+        // Only the env var name is stored, never the token
+        assert_eq!(store.custom_sources().unwrap()[0].auth.as_deref(), Some("bearer:PAYWALL_TOKEN"));
+    }
+
+    type SpanFields = (&'static str, std::collections::BTreeMap<String, String>);
+
+    /// Name and fields of every span opened while installed, by span id
+    #[derive(Clone, Default)]
+    struct SpanRecorder(Arc<std::sync::Mutex<std::collections::HashMap<u64, SpanFields>>>);
+
+    struct Fields<'a>(&'a mut std::collections::BTreeMap<String, String>);
+
+    impl tracing::field::Visit for Fields<'_> {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanRecorder {
+        fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, id: &tracing::span::Id, _: tracing_subscriber::layer::Context<'_, S>) {
+            let mut fields = Default::default();
+            attrs.record(&mut Fields(&mut fields));
+            self.0.lock().unwrap().insert(id.into_u64(), (attrs.metadata().name(), fields));
+        }
+
+        fn on_record(&self, id: &tracing::span::Id, values: &tracing::span::Record<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+            if let Some((_, fields)) = self.0.lock().unwrap().get_mut(&id.into_u64()) {
+                values.record(&mut Fields(fields));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn source_fetch_span_carries_its_fields() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        const RSS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0"><channel><title>Mock Wire</title>
+<item><title>Central bank holds rates steady amid inflation worries</title><link>https://example.com/a</link></item>
+<item><title>Oil prices climb after supply cuts are extended again</title><link>https://example.com/b</link></item>
+</channel></rss>"#;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/feed", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.read(&mut [0; 2048]).await;
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", RSS.len(), RSS);
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        let source: &'static Source = Box::leak(Box::new(Source::new("Mock Wire", Box::leak(url.into_boxed_str()), SourceType::Rss, Category::Global, "en")));
+
+        let recorder = SpanRecorder::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+        let options = FetchOptions { lang: Some("en"), ..FetchOptions::default() };
+        let result = fetch_source(&NewsEngine::new().unwrap(), source, options).await;
+        assert_eq!(result.outcome.unwrap().len(), 2);
+
+        let spans = recorder.0.lock().unwrap();
+        let (_, fields) = spans.values().find(|(name, _)| *name == "fetch_source").expect("no fetch_source span");
+        assert_eq!(fields.get("source").map(String::as_str), Some("Mock Wire"));
+        assert_eq!(fields.get("items").map(String::as_str), Some("2"));
+        assert_eq!(fields.get("cache_hit").map(String::as_str), Some("false"));
+        assert!(fields.get("ms").is_some_and(|ms| ms.parse::<u64>().is_ok()), "{:?}", fields);
+    }
+}
@@ -1,106 +1,974 @@
 //! LOGOS - High-performance Telegram News Aggregator
 
+mod alerts;
+mod budget;
+mod buttons;
+mod circuit;
+mod config;
 mod consts;
+mod format;
+mod health;
+mod inline;
+mod itemlog;
 mod logic;
+mod metrics;
 mod network;
+mod outgoing;
+mod state;
+mod storage;
+mod store;
+mod subscriptions;
+#[cfg(feature = "telemetry")]
+mod telemetry;
 mod utils;
 mod translate;
 
-use crate::logic::{build_help_message, build_summary, fetch_target, routes, Target};
-use crate::network::NewsEngine;
+use crate::alerts::Bursts;
+use crate::config::Config;
+use crate::consts::{find_source, limits, SOURCES};
+use crate::format::{escape_html, format_fetch_report, format_sources_report, format_status_report, render_blocks, shown_fingerprints, RenderOptions};
+use crate::logic::{add_custom_source, build_footer, build_help_message, build_summary, fetch_target, restore_custom_sources, routes, AggregatedNews, AggregationHandle, FetchOptions, Target};
+use crate::network::{redact_proxy, NewsEngine};
+use crate::translate::{supported_language, LANGUAGES};
+use crate::utils::truncate_text;
+use crate::outgoing::{edit_chunk, fits_message, plan_blocks, send_plan, OutgoingChunk, SendOptions};
+use crate::itemlog::ItemLog;
+use crate::store::{SqliteStore, Store};
+use crate::buttons::{Action, ButtonData};
+use crate::state::{Admins, AppState, ChatSettings, CommandStats, HandledMessages, Maintenance, Profile, SeenItems, Settings, SettingsError};
+use chrono::{NaiveTime, Utc};
 use std::sync::Arc;
 use std::env;
+use std::time::{Duration, Instant};
+use teloxide::dispatching::{ShutdownToken, UpdateFilterExt, UpdateHandler};
 use teloxide::prelude::*;
-use teloxide::types::ParseMode;
+use teloxide::types::{BotCommand, InlineKeyboardMarkup, InlineQueryResult, Me, MessageId, ParseMode};
+use teloxide::{ApiError, RequestError};
 use teloxide::utils::command::BotCommands;
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
 
 #[derive(BotCommands, Clone, Debug)]
 #[command(rename_rule = "lowercase", description = "Available commands:")]
 enum Command {
     #[command(description = "Show help message")]
-    Start,
+    Start(String),
     #[command(description = "Show help message")]
     Help,
+    #[command(description = "Toggle per-source fetch timings (admins)")]
+    Diagnostics,
+    #[command(description = "Show usage and API budgets (admins)")]
+    Stats,
+    #[command(description = "Show each source's last fetch status")]
+    Sources,
+    #[command(description = "🩺 Per-source health, latency and cache age")]
+    Status,
+    #[command(description = "🌐 Translation language: /lang en")]
+    Lang(String),
+    #[command(description = "⚙️ Chat settings: /settings items 8, /settings tz +3")]
+    Settings(String),
+    #[command(description = "🔔 Get new items pushed: /subscribe war 60")]
+    Subscribe(String),
+    #[command(description = "🔕 Stop a subscription: /unsubscribe war|all")]
+    Unsubscribe(String),
+    #[command(description = "🔔 List this chat's subscriptions")]
+    Subscriptions,
+    #[command(description = "👤 Subscription sets: /profile add night war,liveuamap 22:00, /profile use night")]
+    Profile(String),
+    #[command(description = "📰 The active profile's first command")]
+    News(String),
+    #[command(description = "💰 Alert on a price or keyword: /alert gold above 2700, /alert war strike")]
+    Alert(String),
+    #[command(description = "💰 List this chat's alerts")]
+    Alerts,
+    #[command(description = "💰 Remove an alert: /unalert 1|all")]
+    Unalert(String),
+    #[command(description = "🆕 Forget which items this chat has seen")]
+    Reset,
+    #[command(description = "🌐 Reply to a news message to translate it: /translate en")]
+    Translate(String),
+    #[command(description = "Pause user traffic: /maintenance on|off (admins)")]
+    Maintenance(String),
+    #[command(rename = "admin_config", description = "Show effective configuration (admins)")]
+    AdminConfig,
+    #[command(description = "Follow an RSS feed: /addsource <name> <url> [auth] (admins)")]
+    AddSource(String),
+    #[command(description = "Post a digest to CHANNEL_ID: /post war [silent] (admins)")]
+    Post(String),
+    #[command(rename = "disable_source", description = "Skip a source until re-enabled: /disable_source tass (admins)")]
+    DisableSource(String),
+    #[command(rename = "enable_source", description = "Fetch a disabled source again (admins)")]
+    EnableSource(String),
 
     // Category commands
     #[command(description = "🖤 Global news")]
-    Global,
+    Global(String),
     #[command(description = "🤍 War updates")]
-    War,
+    War(String),
+    #[command(description = "🇺🇦 Ukrainian war sources")]
+    Ukraine(String),
     #[command(description = "🏴 Market news")]
-    Market,
+    Market(String),
     #[command(description = "✟ Ancient Dust")]
-    Commodities,
+    Commodities(String),
+    #[command(description = "🕒 Newest items of a category: /latest war")]
+    Latest(String),
+    #[command(description = "🔎 Search all sources: /search oil price")]
+    Search(String),
 
-    // Individual sources
-    #[command(description = "Reuters NewsData")]
-    Reuters,
-    #[command(description = "Yahoo Politics")]
-    Yahoo,
-    #[command(description = "Gold price")]
-    Gold,
-    #[command(description = "Oil price")]
-    Oil,
+    /// A single registered source: "/source reuters", or bare "/reuters" via parse_source_command
+    #[command(hide)]
+    Source(String),
 }
 
 impl Command {
     fn to_target(&self) -> Option<Target> {
         let cmd_str = match self {
-            Command::Start | Command::Help => return None,
-            Command::Global => "global",
-            Command::War => "war",
-            Command::Market => "market",
-            Command::Commodities => "commodities",
-            Command::Reuters => "reuters",
-            Command::Yahoo => "yahoopolitics", // Updated mapping
-            Command::Gold => "gold",
-            Command::Oil => "oil",
+            // Deep links from inline mode carry the command as the start parameter
+            Command::Start(param) => param.trim(),
+            Command::Help | Command::Diagnostics | Command::Stats | Command::Sources | Command::Status | Command::Lang(_)
+            | Command::Settings(_) | Command::Subscribe(_) | Command::Unsubscribe(_) | Command::Subscriptions
+            | Command::Profile(_) | Command::News(_) | Command::Alert(_) | Command::Alerts | Command::Unalert(_) | Command::Reset | Command::Translate(_) | Command::Maintenance(_) | Command::AdminConfig | Command::AddSource(_) | Command::Post(_)
+            | Command::DisableSource(_) | Command::EnableSource(_) => return None,
+            Command::Global(_) => "global",
+            Command::War(_) => "war",
+            Command::Ukraine(_) => "ukraine",
+            Command::Market(_) => "market",
+            Command::Commodities(_) => "commodities",
+            Command::Latest(arg) => return routes::resolve_latest(arg),
+            Command::Search(query) => {
+                let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+                return (!query.is_empty()).then_some(Target::Search(query));
+            }
+            Command::Source(line) => line.split_whitespace().next().unwrap_or_default(),
         };
         routes::resolve_command(cmd_str)
     }
+
+    /// Lowercase command name for usage counters
+    fn name(&self) -> String {
+        if let Command::Source(line) = self {
+            return line.split_whitespace().next().unwrap_or_default().to_lowercase();
+        }
+        let debug = format!("{:?}", self);
+        debug.split('(').next().unwrap_or_default().to_lowercase()
+    }
+
+    /// Operator commands, refused to everyone outside ADMIN_IDS
+    fn admin_only(&self) -> bool {
+        matches!(
+            self,
+            Command::Diagnostics | Command::Stats | Command::Maintenance(_) | Command::AdminConfig | Command::AddSource(_)
+            | Command::Post(_) | Command::DisableSource(_) | Command::EnableSource(_)
+        )
+    }
+
+    /// Free-form arguments after the command, e.g. "popular"
+    fn args(&self) -> &str {
+        match self {
+            Command::Start(_) | Command::Help | Command::Diagnostics | Command::Stats | Command::Sources | Command::Status
+            | Command::Lang(_) | Command::Settings(_) | Command::Subscribe(_) | Command::Unsubscribe(_)
+            | Command::Subscriptions | Command::Profile(_) | Command::Alert(_) | Command::Alerts | Command::Unalert(_) | Command::Reset
+            | Command::Translate(_) | Command::Maintenance(_) | Command::AdminConfig | Command::AddSource(_) | Command::Post(_)
+            | Command::DisableSource(_) | Command::EnableSource(_) | Command::Search(_) => "",
+            Command::Global(a) | Command::War(a) | Command::Ukraine(a) | Command::Market(a)
+            | Command::Commodities(a) | Command::News(a) => a,
+            // The first word names the category
+            Command::Latest(line) => line.trim().split_once(char::is_whitespace).map_or("", |(_, a)| a),
+            Command::Source(line) => line.trim().split_once(char::is_whitespace).map_or("", |(_, a)| a),
+        }
+    }
+}
+
+/// Read "/reuters popular" (or "/reuters@bot") as a command for any registered source
+fn parse_source_command(msg: Message, me: Me) -> Option<Command> {
+    let text = msg.text()?.strip_prefix('/')?;
+    let (head, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let name = match head.split_once('@') {
+        Some((name, bot)) if bot.eq_ignore_ascii_case(me.username()) => name,
+        Some(_) => return None,
+        None => head,
+    };
+    let source = find_source(name)?;
+    Some(Command::Source(format!("{} {}", source.command(), args.trim())))
+}
+
+/// Whether a name is one of the fixed bot commands, hidden ones included
+fn is_command_name(name: &str) -> bool {
+    name.eq_ignore_ascii_case("source")
+        || Command::bot_commands()
+            .iter()
+            .any(|c| c.command.trim_start_matches('/').eq_ignore_ascii_case(name))
+}
+
+/// Fixed commands plus one entry per registered source, for the Telegram menu
+fn menu_commands() -> Vec<BotCommand> {
+    let mut commands = Command::bot_commands();
+    commands.extend(
+        SOURCES
+            .iter()
+            .map(|s| BotCommand::new(s.command(), format!("{} · {}", s.name, s.category))),
+    );
+    commands
 }
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
 
-    pretty_env_logger::formatted_builder()
-        .filter_level(log::LevelFilter::Info)
+    // `log` records are bridged in, so they carry the command and fetch spans they ran in
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
         .init();
 
     log::info!("═══════════════════════════════════════════");
     log::info!("  LOGOS SYSTEM ONLINE. FILTERING AETHER...");
     log::info!("═══════════════════════════════════════════");
 
-    let token = env::var("TELOXIDE_TOKEN").expect("TELOXIDE_TOKEN not found!");
+    let admins = Arc::new(Admins::from_env());
+    let config = Arc::new(Config::from_env(admins.count()));
+    for line in config.to_string().lines() {
+        log::info!("  {}", line);
+    }
+
+    let store: Arc<dyn Store> = match SqliteStore::open_default() {
+        Ok(store) => Arc::new(store),
+        Err(e) => {
+            log::error!("Can't open the database: {}", e);
+            std::process::exit(1);
+        }
+    };
+    restore_custom_sources(store.as_ref());
+
+    let engine = match NewsEngine::new() {
+        Ok(engine) => engine,
+        Err(e) => {
+            let proxy = config.proxy.as_deref().map_or_else(|| "direct".to_string(), redact_proxy);
+            log::error!("Can't build the HTTP client (proxy: {}): {}", proxy, e);
+            std::process::exit(1);
+        }
+    };
+
+    let Ok(token) = env::var("TELOXIDE_TOKEN") else {
+        log::error!("TELOXIDE_TOKEN not found!");
+        std::process::exit(1);
+    };
     let bot = Bot::new(token);
-    let engine = NewsEngine::new();
+    match validate_token(&bot).await {
+        Ok(me) => log::info!("Authorized as @{}", me.username()),
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    // Populate Telegram's command menu; a failure here only loses autocomplete
+    if let Err(e) = bot.set_my_commands(menu_commands()).await {
+        log::warn!("Failed to register bot commands: {}", e);
+    }
+
+    let state = AppState {
+        engine,
+        handled: Arc::new(HandledMessages::default()),
+        admins,
+        config,
+        stats: Arc::new(CommandStats::default()),
+        item_log: ItemLog::from_env(),
+        maintenance: Arc::new(Maintenance::load()),
+        settings: Arc::new(ChatSettings::load(Arc::clone(&store))),
+        store,
+        seen: Arc::new(SeenItems::load()),
+        bursts: Arc::new(Bursts::new(limits::burst_window())),
+    };
+
+    #[cfg(feature = "telemetry")]
+    if let Some(url) = state.config.telemetry_url.clone() {
+        telemetry::spawn(url, Arc::clone(&state.stats));
+    }
+
+    if let Some(alerts) = state.engine.take_alerts() {
+        tokio::spawn(notify_admins(bot.clone(), Arc::clone(&state.admins), alerts));
+    }
+    tokio::spawn(subscriptions::run(bot.clone(), state.clone()));
+    tokio::spawn(subscriptions::run_digests(bot.clone(), state.clone()));
+    tokio::spawn(alerts::run(bot.clone(), state.clone()));
+    tokio::spawn(alerts::run_keywords(bot.clone(), state.clone()));
+    tokio::spawn(alerts::run_bursts(bot.clone(), Arc::clone(&state.bursts)));
+    tokio::spawn(Arc::clone(&state.seen).run_flush());
+    let seen = Arc::clone(&state.seen);
+    let (bursts, follow_up_bot) = (Arc::clone(&state.bursts), bot.clone());
+    if let Some(port) = state.config.health_port {
+        tokio::spawn(health::run(port, Arc::clone(&state.engine), Arc::clone(&state.maintenance)));
+    }
+
+    let mut dispatcher = Dispatcher::builder(bot, schema())
+        .dependencies(dptree::deps![state])
+        .default_handler(|_| async {})
+        .enable_ctrlc_handler()
+        .build();
+    tokio::spawn(shutdown_on_sigterm(dispatcher.shutdown_token()));
+    dispatcher.dispatch().await;
+    seen.flush().await;
+    alerts::flush_bursts(&follow_up_bot, &bursts).await;
+    log::info!("Stopped cleanly, in-flight updates finished");
+}
+
+/// Update routing: commands (typed or from a source name, new or edited), inline queries
+/// and button presses
+fn schema() -> UpdateHandler<RequestError> {
+    dptree::entry()
+        .branch(
+            Update::filter_message()
+                .filter_command::<Command>()
+                .endpoint(handle_command),
+        )
+        .branch(
+            Update::filter_edited_message()
+                .filter_command::<Command>()
+                .endpoint(handle_edited_command),
+        )
+        .branch(
+            Update::filter_message()
+                .filter_map(parse_source_command)
+                .endpoint(handle_command),
+        )
+        .branch(
+            Update::filter_edited_message()
+                .filter_map(parse_source_command)
+                .endpoint(handle_edited_command),
+        )
+        .branch(Update::filter_inline_query().endpoint(handle_inline_query))
+        .branch(Update::filter_callback_query().endpoint(handle_callback_query))
+}
+
+/// Stop taking updates on SIGTERM (docker stop, systemd); the dispatcher lets handlers
+/// already running finish their fetches before `dispatch` returns. Ctrl-C does the same.
+async fn shutdown_on_sigterm(token: ShutdownToken) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let Ok(mut term) = signal(SignalKind::terminate()) else {
+            log::warn!("Can't listen for SIGTERM, only Ctrl-C stops the bot gracefully");
+            return;
+        };
+        term.recv().await;
+        log::info!("SIGTERM received, finishing in-flight updates");
+        if let Ok(done) = token.shutdown() {
+            done.await;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = token;
+}
+
+/// Forward engine alerts to every admin's private chat
+async fn notify_admins(bot: Bot, admins: Arc<Admins>, mut alerts: mpsc::UnboundedReceiver<String>) {
+    while let Some(alert) = alerts.recv().await {
+        for admin in admins.ids() {
+            if let Err(e) = bot.send_message(admin, &alert).await {
+                log::warn!("Failed to notify admin {}: {}", admin, e);
+            }
+        }
+    }
+}
+
+/// Attempts at reaching Telegram before giving up on startup
+const TOKEN_CHECK_ATTEMPTS: u32 = 3;
+
+/// How a failed get_me call at startup is treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenCheckError {
+    /// Telegram answered 401: the token is revoked or mistyped
+    Rejected,
+    /// Network trouble or flood control, worth retrying
+    Transient,
+    /// Anything else, reported as-is
+    Fatal,
+}
+
+fn classify_get_me_error(err: &RequestError) -> TokenCheckError {
+    match err {
+        RequestError::Api(ApiError::InvalidToken) => TokenCheckError::Rejected,
+        RequestError::Network(_) | RequestError::RetryAfter(_) | RequestError::Io(_) => TokenCheckError::Transient,
+        _ => TokenCheckError::Fatal,
+    }
+}
 
-    Command::repl(bot, move |bot: Bot, msg: Message, cmd: Command| {
-        let engine = Arc::clone(&engine);
-        async move {
-            handle_command(bot, msg, cmd, engine).await
+/// Call get_me with bounded retries so a bad token fails fast and loudly
+async fn validate_token(bot: &Bot) -> Result<Me, String> {
+    let mut attempt = 1;
+    loop {
+        let err = match bot.get_me().await {
+            Ok(me) => return Ok(me),
+            Err(e) => e,
+        };
+        match classify_get_me_error(&err) {
+            TokenCheckError::Rejected => return Err("TELOXIDE_TOKEN rejected by Telegram (401 Unauthorized)".into()),
+            TokenCheckError::Transient if attempt < TOKEN_CHECK_ATTEMPTS => {
+                let wait = match &err {
+                    RequestError::RetryAfter(secs) => secs.duration(),
+                    _ => Duration::from_secs(2u64.pow(attempt)),
+                };
+                log::warn!("Telegram unreachable ({}), retrying in {:?}", err, wait);
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+            }
+            _ => return Err(format!("Token check failed: {}", err)),
         }
-    }).await;
+    }
+}
+
+/// Reply to non-admins when PRIVATE_MODE=1
+const PRIVATE_REPLY: &str = "🔒 This bot is private";
+
+/// Why a command is turned away before it runs
+#[derive(Debug, PartialEq, Eq)]
+enum Refusal {
+    Maintenance,
+    Private,
+    AdminOnly,
+}
+
+/// Admins are never refused. Maintenance turns away every other command, private mode
+/// all but /help and a bare /start, and operator commands need an admin.
+fn refusal(cmd: &Command, is_admin: bool, maintenance: bool, private_mode: bool) -> Option<Refusal> {
+    if is_admin {
+        return None;
+    }
+    if maintenance {
+        return Some(Refusal::Maintenance);
+    }
+    let public = matches!(cmd, Command::Help) || matches!(cmd, Command::Start(p) if p.trim().is_empty());
+    if private_mode && !public {
+        return Some(Refusal::Private);
+    }
+    cmd.admin_only().then_some(Refusal::AdminOnly)
 }
 
-async fn handle_command(
-    bot: Bot,
-    msg: Message,
-    cmd: Command,
-    engine: Arc<NewsEngine>,
-) -> ResponseResult<()> {
+/// Edits older than this are not re-parsed as commands
+const EDIT_WINDOW: Duration = Duration::from_secs(60);
+
+async fn handle_command(bot: Bot, msg: Message, cmd: Command, state: AppState) -> ResponseResult<()> {
+    let span = command_span(&msg, &cmd);
+    run_command(bot, msg, cmd, state, false).instrument(span).await
+}
+
+/// Span tying a command's log lines together under a short random correlation id
+fn command_span(msg: &Message, cmd: &Command) -> tracing::Span {
+    let id = format!("{:08x}", rand::random::<u32>());
+    tracing::info_span!("command", id = %id, cmd = %cmd.name(), chat = msg.chat.id.0)
+}
+
+/// Re-run a command fixed by editing, unless it is stale or already served
+async fn handle_edited_command(bot: Bot, msg: Message, cmd: Command, state: AppState) -> ResponseResult<()> {
+    let within_window = msg
+        .edit_date()
+        .and_then(|edited| (*edited - msg.date).to_std().ok())
+        .is_some_and(|delay| delay <= EDIT_WINDOW);
+
+    if !within_window || state.handled.contains(msg.chat.id, msg.id) {
+        return Ok(());
+    }
+    let span = command_span(&msg, &cmd);
+    run_command(bot, msg, cmd, state, true).instrument(span).await
+}
+
+/// Minimum gap between loading-message progress edits, to stay clear of flood limits
+const PROGRESS_EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// Edit the loading message as sources finish, then hand back the result
+async fn await_with_progress(
+    bot: &Bot,
+    chat_id: ChatId,
+    loading_id: MessageId,
+    label: &str,
+    handle: AggregationHandle,
+) -> Option<AggregatedNews> {
+    let mut progress = handle.progress.clone();
+    let mut last_edit = Instant::now();
+    let result = handle.result();
+    tokio::pin!(result);
+    loop {
+        tokio::select! {
+            news = &mut result => return news,
+            changed = progress.changed() => {
+                if changed.is_err() {
+                    return result.await;
+                }
+                if last_edit.elapsed() < PROGRESS_EDIT_INTERVAL {
+                    continue;
+                }
+                let (finished, total) = {
+                    let p = progress.borrow_and_update();
+                    (p.finished(), p.total())
+                };
+                let text = format!("⏳ Fetching {}... {}/{}", label, finished, total);
+                let _ = bot.edit_message_text(chat_id, loading_id, text).await;
+                last_edit = Instant::now();
+            }
+        }
+    }
+}
+
+/// Answer "@bot war" with article results; the first word picks the target
+async fn handle_inline_query(bot: Bot, query: InlineQuery, state: AppState) -> ResponseResult<()> {
+    let (command, args) = query.query.trim().split_once(' ').unwrap_or((query.query.trim(), ""));
+    let is_admin = state.admins.is_admin(Some(query.from.id));
+    let target = match routes::resolve_command(command) {
+        Some(t) if is_admin || (!state.maintenance.is_on() && !state.config.private_mode) => t,
+        _ => {
+            bot.answer_inline_query(query.id, Vec::<InlineQueryResult>::new()).cache_time(0).await?;
+            return Ok(());
+        }
+    };
+    state.stats.record("inline");
+
+    // The user's private chat holds their /settings
+    let settings = state.settings.get(ChatId(query.from.id.0 as i64));
+    let options = fetch_options(args, is_admin, &settings);
+    // Telegram drops slow inline answers, so a late aggregation is cancelled outright
+    let handle = fetch_target(Arc::clone(&state.engine), target, options);
+    let Some(result) = handle.result_within(Duration::from_secs(limits::INLINE_ANSWER_SECS)).await else {
+        return Ok(());
+    };
+    let (results, has_more) = inline::build_results(&result, &settings.render_options());
+
+    // Answers shaped by one user's settings must not be served to everyone else
+    let mut answer = bot
+        .answer_inline_query(query.id, results)
+        .cache_time(limits::INLINE_CACHE_SECS)
+        .is_personal(settings.is_personalized());
+    if has_more {
+        answer = answer.button(inline::full_output_button(&command.to_lowercase()));
+    }
+    answer.await?;
+    Ok(())
+}
+
+/// Options for a fetch: explicit command arguments first, then the chat's settings
+fn fetch_options(args: &str, privileged: bool, settings: &Settings) -> FetchOptions {
+    let options = FetchOptions::parse(args);
+    FetchOptions {
+        privileged,
+        lang: settings.lang(),
+        max_items: options.max_items.or(settings.items),
+        ..options
+    }
+}
+
+/// Answer to "/profile add|use|remove|list|status"
+fn profile_reply(state: &AppState, chat_id: ChatId, arg: &str) -> String {
+    const USAGE: &str = "Usage: /profile add <name> <command,command> [HH:MM] | use <name> | remove <name> | list";
+    let words: Vec<String> = arg.split_whitespace().map(str::to_lowercase).collect();
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let result = match words[..] {
+        [] | ["list"] | ["status"] => {
+            let settings = state.settings.get(chat_id);
+            if settings.profiles.is_empty() {
+                return "No profiles. Add one with /profile add night war,liveuamap 22:00".to_string();
+            }
+            let active = settings.active_profile.as_deref();
+            let mut lines = vec![format!("👤 Profiles, times in UTC{}", settings.render_options().offset)];
+            lines.extend(
+                settings
+                    .profiles
+                    .iter()
+                    .map(|p| format!("{} {}", if Some(p.name.as_str()) == active { "▶️" } else { "·" }, p)),
+            );
+            if let Some(command) = settings.default_command() {
+                lines.push(format!("/news shows /{}", command));
+            }
+            return lines.join("
+");
+        }
+        ["add", name, commands] | ["add", name, commands, _] => {
+            let commands: Vec<String> = commands.split(',').filter(|c| !c.is_empty()).map(str::to_string).collect();
+            if let Some(unknown) = commands.iter().find(|c| routes::resolve_command(c).is_none()) {
+                return format!("❌ /{} is not a news command", unknown);
+            }
+            let at = match words.get(3) {
+                Some(time) => match NaiveTime::parse_from_str(time, "%H:%M") {
+                    Ok(at) => Some(at),
+                    Err(_) => return "❌ Time must be HH:MM, e.g. 22:00".to_string(),
+                },
+                None => None,
+            };
+            let profile = Profile { name: name.to_string(), commands, at };
+            let summary = profile.to_string();
+            state
+                .settings
+                .update(chat_id, |s| s.add_profile(profile))
+                .map(|_| format!("👤 Profile {}. Switch with /profile use {}", summary, name))
+        }
+        ["use", name] => state
+            .settings
+            .update(chat_id, |s| s.switch_profile(name, Utc::now()))
+            .map(|_| format!("🔁 Profile {} is on until its next scheduled switch, see /subscriptions", name)),
+        ["remove", name] => {
+            let mut removed = false;
+            let _ = state.settings.update(chat_id, |s| {
+                removed = s.remove_profile(name);
+                Ok::<_, SettingsError>(())
+            });
+            Ok(match removed {
+                true => format!("🗑 Profile {} removed, its subscriptions stay", name),
+                false => format!("❌ No profile named \"{}\"", name),
+            })
+        }
+        _ => Ok(USAGE.to_string()),
+    };
+    result.unwrap_or_else(|e| format!("❌ {}", e))
+}
+
+/// Plain-text translation of the bot message `msg` replies to, or a usage hint.
+/// `text()` carries no entity markup, so the reply can't contain broken tags.
+async fn translate_reply(bot: &Bot, msg: &Message, arg: &str, state: &AppState) -> ResponseResult<String> {
+    const USAGE: &str = "Reply to one of my messages with /translate [language], e.g. /translate en";
+    let target = match arg {
+        "" => state.settings.get(msg.chat.id).lang().unwrap_or_else(limits::translate_target),
+        code => match supported_language(code) {
+            Some(lang) => lang,
+            None => return Ok(format!("❌ Unknown language \"{}\". Available: {}", code, LANGUAGES.join(", "))),
+        },
+    };
+    let Some(original) = msg.reply_to_message() else { return Ok(USAGE.to_string()) };
+    let me = bot.get_me().await?;
+    let from_me = original.from.as_ref().is_some_and(|u| u.id == me.id);
+    let Some(text) = original.text().or(original.caption()).filter(|t| from_me && !t.trim().is_empty()) else {
+        return Ok(USAGE.to_string());
+    };
+    Ok(match state.engine.translate(text, target).await {
+        Ok(translated) => truncate_text(&translated, limits::TELEGRAM_MESSAGE_LEN - 3),
+        Err(e) => format!("❌ Translation failed: {}", e),
+    })
+}
+
+async fn run_command(bot: Bot, msg: Message, cmd: Command, state: AppState, from_edit: bool) -> ResponseResult<()> {
     let chat_id = msg.chat.id;
+    state.stats.record(&cmd.name());
+    let is_admin = state.admins.is_admin(msg.from.as_ref().map(|u| u.id));
 
-    if matches!(cmd, Command::Start | Command::Help) {
-        bot.send_message(chat_id, build_help_message())
-            .parse_mode(ParseMode::Markdown)
-            .await?;
+    if let Some(refusal) = refusal(&cmd, is_admin, state.maintenance.is_on(), state.config.private_mode) {
+        let reply = match refusal {
+            Refusal::Maintenance => state.maintenance.message.as_str(),
+            Refusal::Private => PRIVATE_REPLY,
+            Refusal::AdminOnly => "⛔ Admins only",
+        };
+        bot.send_message(chat_id, reply).await?;
         return Ok(());
     }
 
-    let target = match cmd.to_target() {
+    match cmd {
+        Command::Diagnostics => {
+            let on = state.admins.toggle_diagnostics(chat_id);
+            bot.send_message(chat_id, format!("⏱ Fetch report {}", if on { "on" } else { "off" })).await?;
+            return Ok(());
+        }
+        Command::AdminConfig => {
+            bot.send_message(chat_id, format!("<pre>{}</pre>", escape_html(&state.config.to_string())))
+                .parse_mode(ParseMode::Html)
+                .await?;
+            return Ok(());
+        }
+        Command::Stats => {
+            let budget = &state.engine.newsdata_budget;
+            let commands: Vec<String> = state
+                .stats
+                .snapshot()
+                .into_iter()
+                .map(|(cmd, n)| format!("/{} {}", cmd, n))
+                .collect();
+            let text = format!(
+                "📊 NewsData budget: {}/{} left, resets in {}h\nCommands: {}",
+                budget.remaining(),
+                budget.limit(),
+                budget.resets_in().as_secs().div_ceil(3600),
+                commands.join(" · ")
+            );
+            let text = if state.maintenance.is_on() { format!("{}\n🛠 Maintenance mode on", text) } else { text };
+            let text = match state.engine.warmup_remaining() {
+                Some(left) => format!("{}\n🔥 Warming up, {}s of paced fetching left", text, left.as_secs()),
+                None => text,
+            };
+            let (hits, misses) = state.engine.translations.hit_miss();
+            let text = match hits + misses {
+                0 => text,
+                n => format!("{}\nTranslation cache: {}/{} hits", text, hits, n),
+            };
+            let text = match &state.item_log {
+                Some(item_log) => format!("{}\nItem log dropped: {}", text, item_log.dropped()),
+                None => text,
+            };
+            bot.send_message(chat_id, text).await?;
+            return Ok(());
+        }
+        Command::Lang(arg) => {
+            let reply = match arg.trim() {
+                "" => format!(
+                    "🌐 Translating into {}. Change with /lang <code>",
+                    state.settings.get(chat_id).lang().unwrap_or_else(limits::translate_target)
+                ),
+                code => match state.settings.update(chat_id, |s| s.set("lang", code)) {
+                    Ok(settings) => format!("✅ Translating into {}", settings.lang().unwrap_or_else(limits::translate_target)),
+                    Err(e) => format!("❌ {}", e),
+                },
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Settings(arg) => {
+            let reply = match arg.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => format!("⚙️ Settings\n{}", state.settings.get(chat_id)),
+                ["reset"] => {
+                    // Subscriptions, digests, alerts and profiles have their own commands and survive a reset
+                    let _ = state.settings.update(chat_id, |s| {
+                        *s = Settings {
+                            subscriptions: std::mem::take(&mut s.subscriptions),
+                            digests: std::mem::take(&mut s.digests),
+                            alerts: std::mem::take(&mut s.alerts),
+                            keyword_alerts: std::mem::take(&mut s.keyword_alerts),
+                            profiles: std::mem::take(&mut s.profiles),
+                            active_profile: s.active_profile.take(),
+                            profile_switched: s.profile_switched.take(),
+                            ..Settings::default()
+                        };
+                        Ok::<_, SettingsError>(())
+                    });
+                    format!("✅ Back to defaults\n{}", Settings::default())
+                }
+                [key, value] => match state.settings.update(chat_id, |s| s.set(key, value)) {
+                    Ok(settings) => format!("✅ Saved\n{}", settings),
+                    Err(e) => format!("❌ {}", e),
+                },
+                _ => "Usage: /settings [lang <code> | items <n> | desc on|off | tz <+3> | reset]".to_string(),
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Subscribe(arg) => {
+            let words: Vec<&str> = arg.split_whitespace().collect();
+            let reply = match words[..] {
+                [command] | [command, _] if routes::resolve_command(command).is_none() => {
+                    format!("❌ /{} is not a news command", command.to_lowercase())
+                }
+                [command, time] if time.contains(':') => {
+                    let command = command.to_lowercase();
+                    match NaiveTime::parse_from_str(time, "%H:%M") {
+                        Ok(at) => match state.settings.update(chat_id, |s| s.add_digest(&command, at)) {
+                            Ok(s) => format!(
+                                "📰 Daily /{} at {} (UTC{}), change the timezone with /settings tz",
+                                command,
+                                at.format("%H:%M"),
+                                s.render_options().offset
+                            ),
+                            Err(e) => format!("❌ {}", e),
+                        },
+                        Err(_) => "❌ Time must be HH:MM, e.g. 08:00".to_string(),
+                    }
+                }
+                [command] | [command, _] => {
+                    let command = command.to_lowercase();
+                    let minutes = match words.get(1) {
+                        Some(m) => m.parse().unwrap_or(0),
+                        None => limits::SUBSCRIPTION_DEFAULT_MINUTES,
+                    };
+                    match state.settings.update(chat_id, |s| s.subscribe(&command, minutes)) {
+                        Ok(_) => format!("🔔 Subscribed to /{} every {} min, only new items are sent", command, minutes),
+                        Err(e) => format!("❌ {}", e),
+                    }
+                }
+                _ => "Usage: /subscribe <command> [minutes|HH:MM], e.g. /subscribe war 60 or /subscribe market 08:00".to_string(),
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Unsubscribe(arg) => {
+            let command = arg.trim().to_lowercase();
+            let mut removed = false;
+            if !command.is_empty() {
+                let _ = state.settings.update(chat_id, |s| {
+                    removed = s.unsubscribe(&command);
+                    Ok::<_, SettingsError>(())
+                });
+            }
+            let reply = match (removed, command.as_str()) {
+                (_, "") => "Usage: /unsubscribe <command>|all".to_string(),
+                (true, "all") => "🔕 All subscriptions removed".to_string(),
+                (true, _) => format!("🔕 Unsubscribed from /{}", command),
+                (false, "all") => "No subscriptions".to_string(),
+                (false, _) => format!("No subscription to /{}", command),
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Subscriptions => {
+            let settings = state.settings.get(chat_id);
+            let offset = settings.render_options().offset;
+            let lines: Vec<String> = settings
+                .subscriptions
+                .iter()
+                .map(|s| format!("🔔 /{} every {} min", s.command, s.interval_minutes))
+                .chain(settings.digests.iter().map(|d| format!("📰 /{} daily at {} (UTC{})", d.command, d.at.format("%H:%M"), offset)))
+                .collect();
+            let reply = match lines.is_empty() {
+                true => "No subscriptions. Add one with /subscribe war 60 or /subscribe market 08:00".to_string(),
+                false => lines.join("\n"),
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Profile(arg) => {
+            bot.send_message(chat_id, profile_reply(&state, chat_id, &arg)).await?;
+            return Ok(());
+        }
+        Command::Alert(arg) => {
+            if let Some(alert) = alerts::parse_keyword_alert(&arg) {
+                let summary = alert.to_string();
+                let reply = match state.settings.update(chat_id, |s| s.add_keyword_alert(alert)) {
+                    Ok(_) => format!("💰 Alert set: {}. You'll hear about new items with these words", summary),
+                    Err(e) => format!("❌ {}", e),
+                };
+                bot.send_message(chat_id, reply).await?;
+                return Ok(());
+            }
+            let reply = match alerts::parse_alert(&arg) {
+                Ok(alert) => {
+                    let summary = format!("{} {} {}", alert.source, alert.direction, alert.threshold);
+                    match state.settings.update(chat_id, |s| s.add_alert(alert)) {
+                        Ok(_) => format!("💰 Alert set: {}. You'll hear when the price crosses it", summary),
+                        Err(e) => format!("❌ {}", e),
+                    }
+                }
+                Err(usage) => usage,
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Alerts => {
+            let alerts = state.settings.get(chat_id).alert_lines();
+            let reply = match alerts.is_empty() {
+                true => "No alerts. Add one with /alert gold above 2700 or /alert war strike".to_string(),
+                false => alerts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| format!("{}. {}", i + 1, a))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Unalert(arg) => {
+            let arg = arg.trim().to_lowercase();
+            let mut removed = false;
+            let _ = state.settings.update(chat_id, |s| {
+                match arg.as_str() {
+                    "all" => {
+                        removed = !s.alerts.is_empty() || !s.keyword_alerts.is_empty();
+                        s.alerts.clear();
+                        s.keyword_alerts.clear();
+                    }
+                    n => removed = n.parse().is_ok_and(|n| s.remove_alert(n)),
+                }
+                Ok::<_, SettingsError>(())
+            });
+            let reply = match removed {
+                true => "🗑 Alert removed, see /alerts".to_string(),
+                false => "Usage: /unalert <number from /alerts>|all".to_string(),
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Reset => {
+            state.seen.reset(chat_id);
+            bot.send_message(chat_id, "🆕 Forgotten: every item counts as new again").await?;
+            return Ok(());
+        }
+        Command::Translate(arg) => {
+            let reply = translate_reply(&bot, &msg, arg.trim(), &state).await?;
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Sources => {
+            bot.send_message(chat_id, format_sources_report(&state.engine))
+                .parse_mode(ParseMode::Html)
+                .await?;
+            return Ok(());
+        }
+        Command::Status => {
+            let cache_ages = state.engine.cache_ages().await;
+            bot.send_message(chat_id, format_status_report(&state.engine, &cache_ages))
+                .parse_mode(ParseMode::Html)
+                .await?;
+            return Ok(());
+        }
+        Command::Maintenance(arg) => {
+            let reply = match arg.trim() {
+                "on" => { state.maintenance.set(true); "🛠 Maintenance mode on" }
+                "off" => { state.maintenance.set(false); "✅ Maintenance mode off" }
+                _ if state.maintenance.is_on() => "🛠 Maintenance is on. Usage: /maintenance on|off",
+                _ => "Maintenance is off. Usage: /maintenance on|off",
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::AddSource(arg) => {
+            let args = arg.split_whitespace().collect::<Vec<_>>();
+            let reply = match args[..] {
+                [name, ..] if (2..=3).contains(&args.len()) && is_command_name(name) => format!("❌ /{} is already taken", name.to_lowercase()),
+                [name, url] | [name, url, _] => match add_custom_source(&state.engine, state.store.as_ref(), name, url, args.get(2).copied()).await {
+                    Ok(source) => format!("✅ Added /{}", source.command()),
+                    Err(e) => format!("❌ {}", e),
+                },
+                _ => "Usage: /addsource <name> <url> [basic:USER_ENV:PASS_ENV | bearer:TOKEN_ENV | query:param:VALUE_ENV]".to_string(),
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::Post(arg) => {
+            let reply = match state.config.channel_id {
+                Some(channel) => post_to_channel(&bot, &state, channel, &arg).await,
+                None => "❌ CHANNEL_ID is not set".to_string(),
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        Command::DisableSource(ref arg) | Command::EnableSource(ref arg) => {
+            let pause = matches!(cmd, Command::DisableSource(_));
+            let reply = match find_source(arg.trim()) {
+                Some(source) => match (state.engine.set_paused(source, pause), pause) {
+                    (true, true) => format!("⏸ {} disabled, commands skip it until /enable_source {}", source.name, source.command()),
+                    (true, false) => format!("▶️ {} enabled", source.name),
+                    (false, true) => format!("{} is already disabled", source.name),
+                    (false, false) => format!("{} is not disabled", source.name),
+                },
+                None if arg.trim().is_empty() => "Usage: /disable_source <name>, /enable_source <name>".to_string(),
+                None => format!("❌ Unknown source \"{}\", see /sources", arg.trim()),
+            };
+            bot.send_message(chat_id, reply).await?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // A bare /news is the active profile's first command
+    let news = matches!(cmd, Command::News(_)).then(|| state.settings.get(chat_id).default_command().and_then(routes::resolve_command));
+    let target = match news.unwrap_or_else(|| cmd.to_target()) {
         Some(t) => t,
+        None if matches!(cmd, Command::News(_)) => {
+            bot.send_message(chat_id, "No active profile. Set one up with /profile add day market,global").await?;
+            return Ok(());
+        }
+        None if matches!(cmd, Command::Start(_) | Command::Help) => {
+            bot.send_message(chat_id, build_help_message())
+                .parse_mode(ParseMode::Html)
+                .await?;
+            return Ok(());
+        }
+        None if matches!(cmd, Command::Search(_)) => {
+            bot.send_message(chat_id, "Usage: /search <words>").await?;
+            return Ok(());
+        }
         None => return Ok(()),
     };
 
@@ -108,47 +976,343 @@ async fn handle_command(
         .send_message(chat_id, format!("⏳ Fetching {}...", target.display_name()))
         .await?;
 
-    let result = fetch_target(engine, target).await;
+    let settings = state.settings.get(chat_id);
+    let options = fetch_options(cmd.args(), is_admin, &settings);
+    let keyboard = buttons::keyboard(&target, options.item_cap());
+    let label = target.display_name();
+    let handle = fetch_target(Arc::clone(&state.engine), target.clone(), options);
+    let Some(result) = await_with_progress(&bot, chat_id, loading_msg.id, &label, handle).await else {
+        return Ok(());
+    };
 
-    let mut response = format!("<b>{}</b>\n\n{}", result.header, result.content);
-    response.push_str(&build_summary(&result));
+    let mut blocks = build_response(&state, chat_id, &target, &result, &settings, is_admin);
+    if from_edit {
+        blocks[0].insert_str(0, "<i>(from edited message)</i>\n");
+    }
+    let (edit, rest) = plan_response(&state, &result, &blocks, keyboard);
 
-    let _ = bot.delete_message(chat_id, loading_msg.id).await;
+    // Editing keeps the response where the command was answered; a message that can't be
+    // edited (too old, no rights) is replaced instead
+    if let Err(e) = edit_chunk(&bot, chat_id, loading_msg.id, &edit).await {
+        log::debug!("Loading message edit in {} failed, resending: {}", chat_id, e);
+        let _ = bot.delete_message(chat_id, loading_msg.id).await;
+        send_plan(&bot, chat_id, vec![edit]).await?;
+    }
+    send_plan(&bot, chat_id, rest).await?;
 
-    if response.len() > 4000 {
-        for chunk in split_message(&response, 4000) {
-            bot.send_message(chat_id, chunk)
-                .parse_mode(ParseMode::Html)
-                .disable_web_page_preview(true)
-                .await?;
-        }
-    } else {
-        bot.send_message(chat_id, response)
-            .parse_mode(ParseMode::Html)
-            .disable_web_page_preview(true)
-            .await?;
+    if result.success_count() > 0 {
+        state.handled.mark(chat_id, msg.id);
+    }
+    Ok(())
+}
+
+/// Full HTML for a fetched target as blocks: items, footer and the admin fetch report.
+/// Also logs the items and records them as seen by the chat.
+fn build_response(state: &AppState, chat_id: ChatId, target: &Target, result: &AggregatedNews, settings: &Settings, is_admin: bool) -> Vec<String> {
+    if let Some(item_log) = &state.item_log {
+        item_log.record(result);
+    }
+
+    let seen = state.seen.mark(chat_id, &shown_fingerprints(result));
+    let mut blocks = render_blocks(result, &settings.render_options().with_seen(seen));
+    blocks.push(build_summary(result));
+    blocks.push(build_footer(target));
+    if is_admin && state.admins.diagnostics_enabled(chat_id) {
+        blocks.push(format_fetch_report(result));
+    }
+    blocks
+}
+
+/// The chunk edited into the loading (or buttoned) message and the overflow chunks sent
+/// after it. The keyboard goes under the last message.
+fn plan_response(
+    state: &AppState,
+    result: &AggregatedNews,
+    blocks: &[String],
+    keyboard: Option<InlineKeyboardMarkup>,
+) -> (OutgoingChunk, Vec<OutgoingChunk>) {
+    let opts = SendOptions {
+        preview_url: if state.config.link_previews { result.lead_link() } else { None },
+        silent: false,
+    };
+    let response = blocks.concat();
+    if fits_message(&response) {
+        return (OutgoingChunk::new(response).with_preview(opts.preview_url).with_keyboard(keyboard), Vec::new());
+    }
+    let mut chunks = plan_blocks(blocks, limits::MESSAGE_CHUNK_LEN, &opts);
+    if let Some(last) = chunks.last_mut() {
+        last.keyboard = keyboard;
+    }
+    // A response too long for one message always splits into several chunks
+    let first = chunks.remove(0);
+    (first, chunks)
+}
+
+/// Run the fetch behind a result button (refresh, more, or another category) and put the
+/// result in place of the message the button sits under
+async fn handle_callback_query(bot: Bot, query: CallbackQuery, state: AppState) -> ResponseResult<()> {
+    const STALE: &str = "This result can't be updated anymore, send the command again";
+    let Some(data) = query.data.as_deref().and_then(ButtonData::decode) else {
+        bot.answer_callback_query(query.id).await?;
+        return Ok(());
+    };
+    let (Some(msg), Some(target)) = (query.regular_message(), data.target()) else {
+        bot.answer_callback_query(query.id).text(STALE).await?;
+        return Ok(());
+    };
+    let is_admin = state.admins.is_admin(Some(query.from.id));
+    if state.maintenance.is_on() && !is_admin {
+        bot.answer_callback_query(query.id).text(&state.maintenance.message).await?;
+        return Ok(());
+    }
+    if state.config.private_mode && !is_admin {
+        bot.answer_callback_query(query.id).text(PRIVATE_REPLY).await?;
+        return Ok(());
     }
+    state.stats.record(match data.action {
+        Action::Refresh => "refresh",
+        Action::More => "more",
+        Action::Open => "open",
+    });
+
+    let chat_id = msg.chat.id;
+    let settings = state.settings.get(chat_id);
+    let defaults = fetch_options("", is_admin, &settings);
+    let options = match data.action {
+        Action::Open => defaults,
+        Action::Refresh | Action::More => FetchOptions {
+            max_items: Some(data.items),
+            refresh: data.action == Action::Refresh,
+            ..defaults
+        },
+    };
+    let handle = fetch_target(Arc::clone(&state.engine), target.clone(), options);
+    let toast = match handle.result().await {
+        Some(result) => {
+            let blocks = build_response(&state, chat_id, &target, &result, &settings, is_admin);
+            let (edit, rest) = plan_response(&state, &result, &blocks, buttons::keyboard(&target, options.item_cap()));
+            match edit_chunk(&bot, chat_id, msg.id, &edit).await {
+                Ok(()) => {
+                    send_plan(&bot, chat_id, rest).await?;
+                    None
+                }
+                Err(RequestError::Api(ApiError::MessageNotModified)) => Some("Already up to date"),
+                Err(e) => {
+                    log::debug!("Button edit in {} failed: {}", chat_id, e);
+                    Some(STALE)
+                }
+            }
+        }
+        None => Some("⚠️ Fetch failed, try again"),
+    };
 
+    // Answered last so the toast can say how it went; a late answer is only cosmetic
+    let mut answer = bot.answer_callback_query(query.id);
+    if let Some(toast) = toast {
+        answer = answer.text(toast);
+    }
+    let _ = answer.await;
     Ok(())
 }
 
-fn split_message(text: &str, max_len: usize) -> Vec<&str> {
-    let mut chunks = Vec::new();
-    let mut start = 0;
-    while start < text.len() {
-        let mut end = start + max_len;
-        if end >= text.len() {
-            chunks.push(&text[start..]);
-            break;
+/// Fetch "/post <command> [options] [silent]" and publish it to the channel, returning the
+/// admin's reply. Channel readers get the items only: no summary line, no command footer.
+async fn post_to_channel(bot: &Bot, state: &AppState, channel: ChatId, arg: &str) -> String {
+    const USAGE: &str = "Usage: /post <command> [popular|<items>] [silent]";
+    let (command, args) = arg.trim().split_once(char::is_whitespace).unwrap_or((arg.trim(), ""));
+    let Some(target) = routes::resolve_command(command) else {
+        return USAGE.to_string();
+    };
+    let silent = args.split_whitespace().any(|w| w.eq_ignore_ascii_case("silent"));
+    let label = target.display_name();
+    let options = FetchOptions { privileged: true, ..FetchOptions::parse(args) };
+    let Some(result) = fetch_target(Arc::clone(&state.engine), target, options).result().await else {
+        return format!("❌ Fetching {} failed", label);
+    };
+    if result.success_count() == 0 {
+        return format!("❌ Nothing to post: no source of {} answered", label);
+    }
+
+    let plan = channel_plan(&result, state.config.link_previews, silent);
+    match send_plan(bot, channel, plan).await {
+        Ok(()) => format!("✅ Posted {} to the channel", label),
+        Err(e) => post_error(channel, &e),
+    }
+}
+
+/// A channel post: the rendered items only, split into messages
+fn channel_plan(result: &AggregatedNews, link_previews: bool, silent: bool) -> Vec<OutgoingChunk> {
+    let opts = SendOptions {
+        preview_url: if link_previews { result.lead_link() } else { None },
+        silent,
+    };
+    plan_blocks(&render_blocks(result, &RenderOptions::default()), limits::MESSAGE_CHUNK_LEN, &opts)
+}
+
+/// What the admin is told when the channel refused a post
+fn post_error(channel: ChatId, e: &RequestError) -> String {
+    match e {
+        RequestError::Api(ApiError::NotEnoughRightsToPostMessages) => {
+            "❌ The bot can't post there: make it a channel administrator with the Post Messages right".to_string()
+        }
+        RequestError::Api(ApiError::ChatNotFound) => {
+            format!("❌ Channel {} not found: check CHANNEL_ID and that the bot was added to it", channel)
         }
-        while !text.is_char_boundary(end) { end -= 1; }
-        let search_range = &text[start..end];
-        if let Some(last_newline) = search_range.rfind('\n') {
-            let split_idx = start + last_newline + 1;
-            if split_idx > start { end = split_idx; }
+        e => format!("❌ Posting failed: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn get_me_errors_are_classified() {
+        assert_eq!(classify_get_me_error(&RequestError::Api(ApiError::InvalidToken)), TokenCheckError::Rejected);
+
+        // Nothing listens on a port that was just released
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let refused = reqwest::get(format!("http://127.0.0.1:{}/", port)).await.unwrap_err();
+        assert_eq!(classify_get_me_error(&RequestError::from(refused)), TokenCheckError::Transient);
+        let flood = RequestError::RetryAfter(teloxide::types::Seconds::from_seconds(5));
+        assert_eq!(classify_get_me_error(&flood), TokenCheckError::Transient);
+        let io = RequestError::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        assert_eq!(classify_get_me_error(&io), TokenCheckError::Transient);
+
+        for api in [ApiError::BotBlocked, ApiError::Unknown("Bad Request: method not found".into())] {
+            assert_eq!(classify_get_me_error(&RequestError::Api(api)), TokenCheckError::Fatal);
         }
-        chunks.push(&text[start..end]);
-        start = end;
     }
-    chunks
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn a_rejected_token_fails_on_the_first_answer() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = reqwest::Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut head = vec![0; 4096];
+            let n = socket.read(&mut head).await.unwrap();
+            let body = r#"{"ok":false,"error_code":401,"description":"Unauthorized"}"#;
+            let reply = format!("HTTP/1.1 401 Unauthorized\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            socket.write_all(reply.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&head[..n]).into_owned()
+        });
+        let bot = Bot::new("123:revoked").set_api_url(url);
+        let err = validate_token(&bot).await.unwrap_err();
+        assert!(err.contains("rejected"), "{}", err);
+        assert!(server.await.unwrap().contains("/bot123:revoked/GetMe"));
+    }
+
+    #[test]
+    fn maintenance_refuses_everyone_but_admins() {
+        for cmd in [Command::Help, Command::War(String::new()), Command::Status, Command::Maintenance("off".into())] {
+            assert_eq!(refusal(&cmd, false, true, false), Some(Refusal::Maintenance), "{:?}", cmd);
+            assert_eq!(refusal(&cmd, true, true, true), None, "{:?}", cmd);
+        }
+    }
+
+    #[test]
+    fn private_mode_keeps_help_public() {
+        assert_eq!(refusal(&Command::Help, false, false, true), None);
+        assert_eq!(refusal(&Command::Start(String::new()), false, false, true), None);
+        assert_eq!(refusal(&Command::Start("war".into()), false, false, true), Some(Refusal::Private));
+        assert_eq!(refusal(&Command::War(String::new()), false, false, true), Some(Refusal::Private));
+        assert_eq!(refusal(&Command::War(String::new()), false, false, false), None);
+    }
+
+    #[test]
+    fn operator_commands_need_an_admin() {
+        for cmd in [Command::Maintenance("on".into()), Command::Stats, Command::AdminConfig, Command::Post(String::new())] {
+            assert_eq!(refusal(&cmd, false, false, false), Some(Refusal::AdminOnly), "{:?}", cmd);
+            assert_eq!(refusal(&cmd, true, false, false), None, "{:?}", cmd);
+        }
+    }
+
+    #[test]
+    fn channel_posts_carry_items_only() {
+        let mut item = network::NewsItem {
+            title: "Central bank holds rates steady amid inflation worries".to_string(),
+            description: None,
+            link: Some("https://example.com/a".to_string()),
+            time_str: "12:40".to_string(),
+            author: None,
+            feed_title: None,
+            views: None,
+            quote: None,
+            timestamp: None,
+            also_on: Vec::new(),
+        };
+        let mut items = vec![item.clone()];
+        item.link = Some("https://example.com/b".to_string());
+        items.push(item);
+        let results = vec![
+            logic::SourceResult::new(&SOURCES[0], Ok(items), logic::FetchMeta::default()),
+            logic::SourceResult::new(&SOURCES[1], Err(network::FetchError::Status(502)), logic::FetchMeta::default()),
+        ];
+        let news = AggregatedNews { header: "World".into(), layout: logic::Layout::Grouped, results, fallback: None, paused: Vec::new() };
+
+        let plan = channel_plan(&news, true, true);
+        let text: String = plan.iter().map(|c| c.text.as_str()).collect();
+        assert!(text.contains("Central bank holds rates steady"));
+        assert!(!text.contains(build_summary(&news).trim()), "{}", text);
+        assert!(!text.contains("active") && !text.contains("dead"), "{}", text);
+        assert!(plan.iter().all(|c| c.silent));
+        assert_eq!(plan[0].preview_url.as_deref(), Some("https://example.com/a"));
+        assert_eq!(channel_plan(&news, false, false)[0].preview_url, None);
+    }
+
+    #[test]
+    fn channel_refusals_explain_the_fix() {
+        let channel = ChatId(-1001234567890);
+        let rights = post_error(channel, &RequestError::Api(ApiError::NotEnoughRightsToPostMessages));
+        assert!(rights.contains("channel administrator"), "{}", rights);
+        let missing = post_error(channel, &RequestError::Api(ApiError::ChatNotFound));
+        assert!(missing.contains("-1001234567890") && missing.contains("CHANNEL_ID"), "{}", missing);
+        assert!(post_error(channel, &RequestError::RetryAfter(teloxide::types::Seconds::from_seconds(3))).starts_with("❌ Posting failed"));
+    }
+
+    #[test]
+    fn handler_tree_builds() {
+        let _ = schema();
+    }
+
+    #[test]
+    fn each_chat_keeps_its_language_and_fetches_use_it() {
+        let settings = ChatSettings::load(Arc::new(crate::store::MemoryStore::default()));
+        let (ours, theirs) = (ChatId(1), ChatId(2));
+        settings.update(ours, |s| s.set("lang", "DE")).unwrap();
+        assert!(matches!(settings.update(ours, |s| s.set("lang", "klingon")), Err(SettingsError::UnknownLanguage(_))));
+        assert_eq!(settings.get(ours).lang(), Some("de"));
+        assert_eq!(settings.get(theirs).lang(), None);
+
+        // Fetches translate into the chat's choice, everyone else into the configured target
+        assert_eq!(fetch_options("popular", false, &settings.get(ours)).target_lang(), "de");
+        assert_eq!(fetch_options("", false, &settings.get(theirs)).target_lang(), limits::translate_target());
+        settings.update(ours, |s| s.set("lang", "default")).unwrap();
+        assert_eq!(fetch_options("", false, &settings.get(ours)).target_lang(), limits::translate_target());
+    }
+
+    #[test]
+    fn every_command_in_the_help_is_recognized() {
+        let help = build_help_message();
+        let mentioned = regex::Regex::new(r"(?:^|[\s>(])/([a-z_]+)").unwrap();
+        let names: Vec<&str> = mentioned.captures_iter(&help).map(|c| c.get(1).unwrap().as_str()).collect();
+        assert!(names.contains(&"commodities") && names.contains(&"gold"), "{:?}", names);
+
+        // Derived commands carry their slash, source commands don't
+        let menu: Vec<String> = menu_commands().into_iter().map(|c| c.command.trim_start_matches('/').to_string()).collect();
+        for name in names {
+            let text = format!("/{}", name);
+            let cmd = Command::parse(&text, "logos_bot")
+                .ok()
+                .or_else(|| find_source(name).map(|s| Command::Source(s.command().to_string())));
+            let cmd = cmd.unwrap_or_else(|| panic!("{} is not a command", text));
+            assert!(menu.iter().any(|m| m == name), "{} is missing from the command menu", text);
+            if routes::resolve_command(name).is_some() {
+                let target = cmd.to_target().unwrap_or_else(|| panic!("{} has no target", text));
+                assert!(!target.resolve().is_empty(), "{} resolves to no sources", text);
+            }
+        }
+    }
+}
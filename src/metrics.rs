@@ -0,0 +1,177 @@
+//! Fetch and translation counters, rendered in the Prometheus text format for /metrics
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds in seconds of the fetch latency histogram buckets
+const LATENCY_BUCKETS: [f64; 8] = [0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 15.0, 30.0];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Non-cumulative counts per bucket; `+Inf` is `count`
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+#[derive(Debug, Default)]
+struct SourceMetrics {
+    /// Network attempts, retries included
+    requests: u64,
+    successes: u64,
+    /// Failed fetches by `FetchError::kind`
+    errors: BTreeMap<&'static str, u64>,
+    retries: u64,
+    latency: Histogram,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    sources: Mutex<BTreeMap<&'static str, SourceMetrics>>,
+    /// Translated texts by outcome: cached, translated, failed, rate_limited
+    translations: Mutex<HashMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    /// One network attempt at a source
+    pub fn record_request(&self, source: &'static str) {
+        self.sources.lock().unwrap().entry(source).or_default().requests += 1;
+    }
+
+    /// How a fetch went over all its attempts; `error` is the failing kind
+    pub fn record_fetch(&self, source: &'static str, error: Option<&'static str>, attempts: u32, elapsed: Duration) {
+        let mut sources = self.sources.lock().unwrap();
+        let metrics = sources.entry(source).or_default();
+        match error {
+            None => metrics.successes += 1,
+            Some(kind) => *metrics.errors.entry(kind).or_default() += 1,
+        }
+        metrics.retries += u64::from(attempts.saturating_sub(1));
+        metrics.latency.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record_translation(&self, outcome: &'static str) {
+        *self.translations.lock().unwrap().entry(outcome).or_default() += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let sources = self.sources.lock().unwrap();
+        let mut out = String::new();
+
+        header(&mut out, "logos_fetch_requests_total", "counter", "Network attempts per source, retries included");
+        for (name, m) in sources.iter() {
+            let _ = writeln!(out, "logos_fetch_requests_total{{source=\"{}\"}} {}", label(name), m.requests);
+        }
+        header(&mut out, "logos_fetches_total", "counter", "Completed fetches per source by result");
+        for (name, m) in sources.iter() {
+            let _ = writeln!(out, "logos_fetches_total{{source=\"{}\",result=\"ok\"}} {}", label(name), m.successes);
+            let failed: u64 = m.errors.values().sum();
+            let _ = writeln!(out, "logos_fetches_total{{source=\"{}\",result=\"error\"}} {}", label(name), failed);
+        }
+        header(&mut out, "logos_fetch_errors_total", "counter", "Failed fetches per source by error kind");
+        for (name, m) in sources.iter() {
+            for (kind, n) in &m.errors {
+                let _ = writeln!(out, "logos_fetch_errors_total{{source=\"{}\",kind=\"{}\"}} {}", label(name), kind, n);
+            }
+        }
+        header(&mut out, "logos_fetch_retries_total", "counter", "Retries after a failed attempt per source");
+        for (name, m) in sources.iter() {
+            let _ = writeln!(out, "logos_fetch_retries_total{{source=\"{}\"}} {}", label(name), m.retries);
+        }
+        header(&mut out, "logos_fetch_duration_seconds", "histogram", "Fetch latency per source over all attempts");
+        for (name, m) in sources.iter() {
+            let mut cumulative = 0;
+            for (le, n) in LATENCY_BUCKETS.iter().zip(m.latency.buckets) {
+                cumulative += n;
+                let _ = writeln!(out, "logos_fetch_duration_seconds_bucket{{source=\"{}\",le=\"{}\"}} {}", label(name), le, cumulative);
+            }
+            let _ = writeln!(out, "logos_fetch_duration_seconds_bucket{{source=\"{}\",le=\"+Inf\"}} {}", label(name), m.latency.count);
+            let _ = writeln!(out, "logos_fetch_duration_seconds_sum{{source=\"{}\"}} {}", label(name), m.latency.sum);
+            let _ = writeln!(out, "logos_fetch_duration_seconds_count{{source=\"{}\"}} {}", label(name), m.latency.count);
+        }
+        drop(sources);
+
+        header(&mut out, "logos_translations_total", "counter", "Translated texts by outcome");
+        let translations: BTreeMap<_, _> = self.translations.lock().unwrap().clone().into_iter().collect();
+        for (outcome, n) in translations {
+            let _ = writeln!(out, "logos_translations_total{{outcome=\"{}\"}} {}", outcome, n);
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value; custom source names come from admins
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Value of the sample line starting with `series`
+    fn sample(text: &str, series: &str) -> u64 {
+        let line = text.lines().find(|l| l.starts_with(series)).unwrap_or_else(|| panic!("no {} in\n{}", series, text));
+        line.rsplit(' ').next().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn rendered_metrics_after_simulated_fetches() {
+        let metrics = Metrics::default();
+        for _ in 0..3 {
+            metrics.record_request("TASS");
+        }
+        metrics.record_fetch("TASS", None, 1, Duration::from_millis(200));
+        metrics.record_fetch("TASS", None, 1, Duration::from_millis(700));
+        metrics.record_fetch("TASS", Some("timeout"), 2, Duration::from_secs(45));
+        metrics.record_fetch("Odd \"name\"", Some("status"), 1, Duration::from_secs(3));
+        metrics.record_translation("translated");
+        metrics.record_translation("cached");
+        metrics.record_translation("cached");
+        let text = metrics.render();
+
+        for name in ["logos_fetch_requests_total", "logos_fetches_total", "logos_fetch_errors_total", "logos_fetch_retries_total", "logos_translations_total"] {
+            assert!(text.contains(&format!("# TYPE {} counter\n", name)), "{}", name);
+        }
+        assert!(text.contains("# TYPE logos_fetch_duration_seconds histogram\n"));
+        assert_eq!(sample(&text, "logos_fetch_requests_total{source=\"TASS\"}"), 3);
+        assert_eq!(sample(&text, "logos_fetches_total{source=\"TASS\",result=\"ok\"}"), 2);
+        assert_eq!(sample(&text, "logos_fetches_total{source=\"TASS\",result=\"error\"}"), 1);
+        assert_eq!(sample(&text, "logos_fetch_errors_total{source=\"TASS\",kind=\"timeout\"}"), 1);
+        assert_eq!(sample(&text, "logos_fetch_retries_total{source=\"TASS\"}"), 1);
+        assert_eq!(sample(&text, "logos_translations_total{outcome=\"cached\"}"), 2);
+        assert_eq!(sample(&text, "logos_fetch_requests_total{source=\"Odd \\\"name\\\"\"}"), 0);
+
+        // Buckets are cumulative and never decrease; +Inf counts everything, like _count
+        let buckets: Vec<u64> = text
+            .lines()
+            .filter(|l| l.starts_with("logos_fetch_duration_seconds_bucket{source=\"TASS\""))
+            .map(|l| l.rsplit(' ').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(buckets.len(), LATENCY_BUCKETS.len() + 1);
+        assert!(buckets.windows(2).all(|w| w[0] <= w[1]), "{:?}", buckets);
+        assert_eq!(sample(&text, "logos_fetch_duration_seconds_bucket{source=\"TASS\",le=\"0.25\"}"), 1);
+        assert_eq!(sample(&text, "logos_fetch_duration_seconds_bucket{source=\"TASS\",le=\"1\"}"), 2);
+        assert_eq!(sample(&text, "logos_fetch_duration_seconds_bucket{source=\"TASS\",le=\"30\"}"), 2);
+        let count = sample(&text, "logos_fetch_duration_seconds_count{source=\"TASS\"}");
+        assert_eq!(count, 3);
+        assert_eq!(sample(&text, "logos_fetch_duration_seconds_bucket{source=\"TASS\",le=\"+Inf\"}"), count);
+    }
+}
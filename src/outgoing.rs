@@ -0,0 +1,447 @@
+//! Outgoing message planning - chunking responses and delivering them
+
+use crate::consts::limits;
+use std::future::Future;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardMarkup, LinkPreviewOptions, MessageId, ParseMode};
+use teloxide::{ApiError, RequestError};
+
+/// Send attempts per chunk before the rest of the response is abandoned
+const CHUNK_SEND_ATTEMPTS: u32 = 3;
+
+/// Whether text can be sent or edited in as a single message.
+/// Measured in bytes, which never undercounts Telegram's character limit.
+pub fn fits_message(text: &str) -> bool {
+    text.len() <= limits::TELEGRAM_MESSAGE_LEN
+}
+
+/// A single Telegram message in a send plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutgoingChunk {
+    pub text: String,
+    /// URL to preview under this chunk; `None` keeps previews off
+    pub preview_url: Option<String>,
+    /// Deliver without a notification sound
+    pub silent: bool,
+    /// Position within the response, zero-based
+    pub index: usize,
+    pub total: usize,
+    /// Buttons under this chunk
+    pub keyboard: Option<InlineKeyboardMarkup>,
+}
+
+impl OutgoingChunk {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into(), preview_url: None, silent: false, index: 0, total: 1, keyboard: None }
+    }
+
+    fn numbered(mut self, index: usize, total: usize) -> Self {
+        self.index = index;
+        self.total = total;
+        self
+    }
+
+    /// Text as sent: multi-chunk responses get a trailing "(2/3)" marker
+    /// so a truncated delivery is visible to the reader
+    pub fn rendered(&self) -> String {
+        if self.total <= 1 {
+            return self.text.clone();
+        }
+        format!("{}\n<i>({}/{})</i>", self.text.trim_end(), self.index + 1, self.total)
+    }
+
+    pub fn with_preview(mut self, url: Option<String>) -> Self { self.preview_url = url; self }
+    pub fn with_silent(mut self, silent: bool) -> Self { self.silent = silent; self }
+    pub fn with_keyboard(mut self, keyboard: Option<InlineKeyboardMarkup>) -> Self { self.keyboard = keyboard; self }
+
+    /// Explicit preview options so Telegram never picks a mid-list URL on its own
+    pub fn link_preview_options(&self) -> LinkPreviewOptions {
+        LinkPreviewOptions {
+            is_disabled: self.preview_url.is_none(),
+            url: self.preview_url.clone(),
+            prefer_small_media: false,
+            prefer_large_media: false,
+            show_above_text: false,
+        }
+    }
+}
+
+/// How a response should be delivered
+#[derive(Debug, Clone, Default)]
+pub struct SendOptions {
+    /// Link previewed on the first chunk (usually the lead item)
+    pub preview_url: Option<String>,
+    pub silent: bool,
+}
+
+/// Split a rendered response into the chunks that will be sent, in order
+pub fn plan_chunks(text: &str, max_len: usize, opts: &SendOptions) -> Vec<OutgoingChunk> {
+    number_parts(split_message(text, max_len), opts)
+}
+
+/// Pack whole blocks into chunks of at most `max_len` bytes, starting a new chunk when the
+/// next block doesn't fit, so an item never straddles two messages. Only a block too long
+/// for a chunk of its own is cut, by `split_message`.
+pub fn plan_blocks(blocks: &[String], max_len: usize, opts: &SendOptions) -> Vec<OutgoingChunk> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for block in blocks {
+        if !current.is_empty() && current.len() + block.len() > max_len {
+            parts.push(std::mem::take(&mut current));
+        }
+        if block.len() <= max_len {
+            current.push_str(block);
+            continue;
+        }
+        let mut pieces = split_message(block, max_len);
+        current = pieces.pop().unwrap_or_default();
+        parts.extend(pieces);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    number_parts(parts, opts)
+}
+
+fn number_parts(parts: Vec<String>, opts: &SendOptions) -> Vec<OutgoingChunk> {
+    let total = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(idx, part)| {
+            let preview = if idx == 0 { opts.preview_url.clone() } else { None };
+            OutgoingChunk::new(part)
+                .with_preview(preview)
+                .with_silent(opts.silent)
+                .numbered(idx, total)
+        })
+        .collect()
+}
+
+/// Send a plan strictly in order: each chunk is retried until delivered
+/// before the next one goes out, and a chunk that keeps failing ends the response
+pub async fn send_plan(bot: &Bot, chat_id: ChatId, plan: Vec<OutgoingChunk>) -> ResponseResult<()> {
+    deliver(chat_id, plan, |chunk| {
+        let mut request = bot
+            .send_message(chat_id, chunk.rendered())
+            .parse_mode(ParseMode::Html)
+            .link_preview_options(chunk.link_preview_options())
+            .disable_notification(chunk.silent);
+        if let Some(keyboard) = &chunk.keyboard {
+            request = request.reply_markup(keyboard.clone());
+        }
+        async move { request.await.map(drop) }
+    })
+    .await
+}
+
+/// The ordering and retry policy of `send_plan`, over any way of sending one chunk
+async fn deliver<F, Fut>(chat_id: ChatId, plan: Vec<OutgoingChunk>, mut send: F) -> ResponseResult<()>
+where
+    F: FnMut(&OutgoingChunk) -> Fut,
+    Fut: Future<Output = ResponseResult<()>>,
+{
+    for chunk in plan {
+        send_chunk_with_retry(chat_id, &chunk, &mut send).await?;
+    }
+    Ok(())
+}
+
+async fn send_chunk_with_retry<F, Fut>(chat_id: ChatId, chunk: &OutgoingChunk, send: &mut F) -> ResponseResult<()>
+where
+    F: FnMut(&OutgoingChunk) -> Fut,
+    Fut: Future<Output = ResponseResult<()>>,
+{
+    let mut attempt = 1;
+    loop {
+        let err = match send(chunk).await {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        let wait = match &err {
+            RequestError::RetryAfter(secs) => secs.duration(),
+            RequestError::Network(_) | RequestError::Io(_) => Duration::from_secs(attempt as u64),
+            _ => return Err(err),
+        };
+        if attempt >= CHUNK_SEND_ATTEMPTS {
+            log::error!("Giving up on chunk {}/{} for {}: {}", chunk.index + 1, chunk.total, chat_id, err);
+            return Err(err);
+        }
+        log::warn!("Chunk {}/{} failed ({}), retrying in {:?}", chunk.index + 1, chunk.total, err, wait);
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+/// The chat blocked, removed or lost the bot, so there's no point sending there again
+pub fn is_chat_gone(err: &RequestError) -> bool {
+    matches!(
+        err,
+        RequestError::Api(
+            ApiError::BotBlocked | ApiError::BotKicked | ApiError::BotKickedFromSupergroup
+            | ApiError::ChatNotFound | ApiError::UserDeactivated
+        )
+    )
+}
+
+/// Replace the text of an existing message with a chunk
+pub async fn edit_chunk(bot: &Bot, chat_id: ChatId, msg_id: MessageId, chunk: &OutgoingChunk) -> ResponseResult<()> {
+    let mut request = bot
+        .edit_message_text(chat_id, msg_id, chunk.rendered())
+        .parse_mode(ParseMode::Html)
+        .link_preview_options(chunk.link_preview_options());
+    if let Some(keyboard) = &chunk.keyboard {
+        request = request.reply_markup(keyboard.clone());
+    }
+    request.await?;
+    Ok(())
+}
+
+/// Bytes kept free in every chunk for the closing tags a boundary forces
+const TAG_RESERVE: usize = 64;
+
+/// Split HTML into chunks of at most `max_len` bytes, preferring newlines. A cut never
+/// lands inside a tag or an entity, and formatting open at a cut is closed at the end of
+/// the chunk and reopened at the start of the next, so every chunk is valid on its own.
+pub fn split_message(text: &str, max_len: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    // Tags open at the current position: (name, opening tag as written)
+    let mut open: Vec<(&str, &str)> = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let prefix: String = open.iter().map(|(_, tag)| *tag).collect();
+        if prefix.len() + rest.len() <= max_len {
+            chunks.push(prefix + rest);
+            break;
+        }
+        let cut = safe_cut(rest, max_len.saturating_sub(prefix.len() + TAG_RESERVE).max(1));
+        let (head, tail) = rest.split_at(cut);
+        track_tags(head, &mut open);
+        let closing: String = open.iter().rev().map(|(name, _)| format!("</{}>", name)).collect();
+        chunks.push(prefix + head + &closing);
+        rest = tail;
+    }
+    chunks
+}
+
+/// Largest cut within `budget` bytes, backed off to a newline when there is one and
+/// never inside `<...>` or `&...;`; always at least one whole character or tag
+fn safe_cut(text: &str, budget: usize) -> usize {
+    let mut end = budget.min(text.len());
+    while !text.is_char_boundary(end) { end -= 1; }
+    if let Some(newline) = text[..end].rfind('\n') {
+        end = newline + 1;
+    }
+    if let Some(lt) = text[..end].rfind('<').filter(|lt| !text[*lt..end].contains('>')) {
+        end = lt;
+    }
+    if let Some(amp) = text[..end].rfind('&').filter(|amp| !text[*amp..end].contains(';')) {
+        end = amp;
+    }
+    if end == 0 {
+        // A single tag or entity longer than the budget: keep it whole
+        end = match text.as_bytes()[0] {
+            b'<' => text.find('>').map_or(text.len(), |i| i + 1),
+            b'&' => text.find(';').map_or(text.len(), |i| i + 1),
+            _ => text.chars().next().map_or(text.len(), char::len_utf8),
+        };
+    }
+    end
+}
+
+/// Update the open-tag stack with the tags found in `html`
+fn track_tags<'a>(html: &'a str, open: &mut Vec<(&'a str, &'a str)>) {
+    let mut rest = html;
+    while let Some(lt) = rest.find('<') {
+        let Some(gt) = rest[lt..].find('>') else { break };
+        let tag = &rest[lt..lt + gt + 1];
+        let inner = &tag[1..tag.len() - 1];
+        match inner.strip_prefix('/') {
+            Some(name) => {
+                if let Some(pos) = open.iter().rposition(|(open_name, _)| open_name.eq_ignore_ascii_case(name.trim())) {
+                    open.truncate(pos);
+                }
+            }
+            None if !inner.ends_with('/') => {
+                let name = inner.split(|c: char| c.is_whitespace()).next().unwrap_or_default();
+                open.push((name, tag));
+            }
+            None => {}
+        }
+        rest = &rest[lt + gt + 1..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::SOURCES;
+    use crate::format::{render_blocks, RenderOptions};
+    use crate::logic::{AggregatedNews, FetchMeta, Layout, SourceResult};
+    use crate::network::NewsItem;
+    use std::sync::Mutex;
+    use teloxide::types::Seconds;
+
+    fn blocks(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn multi_chunk_plans_carry_position_markers() {
+        let opts = SendOptions { preview_url: Some("https://example.com/lead".into()), silent: true };
+        let plan = plan_blocks(&blocks(&["first item\n", "second item\n", "third item\n"]), 14, &opts);
+        let rendered: Vec<String> = plan.iter().map(OutgoingChunk::rendered).collect();
+        assert_eq!(rendered, [
+            "first item\n<i>(1/3)</i>",
+            "second item\n<i>(2/3)</i>",
+            "third item\n<i>(3/3)</i>",
+        ]);
+        assert_eq!(plan[0].preview_url.as_deref(), Some("https://example.com/lead"));
+        assert!(plan[1..].iter().all(|c| c.preview_url.is_none() && c.silent));
+
+        let single = plan_blocks(&blocks(&["only\n"]), 100, &SendOptions::default());
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].rendered(), "only\n");
+        assert!(single[0].link_preview_options().is_disabled);
+    }
+
+    #[test]
+    fn blocks_are_packed_whole_and_only_oversized_ones_cut() {
+        let plan = plan_blocks(&blocks(&["aaaa\n", "bbbb\n", "cccc\n"]), 10, &SendOptions::default());
+        let texts: Vec<&str> = plan.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, ["aaaa\nbbbb\n", "cccc\n"]);
+
+        let long = "x".repeat(25);
+        let plan = plan_blocks(&blocks(&["head\n", &long]), 10, &SendOptions::default());
+        assert_eq!(plan[0].text, "head\n");
+        assert_eq!(plan[1..].iter().map(|c| c.text.as_str()).collect::<String>(), long);
+        assert!(plan.iter().all(|c| c.text.len() <= 10));
+    }
+
+    /// Connection-refused error from a port nobody listens on
+    async fn network_error() -> RequestError {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        RequestError::Network(reqwest::get(format!("http://{}", addr)).await.unwrap_err())
+    }
+
+    /// Deliver three chunks, failing chunk 2 with each error of `failures` in turn;
+    /// returns the result and the chunk indexes in the order sends were attempted
+    async fn deliver_failing_middle(failures: Vec<RequestError>) -> (ResponseResult<()>, Vec<usize>) {
+        let plan = plan_blocks(&blocks(&["one\n", "two\n", "three\n"]), 6, &SendOptions::default());
+        let attempts = Mutex::new(Vec::new());
+        let failures = Mutex::new(failures.into_iter());
+        let result = deliver(ChatId(1), plan, |chunk| {
+            attempts.lock().unwrap().push(chunk.index);
+            let outcome = match chunk.index {
+                1 => failures.lock().unwrap().next().map_or(Ok(()), Err),
+                _ => Ok(()),
+            };
+            async move { outcome }
+        })
+        .await;
+        (result, attempts.into_inner().unwrap())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retried_chunk_is_delivered_before_the_next() {
+        let failures = vec![RequestError::RetryAfter(Seconds::from_seconds(5)), network_error().await];
+        let (result, attempts) = deliver_failing_middle(failures).await;
+        assert!(result.is_ok());
+        assert_eq!(attempts, [0, 1, 1, 1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn chunk_failing_every_attempt_ends_the_response() {
+        let failures = vec![
+            RequestError::RetryAfter(Seconds::from_seconds(1)),
+            RequestError::Io(std::io::ErrorKind::ConnectionReset.into()),
+            RequestError::RetryAfter(Seconds::from_seconds(1)),
+        ];
+        let (result, attempts) = deliver_failing_middle(failures).await;
+        assert!(matches!(result, Err(RequestError::RetryAfter(_))));
+        assert_eq!(attempts, [0, 1, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_are_not_retried() {
+        let (result, attempts) = deliver_failing_middle(vec![RequestError::Api(ApiError::BotBlocked)]).await;
+        assert!(result.as_ref().is_err_and(is_chat_gone));
+        assert_eq!(attempts, [0, 1]);
+    }
+
+    /// Tags balanced and never cut, entities whole
+    fn assert_valid_markup(chunk: &str) {
+        let mut open = Vec::new();
+        let mut rest = chunk;
+        while let Some(lt) = rest.find('<') {
+            let gt = rest[lt..].find('>').unwrap_or_else(|| panic!("cut tag in {:?}", chunk));
+            let tag = &rest[lt + 1..lt + gt];
+            match tag.strip_prefix('/') {
+                Some(name) => assert_eq!(open.pop(), Some(name), "misnested in {:?}", chunk),
+                None => open.push(tag.split(' ').next().unwrap()),
+            }
+            rest = &rest[lt + gt + 1..];
+        }
+        assert!(open.is_empty(), "unclosed {:?} in {:?}", open, chunk);
+        for (i, _) in chunk.match_indices('&') {
+            let entity = &chunk[i..chunk[i..].find(';').map_or(chunk.len(), |end| i + end + 1)];
+            assert!(["&amp;", "&lt;", "&gt;", "&quot;"].contains(&entity), "cut entity {:?} in {:?}", entity, chunk);
+        }
+    }
+
+    #[test]
+    fn long_html_digest_splits_into_valid_chunks() {
+        let item = "<b>Oil &amp; gas</b>: <a href=\"https://example.com/a?x=1&amp;y=2\">Brent climbs &gt; $90 on <i>supply &lt;cuts&gt;</i></a> — «котировки» растут\n";
+        let text = format!("<blockquote>{}</blockquote>\n{}", item.repeat(40), item.repeat(40));
+        for max_len in [200, 333, 1000, limits::TELEGRAM_MESSAGE_LEN] {
+            let chunks = split_message(&text, max_len);
+            assert!(chunks.len() > 1);
+            for chunk in &chunks {
+                assert!(chunk.len() <= max_len, "{} > {}", chunk.len(), max_len);
+                assert_valid_markup(chunk);
+            }
+        }
+    }
+
+    fn item(n: usize) -> NewsItem {
+        NewsItem {
+            title: format!("Headline number {} about markets, shipping and the rouble exchange rate", n),
+            description: Some("A longer description that adds a line under the title. ".repeat(n % 4 + 1)),
+            link: Some(format!("https://example.com/news/{}", n)),
+            time_str: "12:40".to_string(),
+            author: None,
+            feed_title: None,
+            views: Some(1_200),
+            quote: None,
+            timestamp: None,
+            also_on: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn link_lines_stay_with_their_item() {
+        let results = SOURCES
+            .iter()
+            .filter(|s| s.extractor.is_none())
+            .take(3)
+            .map(|s| SourceResult::new(s, Ok((0..12).map(item).collect()), FetchMeta::default()))
+            .collect();
+        let news = AggregatedNews { header: "World".into(), layout: Layout::Grouped, results, fallback: None, paused: Vec::new() };
+        let blocks = render_blocks(&news, &RenderOptions::default());
+        // Only a block too long for any chunk may be cut; these all fit
+        assert!(blocks.iter().all(|b| b.len() <= 700));
+        for max_len in [700, 1500, limits::TELEGRAM_MESSAGE_LEN] {
+            let plan = plan_blocks(&blocks, max_len, &SendOptions::default());
+            assert!(plan.len() > 1);
+            for chunk in &plan {
+                let first = chunk.text.lines().find(|l| !l.trim().is_empty()).unwrap();
+                assert!(!first.trim_start().starts_with('└'), "chunk at {} starts with {:?}", max_len, first);
+                // Every item's title and its time/link line land in the same chunk
+                assert_eq!(chunk.text.matches("▪️").count(), chunk.text.matches("└ <code>").count(), "{}", chunk.text);
+            }
+        }
+    }
+}
@@ -0,0 +1,849 @@
+//! Shared bot state injected into update handlers
+
+use crate::alerts::Bursts;
+use crate::config::Config;
+use crate::consts::limits;
+use crate::format::RenderOptions;
+use crate::itemlog::ItemLog;
+use crate::network::NewsEngine;
+use crate::storage::{load_json, save_json, write_json};
+use crate::store::Store;
+use crate::translate::{supported_language, LANGUAGES};
+use crate::utils::parse_utc_offset;
+use chrono::{DateTime, FixedOffset, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use teloxide::types::{ChatId, MessageId, UserId};
+use thiserror::Error;
+
+/// Everything handlers need, injected as a single dependency
+#[derive(Clone)]
+pub struct AppState {
+    pub engine: Arc<NewsEngine>,
+    pub handled: Arc<HandledMessages>,
+    pub admins: Arc<Admins>,
+    pub config: Arc<Config>,
+    pub stats: Arc<CommandStats>,
+    pub item_log: Option<Arc<ItemLog>>,
+    pub maintenance: Arc<Maintenance>,
+    pub settings: Arc<ChatSettings>,
+    pub seen: Arc<SeenItems>,
+    /// Alert follow-ups being collected, sent when their window ends or on shutdown
+    pub bursts: Arc<Bursts>,
+    /// Where settings and custom sources are persisted
+    pub store: Arc<dyn Store>,
+}
+
+/// How many recently handled message ids are remembered per chat
+const HANDLED_PER_CHAT: usize = 32;
+
+/// Messages that already produced a successful fetch, so edits don't re-run them
+#[derive(Default)]
+pub struct HandledMessages {
+    inner: Mutex<HashMap<ChatId, VecDeque<MessageId>>>,
+}
+
+impl HandledMessages {
+    pub fn mark(&self, chat_id: ChatId, msg_id: MessageId) {
+        let mut map = self.inner.lock().unwrap();
+        let ids = map.entry(chat_id).or_default();
+        if ids.len() >= HANDLED_PER_CHAT {
+            ids.pop_front();
+        }
+        ids.push_back(msg_id);
+    }
+
+    pub fn contains(&self, chat_id: ChatId, msg_id: MessageId) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .get(&chat_id)
+            .is_some_and(|ids| ids.contains(&msg_id))
+    }
+}
+
+/// Operators allowed to run admin commands, from ADMIN_IDS (comma-separated user ids)
+#[derive(Default)]
+pub struct Admins {
+    ids: HashSet<UserId>,
+    /// Chats where admins turned the fetch report on
+    diagnostics: Mutex<HashSet<ChatId>>,
+}
+
+impl Admins {
+    pub fn from_env() -> Self {
+        Self::parse(&std::env::var("ADMIN_IDS").unwrap_or_default())
+    }
+
+    pub fn parse(raw: &str) -> Self {
+        let ids = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .filter_map(|id| match id.parse::<u64>() {
+                Ok(id) => Some(UserId(id)),
+                Err(_) => {
+                    log::warn!("Ignoring ADMIN_IDS entry {:?}: not a user id", id);
+                    None
+                }
+            })
+            .collect();
+        Self { ids, diagnostics: Mutex::default() }
+    }
+
+    pub fn count(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = UserId> + '_ {
+        self.ids.iter().copied()
+    }
+
+    pub fn is_admin(&self, user: Option<UserId>) -> bool {
+        user.is_some_and(|id| self.ids.contains(&id))
+    }
+
+    /// Flip the fetch report for a chat, returning the new state
+    pub fn toggle_diagnostics(&self, chat_id: ChatId) -> bool {
+        let mut chats = self.diagnostics.lock().unwrap();
+        if !chats.remove(&chat_id) {
+            chats.insert(chat_id);
+            return true;
+        }
+        false
+    }
+
+    pub fn diagnostics_enabled(&self, chat_id: ChatId) -> bool {
+        self.diagnostics.lock().unwrap().contains(&chat_id)
+    }
+}
+
+/// Aggregate command counts since startup
+#[derive(Default)]
+pub struct CommandStats {
+    counts: Mutex<BTreeMap<String, u64>>,
+}
+
+impl CommandStats {
+    pub fn record(&self, command: &str) {
+        *self.counts.lock().unwrap().entry(command.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.counts.lock().unwrap().clone()
+    }
+}
+
+const MAINTENANCE_FILE: &str = "maintenance.json";
+
+/// Bot-wide pause of user traffic, persisted so restarts keep it
+pub struct Maintenance {
+    enabled: AtomicBool,
+    /// Reply shown to non-admins, from MAINTENANCE_MESSAGE
+    pub message: String,
+}
+
+impl Maintenance {
+    pub fn load() -> Self {
+        let enabled = load_json::<bool>(MAINTENANCE_FILE).unwrap_or(false);
+        if enabled {
+            log::warn!("Starting in maintenance mode");
+        }
+        let message = std::env::var("MAINTENANCE_MESSAGE")
+            .unwrap_or_else(|_| "🛠 Maintenance in progress, back shortly.".to_string());
+        Self { enabled: AtomicBool::new(enabled), message }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, on: bool) {
+        self.enabled.store(on, Ordering::Relaxed);
+        save_json(MAINTENANCE_FILE, &on);
+        log::warn!("Maintenance mode {}", if on { "ON" } else { "OFF" });
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("unknown setting, use lang, items, desc, seen or tz")]
+    UnknownKey,
+    #[error("unknown language \"{0}\", available: {}", LANGUAGES.join(", "))]
+    UnknownLanguage(String),
+    #[error("items must be 1-{}", limits::MAX_ITEMS_CAP)]
+    ItemsOutOfRange,
+    #[error("desc must be on or off")]
+    BadToggle,
+    #[error("seen must be show or hide")]
+    BadSeenMode,
+    #[error("timezone must be an offset from UTC between -12 and +14, e.g. +3 or +05:30")]
+    BadOffset,
+    #[error("interval must be {}-{} minutes", limits::SUBSCRIPTION_MIN_MINUTES, limits::SUBSCRIPTION_MAX_MINUTES)]
+    BadInterval,
+    #[error("at most {} subscriptions per chat", limits::MAX_SUBSCRIPTIONS_PER_CHAT)]
+    TooManySubscriptions,
+    #[error("at most {} alerts per chat", limits::MAX_ALERTS_PER_CHAT)]
+    TooManyAlerts,
+    #[error("at most {} profiles per chat", limits::MAX_PROFILES_PER_CHAT)]
+    TooManyProfiles,
+    #[error("no profile named \"{0}\", see /profile list")]
+    UnknownProfile(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Above,
+    Below,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Above => "above",
+            Direction::Below => "below",
+        })
+    }
+}
+
+/// An /alert entry: notify once when `source`'s price crosses `threshold` in `direction`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceAlert {
+    pub source: String,
+    pub direction: Direction,
+    pub threshold: f64,
+}
+
+/// A "/alert war strike" entry: notify about new `command` items containing every word
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct KeywordAlert {
+    pub command: String,
+    pub words: String,
+}
+
+impl fmt::Display for KeywordAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' in /{}", self.words, self.command)
+    }
+}
+
+/// A /subscribe entry: `command` is polled every `interval_minutes` and new items are pushed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub command: String,
+    pub interval_minutes: u32,
+}
+
+/// A "/subscribe market 08:00" entry: the full `command` result, daily at `at` in the
+/// chat's timezone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Digest {
+    pub command: String,
+    pub at: NaiveTime,
+    /// Last time it went out, persisted so neither a restart nor a clock jump sends it twice
+    pub last_sent: Option<DateTime<Utc>>,
+}
+
+/// A "/profile add night war,liveuamap 22:00" entry: commands subscribed together, switched
+/// to daily at `at` in the chat's timezone when set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// The first one is the chat's default target, see `Settings::default_command`
+    pub commands: Vec<String>,
+    pub at: Option<NaiveTime>,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commands: Vec<String> = self.commands.iter().map(|c| format!("/{}", c)).collect();
+        write!(f, "{}: {}", self.name, commands.join(" "))?;
+        match self.at {
+            Some(at) => write!(f, " from {}", at.format("%H:%M")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// One chat's /settings; unset fields follow the bot-wide defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Translation target, an allowlisted code
+    pub lang: Option<String>,
+    /// Items per source when a command names no number
+    pub items: Option<usize>,
+    /// Titles only, for a denser digest
+    pub hide_descriptions: bool,
+    /// Collapse items the chat has already seen into a count
+    pub hide_seen: bool,
+    /// Display timezone in minutes east of UTC
+    pub utc_offset_minutes: Option<i32>,
+    pub subscriptions: Vec<Subscription>,
+    pub digests: Vec<Digest>,
+    pub alerts: Vec<PriceAlert>,
+    pub keyword_alerts: Vec<KeywordAlert>,
+    pub profiles: Vec<Profile>,
+    /// The profile whose commands are subscribed
+    pub active_profile: Option<String>,
+    /// Last profile switch, scheduled or by hand; one by hand holds until the next scheduled one
+    pub profile_switched: Option<DateTime<Utc>>,
+}
+
+impl Settings {
+    pub fn lang(&self) -> Option<&'static str> {
+        self.lang.as_deref().and_then(supported_language)
+    }
+
+    pub fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            descriptions: !self.hide_descriptions,
+            seen: None,
+            hide_seen: self.hide_seen,
+            offset: self
+                .utc_offset_minutes
+                .and_then(|m| FixedOffset::east_opt(m * 60))
+                .unwrap_or_else(limits::display_offset),
+        }
+    }
+
+    /// Whether fetched results differ from what a chat with default settings gets
+    pub fn is_personalized(&self) -> bool {
+        self.lang.is_some() || self.items.is_some() || self.utc_offset_minutes.is_some()
+    }
+
+    /// Add a subscription or change the interval of an existing one for `command`
+    pub fn subscribe(&mut self, command: &str, interval_minutes: u32) -> Result<(), SettingsError> {
+        if !(limits::SUBSCRIPTION_MIN_MINUTES..=limits::SUBSCRIPTION_MAX_MINUTES).contains(&interval_minutes) {
+            return Err(SettingsError::BadInterval);
+        }
+        if let Some(existing) = self.subscriptions.iter_mut().find(|s| s.command == command) {
+            existing.interval_minutes = interval_minutes;
+            return Ok(());
+        }
+        if self.subscriptions.len() + self.digests.len() >= limits::MAX_SUBSCRIPTIONS_PER_CHAT {
+            return Err(SettingsError::TooManySubscriptions);
+        }
+        self.subscriptions.push(Subscription { command: command.to_string(), interval_minutes });
+        Ok(())
+    }
+
+    /// Add a daily digest of `command` or move an existing one to `at`
+    pub fn add_digest(&mut self, command: &str, at: NaiveTime) -> Result<(), SettingsError> {
+        if let Some(existing) = self.digests.iter_mut().find(|d| d.command == command) {
+            existing.at = at;
+            return Ok(());
+        }
+        if self.subscriptions.len() + self.digests.len() >= limits::MAX_SUBSCRIPTIONS_PER_CHAT {
+            return Err(SettingsError::TooManySubscriptions);
+        }
+        self.digests.push(Digest { command: command.to_string(), at, last_sent: None });
+        Ok(())
+    }
+
+    /// Drop the subscription and digest of `command`, or all of them for "all"; false when
+    /// nothing matched
+    pub fn unsubscribe(&mut self, command: &str) -> bool {
+        let before = self.subscriptions.len() + self.digests.len();
+        self.subscriptions.retain(|s| command != "all" && s.command != command);
+        self.digests.retain(|d| command != "all" && d.command != command);
+        self.subscriptions.len() + self.digests.len() < before
+    }
+
+    pub fn add_alert(&mut self, alert: PriceAlert) -> Result<(), SettingsError> {
+        if self.alerts.contains(&alert) {
+            return Ok(());
+        }
+        if self.alerts.len() + self.keyword_alerts.len() >= limits::MAX_ALERTS_PER_CHAT {
+            return Err(SettingsError::TooManyAlerts);
+        }
+        self.alerts.push(alert);
+        Ok(())
+    }
+
+    pub fn add_keyword_alert(&mut self, alert: KeywordAlert) -> Result<(), SettingsError> {
+        if self.keyword_alerts.contains(&alert) {
+            return Ok(());
+        }
+        if self.alerts.len() + self.keyword_alerts.len() >= limits::MAX_ALERTS_PER_CHAT {
+            return Err(SettingsError::TooManyAlerts);
+        }
+        self.keyword_alerts.push(alert);
+        Ok(())
+    }
+
+    /// One line per alert as /alerts numbers them: price alerts first, then keyword ones
+    pub fn alert_lines(&self) -> Vec<String> {
+        self.alerts
+            .iter()
+            .map(|a| format!("{} {} {}", a.source, a.direction, a.threshold))
+            .chain(self.keyword_alerts.iter().map(|a| a.to_string()))
+            .collect()
+    }
+
+    /// Remove alert `number` (1-based, as in `alert_lines`); false when there is none
+    pub fn remove_alert(&mut self, number: usize) -> bool {
+        let prices = self.alerts.len();
+        match number {
+            n if (1..=prices).contains(&n) => drop(self.alerts.remove(n - 1)),
+            n if (prices + 1..=prices + self.keyword_alerts.len()).contains(&n) => drop(self.keyword_alerts.remove(n - prices - 1)),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Add a profile or replace the one with the same name
+    pub fn add_profile(&mut self, profile: Profile) -> Result<(), SettingsError> {
+        if profile.commands.len() + self.digests.len() > limits::MAX_SUBSCRIPTIONS_PER_CHAT {
+            return Err(SettingsError::TooManySubscriptions);
+        }
+        if let Some(existing) = self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            *existing = profile;
+            return Ok(());
+        }
+        if self.profiles.len() >= limits::MAX_PROFILES_PER_CHAT {
+            return Err(SettingsError::TooManyProfiles);
+        }
+        self.profiles.push(profile);
+        Ok(())
+    }
+
+    /// Drop a profile; its subscriptions stay until the next switch. False when there is none.
+    pub fn remove_profile(&mut self, name: &str) -> bool {
+        let before = self.profiles.len();
+        self.profiles.retain(|p| p.name != name);
+        if self.active_profile.as_deref() == Some(name) {
+            self.active_profile = None;
+        }
+        self.profiles.len() < before
+    }
+
+    /// Make `name` the active profile: its commands replace the subscriptions in one change,
+    /// keeping the interval of those subscribed already
+    pub fn switch_profile(&mut self, name: &str, now: DateTime<Utc>) -> Result<(), SettingsError> {
+        let profile = self.profiles.iter().find(|p| p.name == name).ok_or_else(|| SettingsError::UnknownProfile(name.to_string()))?;
+        if profile.commands.len() + self.digests.len() > limits::MAX_SUBSCRIPTIONS_PER_CHAT {
+            return Err(SettingsError::TooManySubscriptions);
+        }
+        self.subscriptions = profile
+            .commands
+            .iter()
+            .map(|command| Subscription {
+                command: command.clone(),
+                interval_minutes: self
+                    .subscriptions
+                    .iter()
+                    .find(|s| s.command == *command)
+                    .map_or(limits::SUBSCRIPTION_DEFAULT_MINUTES, |s| s.interval_minutes),
+            })
+            .collect();
+        self.active_profile = Some(profile.name.clone());
+        self.profile_switched = Some(now);
+        Ok(())
+    }
+
+    /// The active profile's first command, what /news shows
+    pub fn default_command(&self) -> Option<&str> {
+        let active = self.active_profile.as_deref()?;
+        let profile = self.profiles.iter().find(|p| p.name == active)?;
+        profile.commands.first().map(String::as_str)
+    }
+
+    /// Apply "/settings <key> <value>"; "default" clears a value back to the bot-wide one
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), SettingsError> {
+        let reset = value.eq_ignore_ascii_case("default");
+        match key.to_lowercase().as_str() {
+            "lang" if reset => self.lang = None,
+            "lang" => {
+                let lang = supported_language(value).ok_or_else(|| SettingsError::UnknownLanguage(value.to_string()))?;
+                self.lang = Some(lang.to_string());
+            }
+            "items" if reset => self.items = None,
+            "items" => {
+                let n = value.parse().ok().filter(|n| (1..=limits::MAX_ITEMS_CAP).contains(n));
+                self.items = Some(n.ok_or(SettingsError::ItemsOutOfRange)?);
+            }
+            "desc" => {
+                self.hide_descriptions = match value.to_lowercase().as_str() {
+                    "on" | "default" => false,
+                    "off" => true,
+                    _ => return Err(SettingsError::BadToggle),
+                }
+            }
+            "seen" => {
+                self.hide_seen = match value.to_lowercase().as_str() {
+                    "show" | "default" => false,
+                    "hide" => true,
+                    _ => return Err(SettingsError::BadSeenMode),
+                }
+            }
+            "tz" if reset => self.utc_offset_minutes = None,
+            "tz" => {
+                let offset = parse_utc_offset(value).ok_or(SettingsError::BadOffset)?;
+                self.utc_offset_minutes = Some(offset.local_minus_utc() / 60);
+            }
+            _ => return Err(SettingsError::UnknownKey),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let default = |set: bool| if set { "" } else { " (default)" };
+        writeln!(f, "lang: {}{}", self.lang().unwrap_or_else(limits::translate_target), default(self.lang.is_some()))?;
+        writeln!(
+            f,
+            "items: {}{}",
+            self.items.unwrap_or(limits::MAX_ITEMS_PER_SOURCE),
+            default(self.items.is_some())
+        )?;
+        writeln!(f, "desc: {}", if self.hide_descriptions { "off" } else { "on" })?;
+        writeln!(f, "seen: {}", if self.hide_seen { "hide" } else { "show" })?;
+        write!(f, "tz: UTC{}{}", self.render_options().offset, default(self.utc_offset_minutes.is_some()))
+    }
+}
+
+/// Per-chat settings, persisted across restarts
+pub struct ChatSettings {
+    chats: Mutex<HashMap<i64, Settings>>,
+    store: Arc<dyn Store>,
+    /// Version of the latest change, bumped under the `chats` lock
+    taken: AtomicU64,
+    /// Version stored per chat; a write that lost the race to a newer one is skipped
+    written: Mutex<HashMap<i64, u64>>,
+}
+
+impl ChatSettings {
+    pub fn load(store: Arc<dyn Store>) -> Self {
+        let chats = store.chats().unwrap_or_else(|e| {
+            log::error!("Failed to load chat settings: {}", e);
+            HashMap::new()
+        });
+        Self { chats: Mutex::new(chats), store, taken: AtomicU64::new(0), written: Mutex::default() }
+    }
+
+    pub fn get(&self, chat_id: ChatId) -> Settings {
+        self.chats.lock().unwrap().get(&chat_id.0).cloned().unwrap_or_default()
+    }
+
+    /// Every chat's price alerts, for the alert poller
+    pub fn alerts(&self) -> Vec<(ChatId, PriceAlert)> {
+        self.chats
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|(chat, settings)| settings.alerts.iter().map(|a| (ChatId(*chat), a.clone())))
+            .collect()
+    }
+
+    /// Every chat's keyword alerts, for the keyword poller
+    pub fn keyword_alerts(&self) -> Vec<(ChatId, KeywordAlert)> {
+        self.chats
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|(chat, settings)| settings.keyword_alerts.iter().map(|a| (ChatId(*chat), a.clone())))
+            .collect()
+    }
+
+    /// Chats with a profile on a schedule, for the digest task
+    pub fn scheduled_profiles(&self) -> Vec<(ChatId, Vec<Profile>, Option<DateTime<Utc>>)> {
+        self.chats
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, settings)| settings.profiles.iter().any(|p| p.at.is_some()))
+            .map(|(chat, settings)| (ChatId(*chat), settings.profiles.clone(), settings.profile_switched))
+            .collect()
+    }
+
+    /// Every chat's daily digests, for the digest task
+    pub fn digests(&self) -> Vec<(ChatId, Digest)> {
+        self.chats
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|(chat, settings)| settings.digests.iter().map(|d| (ChatId(*chat), d.clone())))
+            .collect()
+    }
+
+    /// Every chat's subscriptions, for the delivery task
+    pub fn subscriptions(&self) -> Vec<(ChatId, Subscription)> {
+        self.chats
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|(chat, settings)| settings.subscriptions.iter().map(|s| (ChatId(*chat), s.clone())))
+            .collect()
+    }
+
+    /// Change a chat's settings and persist them, returning the result. The store is
+    /// written after releasing the lock.
+    pub fn update<E>(&self, chat_id: ChatId, change: impl FnOnce(&mut Settings) -> Result<(), E>) -> Result<Settings, E> {
+        let mut chats = self.chats.lock().unwrap();
+        let settings = chats.entry(chat_id.0).or_default();
+        change(settings)?;
+        let updated = settings.clone();
+        let version = self.taken.fetch_add(1, Ordering::Relaxed) + 1;
+        drop(chats);
+
+        let mut written = self.written.lock().unwrap();
+        let stored = written.entry(chat_id.0).or_default();
+        if version > *stored {
+            if let Err(e) = self.store.save_chat(chat_id.0, &updated) {
+                log::error!("Failed to persist settings of {}: {}", chat_id, e);
+            }
+            *stored = version;
+        }
+        Ok(updated)
+    }
+}
+
+const SEEN_FILE: &str = "seen_items.json";
+
+/// When a chat was last shown an item
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SeenEntry {
+    fingerprint: u64,
+    at: i64,
+}
+
+/// Fingerprints of the items each chat was shown, for 🆕 marks; bounded per chat and expiring.
+/// Changes are written by `run_flush` in the background, never on the command path.
+pub struct SeenItems {
+    chats: Mutex<HashMap<i64, VecDeque<SeenEntry>>>,
+    /// Changed since the last flush
+    dirty: AtomicBool,
+}
+
+impl SeenItems {
+    pub fn load() -> Self {
+        Self { chats: Mutex::new(load_json(SEEN_FILE).unwrap_or_default()), dirty: AtomicBool::new(false) }
+    }
+
+    /// Record `fingerprints` as shown to the chat and return the ones it had seen before.
+    /// `None` on the chat's first fetch, when marking everything as new would only be noise.
+    pub fn mark(&self, chat_id: ChatId, fingerprints: &[u64]) -> Option<HashSet<u64>> {
+        self.mark_at(chat_id, fingerprints, Utc::now())
+    }
+
+    fn mark_at(&self, chat_id: ChatId, fingerprints: &[u64], now: DateTime<Utc>) -> Option<HashSet<u64>> {
+        let now = now.timestamp();
+        let cutoff = now - limits::SEEN_TTL_HOURS * 3600;
+        let shown: HashSet<u64> = fingerprints.iter().copied().collect();
+
+        let mut chats = self.chats.lock().unwrap();
+        let first = !chats.contains_key(&chat_id.0);
+        let entries = chats.entry(chat_id.0).or_default();
+        entries.retain(|e| e.at > cutoff);
+        let seen: HashSet<u64> = entries.iter().map(|e| e.fingerprint).filter(|fp| shown.contains(fp)).collect();
+        // Re-add shown items at the back so the cap evicts the longest unseen first
+        entries.retain(|e| !shown.contains(&e.fingerprint));
+        entries.extend(shown.into_iter().map(|fingerprint| SeenEntry { fingerprint, at: now }));
+        while entries.len() > limits::SEEN_PER_CHAT {
+            entries.pop_front();
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        (!first).then_some(seen)
+    }
+
+    /// Forget everything the chat has seen, so the next fetch marks every item new
+    pub fn reset(&self, chat_id: ChatId) {
+        self.chats.lock().unwrap().insert(chat_id.0, VecDeque::new());
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Write the seen items if they changed since the last flush. Serialized compactly under
+    /// the lock; the write itself runs on the blocking pool.
+    pub async fn flush(&self) {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let snapshot = serde_json::to_vec(&*self.chats.lock().unwrap());
+        match snapshot {
+            Ok(bytes) => {
+                if let Err(e) = tokio::task::spawn_blocking(move || write_json(SEEN_FILE, &bytes)).await {
+                    log::error!("Failed to persist {}: {}", SEEN_FILE, e);
+                }
+            }
+            Err(e) => log::error!("Failed to serialize seen items: {}", e),
+        }
+    }
+
+    /// Flush every SEEN_FLUSH_SECS for the life of the bot
+    pub async fn run_flush(self: Arc<Self>) {
+        let mut tick = tokio::time::interval(Duration::from_secs(limits::SEEN_FLUSH_SECS));
+        loop {
+            tick.tick().await;
+            self.flush().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admin_ids_parse_leniently() {
+        let admins = Admins::parse(" 123, 456 ,,789 ");
+        assert_eq!(admins.count(), 3);
+        assert!(admins.is_admin(Some(UserId(456))));
+        assert!(!admins.is_admin(Some(UserId(1))));
+        assert!(!admins.is_admin(None));
+
+        // Bad entries are dropped, the rest still count
+        let admins = Admins::parse("42,alice,-7,12.5, 99999999999999999999,43");
+        let mut ids: Vec<u64> = admins.ids().map(|id| id.0).collect();
+        ids.sort();
+        assert_eq!(ids, [42, 43]);
+
+        assert_eq!(Admins::parse("").count(), 0);
+        assert_eq!(Admins::parse(" , ").count(), 0);
+        assert!(!Admins::parse("").is_admin(Some(UserId(0))));
+    }
+
+    #[test]
+    fn diagnostics_toggle_per_chat() {
+        let admins = Admins::parse("1");
+        assert!(admins.toggle_diagnostics(ChatId(5)));
+        assert!(admins.diagnostics_enabled(ChatId(5)));
+        assert!(!admins.diagnostics_enabled(ChatId(6)));
+        assert!(!admins.toggle_diagnostics(ChatId(5)));
+        assert!(!admins.diagnostics_enabled(ChatId(5)));
+    }
+
+    #[test]
+    fn maintenance_survives_a_restart() {
+        let maintenance = Maintenance::load();
+        maintenance.set(true);
+        assert!(Maintenance::load().is_on());
+        maintenance.set(false);
+        assert!(!Maintenance::load().is_on());
+    }
+
+    #[test]
+    fn settings_validate_their_values() {
+        let mut settings = Settings::default();
+        settings.set("lang", "EN").unwrap();
+        assert_eq!(settings.lang(), Some("en"));
+        assert!(matches!(settings.set("lang", "xx"), Err(SettingsError::UnknownLanguage(code)) if code == "xx"));
+        settings.set("lang", "default").unwrap();
+        assert_eq!(settings.lang, None);
+
+        settings.set("items", "1").unwrap();
+        settings.set("items", &limits::MAX_ITEMS_CAP.to_string()).unwrap();
+        assert_eq!(settings.items, Some(limits::MAX_ITEMS_CAP));
+        for bad in ["0", "21", "-1", "many"] {
+            assert!(matches!(settings.set("items", bad), Err(SettingsError::ItemsOutOfRange)), "{}", bad);
+        }
+        assert_eq!(settings.items, Some(limits::MAX_ITEMS_CAP));
+
+        settings.set("desc", "off").unwrap();
+        assert!(settings.hide_descriptions);
+        assert!(matches!(settings.set("desc", "maybe"), Err(SettingsError::BadToggle)));
+        assert!(matches!(settings.set("seen", "off"), Err(SettingsError::BadSeenMode)));
+        settings.set("tz", "+05:30").unwrap();
+        assert_eq!(settings.utc_offset_minutes, Some(330));
+        assert!(matches!(settings.set("tz", "+15"), Err(SettingsError::BadOffset)));
+        assert!(matches!(settings.set("colour", "red"), Err(SettingsError::UnknownKey)));
+    }
+
+    #[test]
+    fn price_and_keyword_alerts_share_numbering_and_the_limit() {
+        let mut settings = Settings::default();
+        let gold = PriceAlert { source: "Gold".into(), direction: Direction::Above, threshold: 2700.0 };
+        let strike = KeywordAlert { command: "war".into(), words: "strike".into() };
+        settings.add_keyword_alert(strike.clone()).unwrap();
+        settings.add_alert(gold).unwrap();
+        settings.add_keyword_alert(strike.clone()).unwrap();
+        assert_eq!(settings.alert_lines(), ["Gold above 2700", "'strike' in /war"]);
+
+        assert!(!settings.remove_alert(0));
+        assert!(!settings.remove_alert(3));
+        assert!(settings.remove_alert(1));
+        assert_eq!(settings.alert_lines(), ["'strike' in /war"]);
+
+        for n in 1..limits::MAX_ALERTS_PER_CHAT {
+            settings.add_alert(PriceAlert { source: "Oil".into(), direction: Direction::Below, threshold: n as f64 }).unwrap();
+        }
+        let cut = KeywordAlert { command: "market".into(), words: "rate cut".into() };
+        assert!(matches!(settings.add_keyword_alert(cut), Err(SettingsError::TooManyAlerts)));
+        assert!(settings.remove_alert(limits::MAX_ALERTS_PER_CHAT));
+        assert!(settings.keyword_alerts.is_empty());
+    }
+
+    #[test]
+    fn supported_languages_are_matched_case_insensitively() {
+        assert_eq!(supported_language(" UK "), Some("uk"));
+        assert_eq!(supported_language("pt"), None);
+        assert_eq!(supported_language(""), None);
+    }
+
+    #[test]
+    fn settings_updates_are_persisted() {
+        let store: Arc<dyn Store> = Arc::new(crate::store::MemoryStore::default());
+        let settings = ChatSettings::load(Arc::clone(&store));
+        let updated = settings.update(ChatId(42), |s| s.set("items", "7")).unwrap();
+        assert_eq!(updated.items, Some(7));
+        assert!(settings.update(ChatId(42), |s| s.set("items", "70")).is_err());
+        settings.update(ChatId(42), |s| s.subscribe("war", 30)).unwrap();
+        let reloaded = ChatSettings::load(store);
+        assert_eq!(reloaded.get(ChatId(42)).items, Some(7));
+        assert_eq!(reloaded.subscriptions(), [(ChatId(42), Subscription { command: "war".into(), interval_minutes: 30 })]);
+    }
+
+
+    fn hours_later(hours: i64) -> DateTime<Utc> {
+        use chrono::TimeZone;
+        Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap() + chrono::Duration::hours(hours)
+    }
+
+    #[test]
+    fn first_fetch_marks_nothing_then_repeats_are_seen() {
+        let seen = SeenItems { chats: Mutex::default(), dirty: AtomicBool::new(false) };
+        assert_eq!(seen.mark_at(ChatId(1), &[1, 2], hours_later(0)), None);
+        assert_eq!(seen.mark_at(ChatId(1), &[2, 3], hours_later(1)), Some(HashSet::from([2])));
+        // Another chat starts from scratch
+        assert_eq!(seen.mark_at(ChatId(2), &[2], hours_later(1)), None);
+        seen.reset(ChatId(1));
+        assert_eq!(seen.mark_at(ChatId(1), &[2, 3], hours_later(2)), Some(HashSet::new()));
+    }
+
+    #[test]
+    fn seen_items_expire_after_the_ttl() {
+        let seen = SeenItems { chats: Mutex::default(), dirty: AtomicBool::new(false) };
+        seen.mark_at(ChatId(1), &[1], hours_later(0));
+        seen.mark_at(ChatId(1), &[2], hours_later(limits::SEEN_TTL_HOURS - 1));
+        let again = seen.mark_at(ChatId(1), &[1, 2], hours_later(limits::SEEN_TTL_HOURS + 1));
+        assert_eq!(again, Some(HashSet::from([2])));
+    }
+
+    #[test]
+    fn seen_items_are_capped_per_chat() {
+        let seen = SeenItems { chats: Mutex::default(), dirty: AtomicBool::new(false) };
+        let cap = limits::SEEN_PER_CHAT as u64;
+        seen.mark_at(ChatId(1), &[0], hours_later(0));
+        let batch: Vec<u64> = (1..=cap).collect();
+        seen.mark_at(ChatId(1), &batch, hours_later(1));
+        assert_eq!(seen.chats.lock().unwrap()[&1].len(), limits::SEEN_PER_CHAT);
+        // The oldest entry made room
+        assert_eq!(seen.mark_at(ChatId(1), &[0, cap], hours_later(2)), Some(HashSet::from([cap])));
+    }
+
+    #[tokio::test]
+    async fn flush_writes_only_changes() {
+        let seen = SeenItems::load();
+        seen.mark(ChatId(9), &[7]);
+        seen.flush().await;
+        assert!(!seen.dirty.load(Ordering::Relaxed));
+        assert_eq!(SeenItems::load().mark(ChatId(9), &[7]), Some(HashSet::from([7])));
+    }
+
+}
@@ -0,0 +1,49 @@
+//! Small JSON persistence under DATA_DIR (default ./data)
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+pub fn data_dir() -> PathBuf {
+    // Tests persist into a scratch directory of their own, never the bot's data
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("logos_bot_test_{}", std::process::id()));
+    }
+    PathBuf::from(std::env::var("DATA_DIR").unwrap_or_else(|_| "data".to_string()))
+}
+
+/// Read a JSON document, `None` when missing or unreadable
+pub fn load_json<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let raw = fs::read_to_string(data_dir().join(name)).ok()?;
+    match serde_json::from_str(&raw) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            log::warn!("Ignoring corrupt {}: {}", name, e);
+            None
+        }
+    }
+}
+
+/// Write a JSON document atomically (temp file + rename)
+pub fn save_json<T: Serialize>(name: &str, value: &T) {
+    match serde_json::to_vec_pretty(value) {
+        Ok(bytes) => write_json(name, &bytes),
+        Err(e) => log::error!("Failed to persist {}: {}", name, e),
+    }
+}
+
+/// Write an already serialized document atomically, so it can be encoded under a lock
+/// and written after releasing it
+pub fn write_json(name: &str, bytes: &[u8]) {
+    let dir = data_dir();
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| {
+            let tmp = dir.join(format!("{}.tmp", name));
+            fs::write(&tmp, bytes)?;
+            fs::rename(tmp, dir.join(name))
+        });
+    if let Err(e) = result {
+        log::error!("Failed to persist {}: {}", name, e);
+    }
+}
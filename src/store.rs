@@ -0,0 +1,514 @@
+//! SQLite persistence for chat preferences, subscriptions and custom sources, behind the
+//! `Store` trait so tests can run on memory
+
+use crate::state::{Digest, Direction, KeywordAlert, PriceAlert, Profile, Settings, Subscription};
+use crate::storage::{data_dir, load_json};
+use chrono::{DateTime, NaiveTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Database under DATA_DIR
+pub const DB_FILE: &str = "logos_bot.db";
+
+/// JSON documents the store replaced, imported once when the database is created
+const LEGACY_SETTINGS_FILE: &str = "chat_settings.json";
+/// Written by /lang before /settings existed
+const LEGACY_LANGUAGES_FILE: &str = "chat_languages.json";
+const LEGACY_SOURCES_FILE: &str = "custom_sources.json";
+
+/// Schema steps in order; `PRAGMA user_version` counts the ones applied
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE chats (
+        chat_id INTEGER PRIMARY KEY,
+        lang TEXT,
+        items INTEGER,
+        hide_descriptions INTEGER NOT NULL DEFAULT 0,
+        hide_seen INTEGER NOT NULL DEFAULT 0,
+        utc_offset_minutes INTEGER
+    );
+    CREATE TABLE subscriptions (
+        chat_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        interval_minutes INTEGER NOT NULL,
+        PRIMARY KEY (chat_id, position)
+    );
+    CREATE TABLE digests (
+        chat_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        at TEXT NOT NULL,
+        last_sent TEXT,
+        PRIMARY KEY (chat_id, position)
+    );
+    CREATE TABLE alerts (
+        chat_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        source TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        threshold REAL NOT NULL,
+        PRIMARY KEY (chat_id, position)
+    );
+    CREATE TABLE custom_sources (
+        name TEXT PRIMARY KEY,
+        url TEXT NOT NULL
+    );
+", "
+    ALTER TABLE custom_sources ADD COLUMN auth TEXT;
+", "
+    CREATE TABLE keyword_alerts (
+        chat_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        words TEXT NOT NULL,
+        PRIMARY KEY (chat_id, position)
+    );
+", "
+    CREATE TABLE profiles (
+        chat_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        commands TEXT NOT NULL,
+        at TEXT,
+        PRIMARY KEY (chat_id, position)
+    );
+    ALTER TABLE chats ADD COLUMN active_profile TEXT;
+    ALTER TABLE chats ADD COLUMN profile_switched TEXT;
+"];
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("database: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("unreadable {0} in the database")]
+    Corrupt(&'static str),
+}
+
+/// A feed added with /addsource
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomSource {
+    pub name: String,
+    pub url: String,
+    /// `Auth::spec` of a private feed: env var names, never credentials
+    #[serde(default)]
+    pub auth: Option<String>,
+}
+
+/// Where chat settings and custom sources live between restarts
+pub trait Store: Send + Sync {
+    /// Every chat's settings, subscriptions, digests and alerts
+    fn chats(&self) -> Result<HashMap<i64, Settings>, StoreError>;
+    /// Replace everything stored for one chat
+    fn save_chat(&self, chat_id: i64, settings: &Settings) -> Result<(), StoreError>;
+    /// Custom sources in the order they were added
+    fn custom_sources(&self) -> Result<Vec<CustomSource>, StoreError>;
+    fn save_custom_source(&self, source: &CustomSource) -> Result<(), StoreError>;
+}
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Open the database under DATA_DIR, creating it and importing the JSON files it
+    /// replaced on first run
+    pub fn open_default() -> Result<Self, StoreError> {
+        let dir = data_dir();
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            log::warn!("Can't create {}: {}", dir.display(), e);
+        }
+        Self::open(&dir.join(DB_FILE))
+    }
+
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        Self::with_connection(Connection::open(path)?, true)
+    }
+
+    /// A fresh database that lives as long as the store, without the JSON import
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, StoreError> {
+        Self::with_connection(Connection::open_in_memory()?, false)
+    }
+
+    fn with_connection(mut conn: Connection, import: bool) -> Result<Self, StoreError> {
+        let created = migrate(&mut conn)? == 0;
+        let store = Self { conn: Mutex::new(conn) };
+        if created && import {
+            store.import_legacy()?;
+        }
+        Ok(store)
+    }
+
+    /// Copy chat settings and custom sources from the JSON files used before the database.
+    /// The files are left in place as a backup.
+    fn import_legacy(&self) -> Result<(), StoreError> {
+        let chats = load_json::<HashMap<i64, Settings>>(LEGACY_SETTINGS_FILE).or_else(|| {
+            load_json::<HashMap<i64, String>>(LEGACY_LANGUAGES_FILE).map(|languages| {
+                languages
+                    .into_iter()
+                    .map(|(chat, lang)| (chat, Settings { lang: Some(lang), ..Settings::default() }))
+                    .collect()
+            })
+        });
+        let sources = load_json::<Vec<CustomSource>>(LEGACY_SOURCES_FILE).unwrap_or_default();
+        let imported = chats.as_ref().map_or(0, HashMap::len);
+        for (chat_id, settings) in chats.into_iter().flatten() {
+            self.save_chat(chat_id, &settings)?;
+        }
+        for source in &sources {
+            self.save_custom_source(source)?;
+        }
+        if imported + sources.len() > 0 {
+            log::info!("Imported {} chats and {} custom sources into {}", imported, sources.len(), DB_FILE);
+        }
+        Ok(())
+    }
+}
+
+/// Apply the migrations not yet in the database, returning the version it had before
+fn migrate(conn: &mut Connection) -> Result<usize, StoreError> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let tx = conn.transaction()?;
+    for (step, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", step + 1)?;
+    }
+    tx.commit()?;
+    if version < MIGRATIONS.len() {
+        log::info!("Database schema migrated from version {} to {}", version, MIGRATIONS.len());
+    }
+    Ok(version)
+}
+
+fn parse_time(text: &str, what: &'static str) -> Result<NaiveTime, StoreError> {
+    text.parse().map_err(|_| StoreError::Corrupt(what))
+}
+
+fn parse_instant(text: &str, what: &'static str) -> Result<DateTime<Utc>, StoreError> {
+    Ok(DateTime::parse_from_rfc3339(text).map_err(|_| StoreError::Corrupt(what))?.with_timezone(&Utc))
+}
+
+fn parse_direction(text: &str) -> Result<Direction, StoreError> {
+    match text {
+        "above" => Ok(Direction::Above),
+        "below" => Ok(Direction::Below),
+        _ => Err(StoreError::Corrupt("alert direction")),
+    }
+}
+
+impl Store for SqliteStore {
+    fn chats(&self) -> Result<HashMap<i64, Settings>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let mut chats = HashMap::new();
+        let mut rows = conn.prepare(
+            "SELECT chat_id, lang, items, hide_descriptions, hide_seen, utc_offset_minutes, active_profile, profile_switched FROM chats",
+        )?;
+        let settings = rows.query_map([], |row| {
+            let items: Option<i64> = row.get(2)?;
+            Ok((
+                row.get::<_, i64>(0)?,
+                Settings {
+                    lang: row.get(1)?,
+                    items: items.map(|n| n as usize),
+                    hide_descriptions: row.get(3)?,
+                    hide_seen: row.get(4)?,
+                    utc_offset_minutes: row.get(5)?,
+                    active_profile: row.get(6)?,
+                    ..Settings::default()
+                },
+                row.get::<_, Option<String>>(7)?,
+            ))
+        })?;
+        for row in settings {
+            let (chat_id, mut settings, switched) = row?;
+            settings.profile_switched = switched.map(|t| parse_instant(&t, "profile switch time")).transpose()?;
+            chats.insert(chat_id, settings);
+        }
+
+        let mut rows = conn.prepare("SELECT chat_id, command, interval_minutes FROM subscriptions ORDER BY chat_id, position")?;
+        let subscriptions = rows.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, Subscription { command: row.get(1)?, interval_minutes: row.get(2)? }))
+        })?;
+        for row in subscriptions {
+            let (chat_id, subscription) = row?;
+            chats.entry(chat_id).or_default().subscriptions.push(subscription);
+        }
+
+        let mut rows = conn.prepare("SELECT chat_id, command, at, last_sent FROM digests ORDER BY chat_id, position")?;
+        let digests = rows.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, Option<String>>(3)?))
+        })?;
+        for row in digests {
+            let (chat_id, command, at, last_sent) = row?;
+            let at = parse_time(&at, "digest time")?;
+            let last_sent = last_sent.map(|sent| parse_instant(&sent, "digest send time")).transpose()?;
+            chats.entry(chat_id).or_default().digests.push(Digest { command, at, last_sent });
+        }
+
+        let mut rows = conn.prepare("SELECT chat_id, source, direction, threshold FROM alerts ORDER BY chat_id, position")?;
+        let alerts = rows.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, f64>(3)?))
+        })?;
+        for row in alerts {
+            let (chat_id, source, direction, threshold) = row?;
+            let alert = PriceAlert { source, direction: parse_direction(&direction)?, threshold };
+            chats.entry(chat_id).or_default().alerts.push(alert);
+        }
+
+        let mut rows = conn.prepare("SELECT chat_id, command, words FROM keyword_alerts ORDER BY chat_id, position")?;
+        let alerts = rows.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, KeywordAlert { command: row.get(1)?, words: row.get(2)? }))
+        })?;
+        for row in alerts {
+            let (chat_id, alert) = row?;
+            chats.entry(chat_id).or_default().keyword_alerts.push(alert);
+        }
+
+        let mut rows = conn.prepare("SELECT chat_id, name, commands, at FROM profiles ORDER BY chat_id, position")?;
+        let profiles = rows.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, Option<String>>(3)?))
+        })?;
+        for row in profiles {
+            let (chat_id, name, commands, at) = row?;
+            let commands = commands.split(',').map(str::to_string).collect();
+            let at = at.map(|at| parse_time(&at, "profile time")).transpose()?;
+            chats.entry(chat_id).or_default().profiles.push(Profile { name, commands, at });
+        }
+        Ok(chats)
+    }
+
+    fn save_chat(&self, chat_id: i64, settings: &Settings) -> Result<(), StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO chats (chat_id, lang, items, hide_descriptions, hide_seen, utc_offset_minutes, active_profile, profile_switched)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT (chat_id) DO UPDATE SET lang = ?2, items = ?3, hide_descriptions = ?4, hide_seen = ?5,
+                 utc_offset_minutes = ?6, active_profile = ?7, profile_switched = ?8",
+            params![
+                chat_id,
+                settings.lang,
+                settings.items.map(|n| n as i64),
+                settings.hide_descriptions,
+                settings.hide_seen,
+                settings.utc_offset_minutes,
+                settings.active_profile,
+                settings.profile_switched.map(|t| t.to_rfc3339())
+            ],
+        )?;
+        for table in ["subscriptions", "digests", "alerts", "keyword_alerts", "profiles"] {
+            tx.execute(&format!("DELETE FROM {} WHERE chat_id = ?1", table), [chat_id])?;
+        }
+        for (position, s) in settings.subscriptions.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO subscriptions (chat_id, position, command, interval_minutes) VALUES (?1, ?2, ?3, ?4)",
+                params![chat_id, position, s.command, s.interval_minutes],
+            )?;
+        }
+        for (position, d) in settings.digests.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO digests (chat_id, position, command, at, last_sent) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![chat_id, position, d.command, d.at.to_string(), d.last_sent.map(|t| t.to_rfc3339())],
+            )?;
+        }
+        for (position, a) in settings.alerts.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO alerts (chat_id, position, source, direction, threshold) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![chat_id, position, a.source, a.direction.to_string(), a.threshold],
+            )?;
+        }
+        for (position, a) in settings.keyword_alerts.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO keyword_alerts (chat_id, position, command, words) VALUES (?1, ?2, ?3, ?4)",
+                params![chat_id, position, a.command, a.words],
+            )?;
+        }
+        for (position, p) in settings.profiles.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO profiles (chat_id, position, name, commands, at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![chat_id, position, p.name, p.commands.join(","), p.at.map(|at| at.to_string())],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn custom_sources(&self) -> Result<Vec<CustomSource>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let mut rows = conn.prepare("SELECT name, url, auth FROM custom_sources ORDER BY rowid")?;
+        let sources = rows.query_map([], |row| Ok(CustomSource { name: row.get(0)?, url: row.get(1)?, auth: row.get(2)? }))?;
+        Ok(sources.collect::<Result<_, _>>()?)
+    }
+
+    fn save_custom_source(&self, source: &CustomSource) -> Result<(), StoreError> {
+        // An upsert keeps the rowid, so a re-added source stays in its place
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO custom_sources (name, url, auth) VALUES (?1, ?2, ?3)
+             ON CONFLICT (name) DO UPDATE SET url = excluded.url, auth = excluded.auth",
+            params![source.name, source.url, source.auth],
+        )?;
+        Ok(())
+    }
+}
+
+/// Store kept in memory, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    chats: Mutex<HashMap<i64, Settings>>,
+    sources: Mutex<Vec<CustomSource>>,
+}
+
+#[cfg(test)]
+impl Store for MemoryStore {
+    fn chats(&self) -> Result<HashMap<i64, Settings>, StoreError> {
+        Ok(self.chats.lock().unwrap().clone())
+    }
+
+    fn save_chat(&self, chat_id: i64, settings: &Settings) -> Result<(), StoreError> {
+        self.chats.lock().unwrap().insert(chat_id, settings.clone());
+        Ok(())
+    }
+
+    fn custom_sources(&self) -> Result<Vec<CustomSource>, StoreError> {
+        Ok(self.sources.lock().unwrap().clone())
+    }
+
+    fn save_custom_source(&self, source: &CustomSource) -> Result<(), StoreError> {
+        let mut sources = self.sources.lock().unwrap();
+        match sources.iter_mut().find(|s| s.name == source.name) {
+            Some(existing) => *existing = source.clone(),
+            None => sources.push(source.clone()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::save_json;
+    use chrono::TimeZone;
+
+    fn stores() -> Vec<Box<dyn Store>> {
+        vec![Box::new(SqliteStore::open_in_memory().unwrap()), Box::new(MemoryStore::default())]
+    }
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn chat_preferences_round_trip() {
+        let prefs = Settings {
+            lang: Some("uk".into()),
+            items: Some(7),
+            hide_descriptions: true,
+            hide_seen: true,
+            utc_offset_minutes: Some(-210),
+            ..Settings::default()
+        };
+        for store in stores() {
+            store.save_chat(-100123, &prefs).unwrap();
+            store.save_chat(5, &Settings::default()).unwrap();
+            let chats = store.chats().unwrap();
+            assert_eq!(chats[&-100123], prefs);
+            assert_eq!(chats[&5], Settings::default());
+            // Saving again replaces, never duplicates
+            let cleared = Settings { lang: None, ..prefs.clone() };
+            store.save_chat(-100123, &cleared).unwrap();
+            assert_eq!(store.chats().unwrap()[&-100123], cleared);
+        }
+    }
+
+    #[test]
+    fn subscriptions_digests_and_alerts_round_trip() {
+        let sent = Utc.with_ymd_and_hms(2030, 3, 10, 5, 30, 0).unwrap();
+        let mut settings = Settings::default();
+        settings.subscribe("war", 30).unwrap();
+        settings.subscribe("tass", 1440).unwrap();
+        settings.add_digest("market", time(8, 0)).unwrap();
+        settings.digests[0].last_sent = Some(sent);
+        settings.add_digest("global", time(23, 45)).unwrap();
+        settings.add_alert(PriceAlert { source: "Gold".into(), direction: Direction::Above, threshold: 2700.5 }).unwrap();
+        settings.add_alert(PriceAlert { source: "Oil".into(), direction: Direction::Below, threshold: 65.0 }).unwrap();
+        settings.add_keyword_alert(KeywordAlert { command: "war".into(), words: "strike".into() }).unwrap();
+        settings.add_keyword_alert(KeywordAlert { command: "market".into(), words: "rate cut".into() }).unwrap();
+        for store in stores() {
+            store.save_chat(42, &settings).unwrap();
+            assert_eq!(store.chats().unwrap()[&42], settings);
+
+            let mut fewer = settings.clone();
+            fewer.unsubscribe("war");
+            fewer.alerts.clear();
+            assert!(fewer.remove_alert(1));
+            store.save_chat(42, &fewer).unwrap();
+            assert_eq!(store.chats().unwrap()[&42], fewer);
+        }
+    }
+
+    #[test]
+    fn profiles_and_the_active_one_round_trip() {
+        let mut settings = Settings::default();
+        let night = Profile { name: "night".into(), commands: vec!["war".into(), "liveuamap".into()], at: Some(time(22, 0)) };
+        settings.add_profile(night).unwrap();
+        settings.add_profile(Profile { name: "travel".into(), commands: vec!["global".into()], at: None }).unwrap();
+        settings.switch_profile("night", Utc.with_ymd_and_hms(2030, 3, 10, 22, 0, 30).unwrap()).unwrap();
+        for store in stores() {
+            store.save_chat(42, &settings).unwrap();
+            assert_eq!(store.chats().unwrap()[&42], settings);
+
+            let mut fewer = settings.clone();
+            assert!(fewer.remove_profile("night"));
+            store.save_chat(42, &fewer).unwrap();
+            let loaded = &store.chats().unwrap()[&42];
+            assert_eq!(*loaded, fewer);
+            assert_eq!(loaded.active_profile, None);
+            assert_eq!(loaded.subscriptions.len(), 2);
+        }
+    }
+
+    #[test]
+    fn custom_sources_round_trip_in_order() {
+        let source = |name: &str, url: &str, auth: Option<&str>| CustomSource { name: name.into(), url: url.into(), auth: auth.map(str::to_string) };
+        for store in stores() {
+            store.save_custom_source(&source("zeta", "https://example.com/z.xml", None)).unwrap();
+            store.save_custom_source(&source("alpha", "https://example.com/a.xml", Some("bearer:ALPHA_TOKEN"))).unwrap();
+            store.save_custom_source(&source("zeta", "https://example.com/z2.xml", Some("basic:Z_USER:Z_PASS"))).unwrap();
+            assert_eq!(store.custom_sources().unwrap(), [
+                source("zeta", "https://example.com/z2.xml", Some("basic:Z_USER:Z_PASS")),
+                source("alpha", "https://example.com/a.xml", Some("bearer:ALPHA_TOKEN")),
+            ]);
+        }
+    }
+
+    #[test]
+    fn first_run_creates_the_schema_and_imports_json() {
+        let dir = data_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("first_run.db");
+        let _ = std::fs::remove_file(&path);
+        let mut legacy = Settings { lang: Some("de".into()), ..Settings::default() };
+        legacy.subscribe("war", 60).unwrap();
+        save_json(LEGACY_SETTINGS_FILE, &HashMap::from([(7i64, legacy.clone())]));
+        save_json(LEGACY_SOURCES_FILE, &[CustomSource { name: "mywire".into(), url: "https://example.com/rss".into(), auth: None }]);
+
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.chats().unwrap()[&7], legacy);
+        assert_eq!(store.custom_sources().unwrap().len(), 1);
+        let version: usize = store.conn.lock().unwrap().query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+
+        // Later starts keep what is in the database and never import again
+        store.save_chat(7, &Settings::default()).unwrap();
+        drop(store);
+        let reopened = SqliteStore::open(&path).unwrap();
+        assert_eq!(reopened.chats().unwrap()[&7], Settings::default());
+        assert_eq!(reopened.custom_sources().unwrap().len(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+}
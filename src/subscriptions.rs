@@ -0,0 +1,538 @@
+//! /subscribe delivery - polls each subscription on its interval and pushes only new items,
+//! sends daily digests at their time of day and switches /profile sets on their schedule
+
+use crate::consts::limits;
+use crate::format::{render_blocks, render_updates};
+use crate::logic::{build_summary, fetch_target, routes, FetchOptions, SourceResult};
+use crate::network::NewsItem;
+use crate::outgoing::{is_chat_gone, plan_blocks, plan_chunks, send_plan, SendOptions};
+use crate::state::{AppState, Digest, Profile, SettingsError};
+use crate::utils::fingerprint;
+use chrono::{DateTime, FixedOffset, NaiveTime, TimeZone, Utc};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use teloxide::prelude::*;
+
+/// Fingerprints remembered per subscription
+const SEEN_CAPACITY: usize = 500;
+
+/// Items a subscription or keyword alert already delivered (or saw on its first poll), oldest
+/// forgotten first
+#[derive(Default)]
+pub struct Seen {
+    set: HashSet<u64>,
+    order: VecDeque<u64>,
+}
+
+impl Seen {
+    /// True the first time a fingerprint is offered
+    pub fn insert(&mut self, fp: u64) -> bool {
+        if !self.set.insert(fp) {
+            return false;
+        }
+        self.order.push_back(fp);
+        if self.order.len() > SEEN_CAPACITY {
+            if let Some(old) = self.order.pop_front() {
+                self.set.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+/// Items not delivered before, newest first, recording them as delivered. Quotes are left out:
+/// a price is not news.
+fn fresh_items<'a>(results: &'a [SourceResult], delivered: &mut Seen) -> Vec<(&'a str, &'a NewsItem)> {
+    let mut fresh: Vec<(&str, &NewsItem)> = results
+        .iter()
+        .filter_map(|r| Some((r.source.name, r.outcome.as_ref().ok()?)))
+        .flat_map(|(name, items)| items.iter().map(move |item| (name, item)))
+        .filter(|(_, item)| item.quote.is_none() && delivered.insert(fingerprint(item)))
+        .collect();
+    fresh.sort_by_key(|(_, item)| std::cmp::Reverse(item.timestamp));
+    fresh
+}
+
+/// Runs for the life of the bot. Subscriptions are polled one at a time; the first poll after
+/// subscribing or a restart only records what is already there, so nothing old gets pushed.
+pub async fn run(bot: Bot, state: AppState) {
+    let mut next_due: HashMap<(ChatId, String), Instant> = HashMap::new();
+    let mut seen: HashMap<(ChatId, String), Seen> = HashMap::new();
+    let mut tick = tokio::time::interval(Duration::from_secs(limits::SUBSCRIPTION_TICK_SECS));
+    loop {
+        tick.tick().await;
+        let subscriptions = state.settings.subscriptions();
+        let active: HashSet<(ChatId, String)> = subscriptions.iter().map(|(chat, s)| (*chat, s.command.clone())).collect();
+        next_due.retain(|key, _| active.contains(key));
+        seen.retain(|key, _| active.contains(key));
+        if state.maintenance.is_on() {
+            continue;
+        }
+
+        for (chat_id, subscription) in subscriptions {
+            let key = (chat_id, subscription.command.clone());
+            let now = Instant::now();
+            if next_due.get(&key).is_some_and(|due| *due > now) {
+                continue;
+            }
+            next_due.insert(key.clone(), now + Duration::from_secs(subscription.interval_minutes as u64 * 60));
+            let Some(target) = routes::resolve_command(&subscription.command) else { continue };
+
+            let settings = state.settings.get(chat_id);
+            let options = FetchOptions { lang: settings.lang(), max_items: settings.items, ..FetchOptions::default() };
+            let Some(news) = fetch_target(Arc::clone(&state.engine), target, options).result().await else { continue };
+
+            let first_poll = !seen.contains_key(&key);
+            let fresh = fresh_items(&news.results, seen.entry(key).or_default());
+            if first_poll || fresh.is_empty() {
+                continue;
+            }
+
+            let title = format!("🔔 /{} · {} new", subscription.command, fresh.len());
+            let text = render_updates(&title, &fresh, &settings.render_options());
+            let plan = plan_chunks(&text, limits::MESSAGE_CHUNK_LEN, &SendOptions { preview_url: None, silent: true });
+            match send_plan(&bot, chat_id, plan).await {
+                Ok(()) => {}
+                Err(e) if is_chat_gone(&e) => {
+                    log::info!("Chat {} is gone, dropping its subscriptions", chat_id);
+                    let _ = state.settings.update(chat_id, |s| {
+                        s.subscriptions.clear();
+                        Ok::<_, ()>(())
+                    });
+                }
+                Err(e) => log::warn!("Subscription push to {} failed: {}", chat_id, e),
+            }
+        }
+    }
+}
+
+/// Where a daily digest stands at a given moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestDue {
+    /// Already sent for its latest slot
+    No,
+    /// The slot just passed
+    OnTime,
+    /// The slot was missed (downtime, a clock jump) but is inside the catch-up window
+    Delayed,
+    /// Missed by more than the catch-up window; the next slot is tomorrow's
+    Missed,
+}
+
+/// Latest slot of a digest scheduled daily at `at` (local time in `offset`) at or before `now`
+fn latest_slot(at: NaiveTime, offset: FixedOffset, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let today = now.with_timezone(&offset).date_naive();
+    [Some(today), today.pred_opt()]
+        .into_iter()
+        .flatten()
+        .filter_map(|day| offset.from_local_datetime(&day.and_time(at)).single())
+        .map(|slot| slot.with_timezone(&Utc))
+        .find(|slot| *slot <= now)
+}
+
+/// Whether a digest should go out at `now`. `last_sent` at or after the latest slot means
+/// it went out already, which also keeps a clock jumping back from firing it twice.
+pub fn digest_due(at: NaiveTime, offset: FixedOffset, now: DateTime<Utc>, last_sent: Option<DateTime<Utc>>) -> DigestDue {
+    let Some(slot) = latest_slot(at, offset, now) else { return DigestDue::No };
+    if last_sent.is_some_and(|sent| sent >= slot) {
+        return DigestDue::No;
+    }
+    match now - slot {
+        late if late < chrono::Duration::minutes(limits::DIGEST_GRACE_MINUTES) => DigestDue::OnTime,
+        late if late <= limits::digest_catchup() => DigestDue::Delayed,
+        _ => DigestDue::Missed,
+    }
+}
+
+/// A digest to send now
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestFire {
+    pub chat_id: ChatId,
+    pub command: String,
+    /// Sent late, labeled "(delayed)"
+    pub delayed: bool,
+}
+
+/// Picks the digests to send on each tick, logging each missed slot once. Digests that
+/// come due during maintenance are held, up to `DIGEST_QUEUE_CAP`, and sent when it is lifted.
+#[derive(Default)]
+pub struct DigestScheduler {
+    /// Last missed slot logged per chat and command
+    missed: HashMap<(ChatId, String), DateTime<Utc>>,
+    /// Digests held during maintenance with the slot they were due for, oldest first
+    held: VecDeque<(DigestFire, DateTime<Utc>)>,
+}
+
+impl DigestScheduler {
+    /// Digests to send at `now`, given every chat's digests and UTC offset. Nothing is sent
+    /// while `maintenance` is on; the first tick after it releases what was held.
+    pub fn tick(&mut self, digests: Vec<(ChatId, Digest, FixedOffset)>, now: DateTime<Utc>, maintenance: bool) -> Vec<DigestFire> {
+        let mut fire = Vec::new();
+        if !maintenance {
+            for (held, slot) in std::mem::take(&mut self.held) {
+                // Only digests still subscribed and not sent since
+                let pending = digests
+                    .iter()
+                    .any(|(chat_id, d, _)| *chat_id == held.chat_id && d.command == held.command && d.last_sent.is_none_or(|sent| sent < slot));
+                if pending {
+                    fire.push(DigestFire { delayed: true, ..held });
+                }
+            }
+        }
+
+        for (chat_id, digest, offset) in digests {
+            let is_key = |f: &DigestFire| f.chat_id == chat_id && f.command == digest.command;
+            if fire.iter().any(is_key) || self.held.iter().any(|(f, _)| is_key(f)) {
+                continue;
+            }
+            let delayed = match digest_due(digest.at, offset, now, digest.last_sent) {
+                DigestDue::No => continue,
+                DigestDue::OnTime => false,
+                DigestDue::Delayed => true,
+                DigestDue::Missed => {
+                    let slot = latest_slot(digest.at, offset, now);
+                    if let Some(slot) = slot.filter(|slot| self.missed.insert((chat_id, digest.command.clone()), *slot) != Some(*slot)) {
+                        log::info!("Skipping /{} digest for {}: its {} slot is past the catch-up window", digest.command, chat_id, slot);
+                    }
+                    continue;
+                }
+            };
+            let due = DigestFire { chat_id, command: digest.command, delayed };
+            if !maintenance {
+                fire.push(due);
+            } else if self.held.len() < limits::DIGEST_QUEUE_CAP {
+                let slot = latest_slot(digest.at, offset, now).unwrap_or(now);
+                self.held.push_back((due, slot));
+            } else {
+                log::warn!("Digest queue is full, /{} for {} waits for catch-up", due.command, chat_id);
+            }
+        }
+        fire
+    }
+}
+
+/// The profile whose start time passed last at `now`, when the chat has not switched since.
+/// A switch by hand after that start holds until the next one.
+pub fn scheduled_profile(profiles: &[Profile], offset: FixedOffset, now: DateTime<Utc>, switched: Option<DateTime<Utc>>) -> Option<&Profile> {
+    let (slot, profile) = profiles
+        .iter()
+        .filter_map(|p| Some((latest_slot(p.at?, offset, now)?, p)))
+        .max_by_key(|(slot, _)| *slot)?;
+    switched.is_none_or(|switched| switched < slot).then_some(profile)
+}
+
+/// Switch every chat whose profile schedule passed a start time, telling those whose
+/// profile changed. Missed starts are caught up: the profile is a state, not an event.
+async fn switch_profiles(bot: &Bot, state: &AppState, now: DateTime<Utc>) {
+    for (chat_id, profiles, switched) in state.settings.scheduled_profiles() {
+        let offset = state.settings.get(chat_id).render_options().offset;
+        let Some(profile) = scheduled_profile(&profiles, offset, now, switched) else { continue };
+        let mut changed = false;
+        let _ = state.settings.update(chat_id, |s| {
+            changed = s.active_profile.as_deref() != Some(profile.name.as_str());
+            if let Err(e) = s.switch_profile(&profile.name, now) {
+                log::warn!("Profile {} of {} not switched to: {}", profile.name, chat_id, e);
+                // Not retried every tick; the next start tries again
+                s.profile_switched = Some(now);
+                changed = false;
+            }
+            Ok::<_, SettingsError>(())
+        });
+        if changed {
+            let text = format!("🔁 Profile {} is on, see /profile status", profile);
+            if let Err(e) = bot.send_message(chat_id, text).disable_notification(true).await {
+                log::warn!("Profile switch notice to {} failed: {}", chat_id, e);
+            }
+        }
+    }
+}
+
+/// Runs for the life of the bot, sending each due digest once a day, catching up on
+/// recently missed ones after a restart and on those held back during maintenance.
+/// Scheduled profile switches share the tick.
+pub async fn run_digests(bot: Bot, state: AppState) {
+    let mut scheduler = DigestScheduler::default();
+    let mut tick = tokio::time::interval(Duration::from_secs(limits::SUBSCRIPTION_TICK_SECS));
+    loop {
+        tick.tick().await;
+        let digests = state
+            .settings
+            .digests()
+            .into_iter()
+            .map(|(chat_id, digest)| (chat_id, digest, state.settings.get(chat_id).render_options().offset))
+            .collect();
+        for fire in scheduler.tick(digests, Utc::now(), state.maintenance.is_on()) {
+            send_digest(&bot, &state, fire).await;
+        }
+        switch_profiles(&bot, &state, Utc::now()).await;
+    }
+}
+
+async fn send_digest(bot: &Bot, state: &AppState, fire: DigestFire) {
+    let chat_id = fire.chat_id;
+    let settings = state.settings.get(chat_id);
+    let view = settings.render_options();
+    let Some(target) = routes::resolve_command(&fire.command) else { return };
+    let options = FetchOptions { lang: settings.lang(), max_items: settings.items, ..FetchOptions::default() };
+    let Some(news) = fetch_target(Arc::clone(&state.engine), target, options).result().await else { return };
+    let mut blocks = render_blocks(&news, &view);
+    let label = if fire.delayed { " (delayed)" } else { "" };
+    blocks[0].insert_str(0, &format!("📰 Daily /{}{}\n", fire.command, label));
+    blocks.push(build_summary(&news));
+    let plan = plan_blocks(&blocks, limits::MESSAGE_CHUNK_LEN, &SendOptions::default());
+    match send_plan(bot, chat_id, plan).await {
+        Ok(()) => {}
+        Err(e) if is_chat_gone(&e) => {
+            log::info!("Chat {} is gone, dropping its digests", chat_id);
+            let _ = state.settings.update(chat_id, |s| {
+                s.digests.clear();
+                Ok::<_, ()>(())
+            });
+        }
+        Err(e) => log::warn!("Digest to {} failed: {}", chat_id, e),
+    }
+    // Marked once the attempt is over, so a fetch that never finished is retried next tick
+    // while one that was sent or failed is not repeated
+    let _ = state.settings.update(chat_id, |s| {
+        if let Some(d) = s.digests.iter_mut().find(|d| d.command == fire.command) {
+            d.last_sent = Some(Utc::now());
+        }
+        Ok::<_, ()>(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Quote;
+    use crate::state::Settings;
+
+    fn utc(day: u32, h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2030, 3, day, h, m, 0).unwrap()
+    }
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn digest(at: NaiveTime, last_sent: Option<DateTime<Utc>>) -> Vec<(ChatId, Digest, FixedOffset)> {
+        vec![(ChatId(7), Digest { command: "war".into(), at, last_sent }, FixedOffset::east_opt(0).unwrap())]
+    }
+
+    #[test]
+    fn restart_catches_up_once_with_a_label() {
+        let yesterday = Some(utc(9, 8, 31));
+        // Down from 08:00 to 09:45: the 08:30 digest goes out late
+        let mut scheduler = DigestScheduler::default();
+        let fired = scheduler.tick(digest(time(8, 30), yesterday), utc(10, 9, 45), false);
+        assert_eq!(fired, [DigestFire { chat_id: ChatId(7), command: "war".into(), delayed: true }]);
+        // Sent; a later tick and another restart see last_sent and stay quiet
+        let sent = Some(utc(10, 9, 46));
+        assert!(scheduler.tick(digest(time(8, 30), sent), utc(10, 9, 47), false).is_empty());
+        assert!(DigestScheduler::default().tick(digest(time(8, 30), sent), utc(10, 9, 50), false).is_empty());
+    }
+
+    #[test]
+    fn misses_past_the_catch_up_window_are_skipped() {
+        let mut scheduler = DigestScheduler::default();
+        assert!(scheduler.tick(digest(time(8, 30), None), utc(10, 10, 31), false).is_empty());
+        assert_eq!(digest_due(time(8, 30), FixedOffset::east_opt(0).unwrap(), utc(10, 10, 31), None), DigestDue::Missed);
+        // Tomorrow's slot fires normally
+        assert_eq!(scheduler.tick(digest(time(8, 30), None), utc(11, 8, 31), false).len(), 1);
+    }
+
+    #[test]
+    fn clock_jumps_never_fire_twice() {
+        let utc0 = FixedOffset::east_opt(0).unwrap();
+        let sent = Some(utc(10, 8, 31));
+        // NTP moves the clock back over the slot
+        assert_eq!(digest_due(time(8, 30), utc0, utc(10, 8, 29), sent), DigestDue::No);
+        assert_eq!(digest_due(time(8, 30), utc0, utc(10, 8, 32), sent), DigestDue::No);
+        // Forward past the slot: one delayed digest, then nothing
+        assert_eq!(digest_due(time(8, 30), utc0, utc(10, 9, 40), Some(utc(9, 8, 30))), DigestDue::Delayed);
+        assert_eq!(digest_due(time(8, 30), utc0, utc(10, 9, 41), Some(utc(10, 9, 40))), DigestDue::No);
+    }
+
+    fn offset(hours: i32) -> FixedOffset {
+        FixedOffset::east_opt(hours * 3600).unwrap()
+    }
+
+    #[test]
+    fn slots_follow_the_chat_timezone() {
+        // 00:10 in UTC+3 is 21:10 UTC the day before
+        assert_eq!(digest_due(time(0, 10), offset(3), utc(9, 21, 12), Some(utc(8, 21, 10))), DigestDue::OnTime);
+        assert_eq!(digest_due(time(0, 10), offset(3), utc(9, 21, 8), Some(utc(8, 21, 10))), DigestDue::No);
+        // 23:30 in UTC+3 is 20:30 UTC; ten minutes on it is delayed
+        assert_eq!(digest_due(time(23, 30), offset(3), utc(10, 20, 40), None), DigestDue::Delayed);
+        // 22:00 in UTC-5 is 03:00 UTC the next day: yesterday's local slot, today's UTC date
+        assert_eq!(digest_due(time(22, 0), offset(-5), utc(11, 3, 2), Some(utc(10, 3, 0))), DigestDue::OnTime);
+        assert_eq!(digest_due(time(22, 0), offset(-5), utc(11, 3, 2), Some(utc(11, 3, 1))), DigestDue::No);
+        // Right after local midnight the latest slot is still yesterday's
+        assert_eq!(digest_due(time(23, 58), offset(3), utc(10, 21, 1), Some(utc(9, 20, 58))), DigestDue::OnTime);
+    }
+
+    #[test]
+    fn grace_and_catch_up_boundaries() {
+        let utc0 = offset(0);
+        let grace = limits::DIGEST_GRACE_MINUTES as u32;
+        let catchup = limits::digest_catchup().num_minutes() as u32;
+        let late = |minutes: u32| digest_due(time(6, 0), utc0, utc(10, 6, 0) + chrono::Duration::minutes(minutes as i64), None);
+        assert_eq!(late(0), DigestDue::OnTime);
+        assert_eq!(late(grace - 1), DigestDue::OnTime);
+        assert_eq!(late(grace), DigestDue::Delayed);
+        assert_eq!(late(catchup), DigestDue::Delayed);
+        assert_eq!(late(catchup + 1), DigestDue::Missed);
+    }
+
+    #[test]
+    fn maintenance_holds_digests_until_lifted() {
+        let mut scheduler = DigestScheduler::default();
+        let mut digests = digest(time(8, 30), Some(utc(9, 8, 30)));
+        digests.push((ChatId(8), Digest { command: "market".into(), at: time(8, 30), last_sent: None }, offset(0)));
+        assert!(scheduler.tick(digests.clone(), utc(10, 8, 31), true).is_empty());
+        assert!(scheduler.tick(digests.clone(), utc(10, 9, 0), true).is_empty());
+
+        // Lifted past the catch-up window: the held digests still go out, once, labeled
+        digests.pop();
+        let fired = scheduler.tick(digests.clone(), utc(10, 12, 0), false);
+        assert_eq!(fired, [DigestFire { chat_id: ChatId(7), command: "war".into(), delayed: true }]);
+        assert!(scheduler.tick(digests, utc(10, 12, 1), false).is_empty());
+    }
+
+    #[test]
+    fn held_digests_are_bounded() {
+        let mut scheduler = DigestScheduler::default();
+        let digests: Vec<_> = (0..limits::DIGEST_QUEUE_CAP as i64 + 3)
+            .map(|chat| (ChatId(chat), Digest { command: "war".into(), at: time(8, 30), last_sent: None }, offset(0)))
+            .collect();
+        assert!(scheduler.tick(digests.clone(), utc(10, 8, 31), true).is_empty());
+        // The overflow is past catch-up by the time maintenance ends, so only the held ones fire
+        assert_eq!(scheduler.tick(digests, utc(10, 12, 0), false).len(), limits::DIGEST_QUEUE_CAP);
+    }
+
+    fn item(title: &str, minute: u32) -> NewsItem {
+        NewsItem {
+            title: title.into(),
+            description: None,
+            link: Some(format!("https://example.com/{}", title.to_lowercase().replace(' ', "-"))),
+            time_str: String::new(),
+            author: None,
+            feed_title: None,
+            views: None,
+            quote: None,
+            timestamp: Some(utc(10, 9, minute)),
+            also_on: Vec::new(),
+        }
+    }
+
+    fn polled(items: Vec<NewsItem>) -> Vec<SourceResult> {
+        vec![
+            SourceResult::new(&crate::consts::SOURCES[0], Ok(items), Default::default()),
+            SourceResult::new(&crate::consts::SOURCES[1], Err(crate::network::FetchError::Status(502)), Default::default()),
+        ]
+    }
+
+    #[test]
+    fn only_items_not_delivered_before_are_pushed() {
+        let mut delivered = Seen::default();
+        let names = |fresh: Vec<(&str, &NewsItem)>| fresh.into_iter().map(|(_, item)| item.title.clone()).collect::<Vec<_>>();
+        let first = polled(vec![item("Ceasefire talks", 1), item("Port strike", 2)]);
+        assert_eq!(names(fresh_items(&first, &mut delivered)), ["Port strike", "Ceasefire talks"]);
+
+        // Nothing new, nothing to send
+        assert!(fresh_items(&first, &mut delivered).is_empty());
+
+        let mut quote = item("Gold $2,700", 9);
+        quote.quote = Some(Quote { price: "$2,700".into(), change: None, value: Some(2700.0), change_percent: None, currency: "$" });
+        let next = polled(vec![item("Ceasefire talks", 1), item("Markets rally", 5), item("Port strike ends", 7), quote]);
+        assert_eq!(names(fresh_items(&next, &mut delivered)), ["Port strike ends", "Markets rally"]);
+    }
+
+    #[test]
+    fn only_a_blocked_or_missing_chat_drops_its_subscriptions() {
+        use teloxide::{ApiError, RequestError};
+        for gone in [ApiError::BotBlocked, ApiError::BotKicked, ApiError::ChatNotFound, ApiError::UserDeactivated] {
+            assert!(is_chat_gone(&RequestError::Api(gone)));
+        }
+        let flood = RequestError::RetryAfter(teloxide::types::Seconds::from_seconds(5));
+        let io = RequestError::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        for transient in [flood, io, RequestError::Api(ApiError::MessageIsTooLong)] {
+            assert!(!is_chat_gone(&transient), "{:?}", transient);
+        }
+    }
+
+    #[test]
+    fn delivered_items_are_forgotten_oldest_first() {
+        let mut delivered = Seen::default();
+        for fp in 0..SEEN_CAPACITY as u64 + 1 {
+            assert!(delivered.insert(fp));
+        }
+        assert!(!delivered.insert(SEEN_CAPACITY as u64));
+        // The first one fell out and counts as new again
+        assert!(delivered.insert(0));
+        assert!(!delivered.insert(2));
+    }
+
+    fn profiles() -> Vec<Profile> {
+        let profile = |name: &str, commands: &[&str], at: Option<NaiveTime>| Profile {
+            name: name.into(),
+            commands: commands.iter().map(|c| c.to_string()).collect(),
+            at,
+        };
+        vec![
+            profile("day", &["market", "global"], Some(time(8, 0))),
+            profile("night", &["war", "liveuamap"], Some(time(22, 0))),
+            profile("travel", &["global"], None),
+        ]
+    }
+
+    fn scheduled(now: DateTime<Utc>, switched: Option<DateTime<Utc>>) -> Option<String> {
+        scheduled_profile(&profiles(), offset(3), now, switched).map(|p| p.name.clone())
+    }
+
+    #[test]
+    fn profiles_switch_at_their_start_in_the_chat_timezone() {
+        // 08:00 and 22:00 in UTC+3 are 05:00 and 19:00 UTC
+        let morning = Some(utc(10, 5, 0));
+        assert_eq!(scheduled(utc(10, 18, 59), morning), None);
+        assert_eq!(scheduled(utc(10, 19, 0), morning).as_deref(), Some("night"));
+        assert_eq!(scheduled(utc(10, 19, 1), Some(utc(10, 19, 0))), None);
+        assert_eq!(scheduled(utc(11, 5, 0), Some(utc(10, 19, 0))).as_deref(), Some("day"));
+        // Down over a start: caught up on the first tick, whatever the delay
+        assert_eq!(scheduled(utc(11, 4, 0), morning).as_deref(), Some("night"));
+        // Never switched: the profile of the latest start
+        assert_eq!(scheduled(utc(10, 12, 0), None).as_deref(), Some("day"));
+        assert_eq!(scheduled_profile(&profiles()[2..], offset(3), utc(10, 12, 0), None), None);
+    }
+
+    #[test]
+    fn switching_swaps_the_subscriptions_at_once() {
+        let mut settings = Settings { profiles: profiles(), ..Settings::default() };
+        settings.subscribe("global", 240).unwrap();
+        settings.subscribe("tass", 30).unwrap();
+        settings.switch_profile("day", utc(10, 5, 0)).unwrap();
+        let subscribed: Vec<(&str, u32)> = settings.subscriptions.iter().map(|s| (s.command.as_str(), s.interval_minutes)).collect();
+        assert_eq!(subscribed, [("market", limits::SUBSCRIPTION_DEFAULT_MINUTES), ("global", 240)]);
+        assert_eq!(settings.default_command(), Some("market"));
+        assert_eq!(settings.profile_switched, Some(utc(10, 5, 0)));
+
+        assert!(matches!(settings.switch_profile("weekend", utc(10, 6, 0)), Err(SettingsError::UnknownProfile(_))));
+        assert_eq!(settings.active_profile.as_deref(), Some("day"));
+    }
+
+    #[test]
+    fn a_manual_switch_holds_until_the_next_start() {
+        let mut settings = Settings { profiles: profiles(), ..Settings::default() };
+        settings.switch_profile("night", utc(10, 19, 0)).unwrap();
+        // By hand at 23:00 local
+        settings.switch_profile("travel", utc(10, 20, 0)).unwrap();
+        for now in [utc(10, 20, 1), utc(10, 23, 0), utc(11, 4, 59)] {
+            assert_eq!(scheduled_profile(&settings.profiles, offset(3), now, settings.profile_switched), None, "{}", now);
+        }
+        let next = scheduled_profile(&settings.profiles, offset(3), utc(11, 5, 0), settings.profile_switched).unwrap().name.clone();
+        assert_eq!(next, "day");
+        settings.switch_profile(&next, utc(11, 5, 0)).unwrap();
+        assert_eq!(settings.default_command(), Some("market"));
+    }
+}
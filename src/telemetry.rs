@@ -0,0 +1,100 @@
+//! Opt-in anonymized usage ping (cargo feature `telemetry` + TELEMETRY_URL)
+
+use crate::consts::SOURCES;
+use crate::state::CommandStats;
+use crate::storage::{load_json, save_json};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Bump when the payload shape changes
+pub const SCHEMA_VERSION: u32 = 1;
+const PING_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const INSTALL_ID_FILE: &str = "install_id.json";
+
+/// Everything that leaves the machine: no chat ids, no content
+#[derive(Debug, Serialize)]
+pub struct UsagePing {
+    pub schema: u32,
+    pub install_id: String,
+    pub version: &'static str,
+    pub features: Vec<&'static str>,
+    pub source_count: usize,
+    /// Command counts since the previous ping
+    pub commands: BTreeMap<String, u64>,
+}
+
+/// Random per-install id, generated on first run and kept in DATA_DIR
+fn install_id() -> String {
+    if let Some(id) = load_json::<String>(INSTALL_ID_FILE) {
+        return id;
+    }
+    let id = format!("{:032x}", rand::random::<u128>());
+    save_json(INSTALL_ID_FILE, &id);
+    id
+}
+
+/// Cargo features this binary was built with
+fn enabled_features() -> Vec<&'static str> {
+    [("telemetry", cfg!(feature = "telemetry"))]
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect()
+}
+
+pub fn build_ping(install_id: &str, commands: BTreeMap<String, u64>) -> UsagePing {
+    UsagePing {
+        schema: SCHEMA_VERSION,
+        install_id: install_id.to_string(),
+        version: env!("CARGO_PKG_VERSION"),
+        features: enabled_features(),
+        source_count: SOURCES.len(),
+        commands,
+    }
+}
+
+/// Post a ping once a day; failures are logged and never retried early
+pub fn spawn(url: String, stats: Arc<CommandStats>) {
+    tokio::spawn(async move {
+        let id = install_id();
+        let client = reqwest::Client::new();
+        let mut last = BTreeMap::new();
+        let mut ticker = tokio::time::interval(PING_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let now = stats.snapshot();
+            let delta = now
+                .iter()
+                .map(|(cmd, n)| (cmd.clone(), n - last.get(cmd).copied().unwrap_or(0)))
+                .filter(|(_, n)| *n > 0)
+                .collect();
+            last = now;
+            match client.post(&url).json(&build_ping(&id, delta)).send().await {
+                Ok(res) => log::debug!("Usage ping: {}", res.status()),
+                Err(e) => log::warn!("Usage ping failed: {}", e.without_url()),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_schema_is_pinned() {
+        let commands = BTreeMap::from([("global".to_string(), 3), ("status".to_string(), 1)]);
+        let ping = serde_json::to_value(build_ping("0f0f", commands)).unwrap();
+        assert_eq!(ping, serde_json::json!({
+            "schema": 1,
+            "install_id": "0f0f",
+            "version": env!("CARGO_PKG_VERSION"),
+            "features": ["telemetry"],
+            "source_count": SOURCES.len(),
+            "commands": { "global": 3, "status": 1 },
+        }));
+        assert!(!ping.to_string().contains("chat"));
+    }
+}
@@ -1,18 +1,183 @@
-use reqwest::Client;
-use serde::Deserialize;
-use std::error::Error;
-
-/// Structure to parse Google Translate response
-/// The response is a messy JSON array: [[["translated_text", "original", ...]], ...]
-#[derive(Deserialize, Debug)]
-struct TranslationResponse(Vec<Vec<Option<String>>>);
-// We use a simplified structure or just raw parsing because the structure is dynamic.
-// Actually, it's easier to parse as serde_json::Value for safety.
-
-pub async fn translate_text(client: &Client, text: &str, target_lang: &str) -> Result<String, Box<dyn Error>> {
-    // URL encoding is handled by reqwest query params
-    let url = "https://translate.googleapis.com/translate_a/single";
+use crate::utils::{fnv1a, progressive_delay};
+use reqwest::{Client, StatusCode};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Accepted translated/original length ratio
+const MIN_LENGTH_RATIO: f64 = 0.3;
+const MAX_LENGTH_RATIO: f64 = 3.0;
+/// Texts shorter than this skip the ratio check ("ОК" → "OK")
+const RATIO_MIN_CHARS: usize = 20;
+/// A word 4-gram seen this often means the backend is looping
+const MAX_NGRAM_REPEATS: usize = 3;
+/// Entities the endpoint sometimes reintroduces into plain text
+const RAW_ENTITIES: &[&str] = &["&amp;", "&quot;", "&#39;", "&lt;", "&gt;", "&nbsp;"];
+
+/// Longest text sent in one request; the endpoint truncates or rejects longer queries
+const MAX_QUERY_CHARS: usize = 5000;
+/// Longer texts are split into chunks of at most this many chars
+const CHUNK_CHARS: usize = 1500;
+/// Places a chunk may end, best first: after a paragraph, then after a sentence
+const SENTENCE_ENDS: &[&str] = &[". ", "! ", "? ", "… ", ".\t"];
+/// Backoff unit between retries of a failed request
+const RETRY_BASE_DELAY_MS: u64 = 500;
+/// The public Google endpoint; `EngineConfig` can point elsewhere
+pub const TRANSLATE_URL: &str = "https://translate.googleapis.com/translate_a/single";
+
+#[derive(Error, Debug)]
+pub enum TranslateError {
+    /// 429 from the endpoint; callers should stop translating for a while
+    #[error("rate limited{}", .retry_after.map(|d| format!(", retry after {}s", d.as_secs())).unwrap_or_default())]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("HTTP: {0}")]
+    Http(String),
+    #[error("empty response")]
+    EmptyResponse,
+    #[error("text too long ({0} chars)")]
+    TooLong(usize),
+    #[error("garbled translation ({0})")]
+    Garbled(&'static str),
+}
+
+impl From<reqwest::Error> for TranslateError {
+    fn from(err: reqwest::Error) -> Self {
+        TranslateError::Http(err.without_url().to_string())
+    }
+}
+
+/// Target languages offered by /lang
+pub const LANGUAGES: &[&str] = &["ru", "en", "uk", "de", "fr", "es", "it", "pl"];
+
+/// The allowlisted code for a user-typed language, e.g. "EN" → "en"
+pub fn supported_language(code: &str) -> Option<&'static str> {
+    LANGUAGES.iter().copied().find(|lang| lang.eq_ignore_ascii_case(code.trim()))
+}
+
+/// Letters only Russian uses among Cyrillic news languages, and letters Russian lacks
+const RUSSIAN_ONLY: &[char] = &['ы', 'э', 'ё', 'ъ'];
+const NOT_RUSSIAN: &[char] = &['і', 'ї', 'є', 'ґ', 'ў'];
+
+/// Whether text is already in `target_lang` and can skip the round trip.
+/// Only Russian is detected; other targets always translate.
+pub fn looks_like_target(text: &str, target_lang: &str) -> bool {
+    if target_lang != "ru" {
+        return false;
+    }
+    let lower = text.to_lowercase();
+    lower.contains(RUSSIAN_ONLY) && !lower.contains(NOT_RUSSIAN)
+}
+
+/// Hash of the original text and the target language
+type CacheKey = (u64, &'static str);
+
+/// Recent translations keyed by (text hash, target language), least recently used evicted first
+pub struct TranslationCache {
+    capacity: usize,
+    entries: Mutex<(HashMap<CacheKey, String>, VecDeque<CacheKey>)>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl TranslationCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: Mutex::default(), hits: AtomicU64::new(0), misses: AtomicU64::new(0) }
+    }
+
+    pub fn get(&self, text: &str, target_lang: &'static str) -> Option<String> {
+        let key = (fnv1a(text), target_lang);
+        let mut guard = self.entries.lock().unwrap();
+        let (map, order) = &mut *guard;
+        match map.get(&key) {
+            Some(translated) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                if let Some(pos) = order.iter().position(|k| *k == key) {
+                    order.remove(pos);
+                }
+                order.push_back(key);
+                Some(translated.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
 
+    pub fn insert(&self, text: &str, target_lang: &'static str, translated: String) {
+        let key = (fnv1a(text), target_lang);
+        let mut guard = self.entries.lock().unwrap();
+        let (map, order) = &mut *guard;
+        if map.insert(key, translated).is_none() {
+            order.push_back(key);
+        }
+        while order.len() > self.capacity {
+            if let Some(oldest) = order.pop_front() {
+                map.remove(&oldest);
+            }
+        }
+    }
+
+    /// Lookups served from cache and lookups that went to the network
+    pub fn hit_miss(&self) -> (u64, u64) {
+        (self.hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed))
+    }
+}
+
+/// Translate via the public Google endpoint, chunk by chunk for long texts.
+/// Whitespace between chunks is kept as is; if any chunk fails the whole text fails,
+/// so callers never show a half-translated post.
+pub async fn translate_text(client: &Client, endpoint: &str, text: &str, target_lang: &str) -> Result<String, TranslateError> {
+    let mut translated = String::with_capacity(text.len());
+    for chunk in split_chunks(text) {
+        let core = chunk.trim();
+        if core.is_empty() {
+            translated.push_str(chunk);
+            continue;
+        }
+        let start = chunk.len() - chunk.trim_start().len();
+        translated.push_str(&chunk[..start]);
+        translated.push_str(&translate_chunk(client, endpoint, core, target_lang).await?);
+        translated.push_str(&chunk[start + core.len()..]);
+    }
+    Ok(translated)
+}
+
+/// Pieces of at most CHUNK_CHARS that concatenate back to `text`. Cuts fall after a newline,
+/// else after a sentence end, else at a space; URLs hold no whitespace, so they stay whole.
+fn split_chunks(text: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while let Some((limit, _)) = rest.char_indices().nth(CHUNK_CHARS) {
+        let window = &rest[..limit];
+        let cut = window
+            .rfind('\n')
+            .map(|i| i + 1)
+            .or_else(|| SENTENCE_ENDS.iter().filter_map(|end| window.rfind(end).map(|i| i + end.len())).max())
+            .or_else(|| window.rfind(' ').map(|i| i + 1))
+            // One unbroken run longer than a chunk: cut at the next space, or not at all
+            .or_else(|| rest.find(' ').map(|i| i + 1))
+            .unwrap_or(rest.len());
+        chunks.push(&rest[..cut]);
+        rest = &rest[cut..];
+    }
+    if !rest.is_empty() {
+        chunks.push(rest);
+    }
+    chunks
+}
+
+/// One request. The response is a messy JSON array: [[["translated_text", "original", ...]], ...],
+/// so it is parsed as serde_json::Value rather than a fixed structure.
+async fn translate_chunk(client: &Client, endpoint: &str, text: &str, target_lang: &str) -> Result<String, TranslateError> {
+    let chars = text.chars().count();
+    if chars > MAX_QUERY_CHARS {
+        return Err(TranslateError::TooLong(chars));
+    }
+
+    // URL encoding is handled by reqwest query params
     let params = [
         ("client", "gtx"),
         ("sl", "auto"),      // Source language: auto-detect
@@ -21,13 +186,21 @@ pub async fn translate_text(client: &Client, text: &str, target_lang: &str) -> R
         ("q", text),
     ];
 
-    let response = client.get(url)
+    let response = client.get(endpoint)
         .query(&params)
         .send()
         .await?;
 
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok()?.trim().parse().ok())
+            .map(Duration::from_secs);
+        return Err(TranslateError::RateLimited { retry_after });
+    }
     if !response.status().is_success() {
-        return Err(format!("Translation failed: {}", response.status()).into());
+        return Err(TranslateError::Http(format!("status {}", response.status())));
     }
 
     let raw_json: serde_json::Value = response.json().await?;
@@ -38,7 +211,7 @@ pub async fn translate_text(client: &Client, text: &str, target_lang: &str) -> R
     if let Some(sentences) = raw_json.get(0).and_then(|v| v.as_array()) {
         for sentence in sentences {
             if let Some(s_arr) = sentence.as_array() {
-                if let Some(text_val) = s_arr.get(0).and_then(|v| v.as_str()) {
+                if let Some(text_val) = s_arr.first().and_then(|v| v.as_str()) {
                     translated_text.push_str(text_val);
                 }
             }
@@ -46,8 +219,233 @@ pub async fn translate_text(client: &Client, text: &str, target_lang: &str) -> R
     }
 
     if translated_text.is_empty() {
-        return Ok(text.to_string()); // Fallback to original
+        return Err(TranslateError::EmptyResponse);
+    }
+
+    if let Some(reason) = garbled_reason(text, &translated_text) {
+        return Err(TranslateError::Garbled(reason));
     }
 
     Ok(translated_text)
-}
\ No newline at end of file
+}
+
+/// `translate_text` with up to `attempts` tries for network and server errors.
+/// Rate limits and oversized texts are returned at once, retrying them only makes things worse.
+pub async fn translate_with_retry(client: &Client, endpoint: &str, text: &str, target_lang: &str, attempts: u32) -> Result<String, TranslateError> {
+    let mut attempt = 1;
+    loop {
+        match translate_text(client, endpoint, text, target_lang).await {
+            Err(TranslateError::Http(e)) if attempt < attempts => {
+                log::debug!("Translation attempt {}/{} failed ({}), retrying", attempt, attempts, e);
+                progressive_delay(RETRY_BASE_DELAY_MS, attempt).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Why a translation looks machine-garbled, if it does
+fn garbled_reason(original: &str, translated: &str) -> Option<&'static str> {
+    let (src_len, dst_len) = (original.chars().count(), translated.chars().count());
+    if src_len >= RATIO_MIN_CHARS {
+        let ratio = dst_len as f64 / src_len as f64;
+        if !(MIN_LENGTH_RATIO..=MAX_LENGTH_RATIO).contains(&ratio) {
+            return Some("length ratio");
+        }
+    }
+
+    if RAW_ENTITIES.iter().any(|e| translated.contains(e) && !original.contains(e)) {
+        return Some("raw html entity");
+    }
+
+    let words: Vec<String> = translated.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let mut counts: HashMap<&[String], usize> = HashMap::new();
+    for gram in words.windows(4) {
+        let seen = counts.entry(gram).or_insert(0);
+        *seen += 1;
+        if *seen >= MAX_NGRAM_REPEATS {
+            return Some("repeated 4-gram loop");
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Pseudo-Russian prose of about `chars` chars: words differ, so no 4-gram repeats,
+    /// with a sentence end every 12 words and a paragraph every 5 sentences
+    fn russian_text(chars: usize) -> String {
+        const SYLLABLES: &[&str] = &["ка", "ро", "ми", "ту", "ле", "на", "со", "ви", "же", "пра", "сть", "дно"];
+        let mut seed = 7u64;
+        let mut text = String::new();
+        for n in 0.. {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let word: String = (0..3).map(|i| SYLLABLES[(seed >> (20 + i * 8)) as usize % SYLLABLES.len()]).collect();
+            text.push_str(&word);
+            text.push_str(match n % 60 {
+                59 => ".\n\n",
+                n if n % 12 == 11 => ". ",
+                _ => " ",
+            });
+            if text.chars().count() >= chars {
+                break;
+            }
+        }
+        text
+    }
+
+    fn assert_chunked(text: &str) -> Vec<&str> {
+        let chunks = split_chunks(text);
+        assert_eq!(chunks.concat(), text);
+        assert!(chunks.iter().all(|c| c.chars().count() <= CHUNK_CHARS), "{:?}", chunks.iter().map(|c| c.chars().count()).collect::<Vec<_>>());
+        chunks
+    }
+
+    #[test]
+    fn chunks_end_at_a_newline_then_a_sentence_then_a_space() {
+        let sentence = "Центробанк сохранил ставку. ";
+        let paragraph = format!("{}\n", sentence.repeat(30));
+        let two_paragraphs = paragraph.clone() + &sentence.repeat(40);
+        assert_eq!(assert_chunked(&two_paragraphs)[0], paragraph);
+
+        let sentences = sentence.repeat(80);
+        let chunks = assert_chunked(&sentences);
+        assert!(chunks.len() > 1);
+        assert!(chunks[..chunks.len() - 1].iter().all(|c| c.ends_with(". ")), "{:?}", chunks);
+
+        let words = "слово ".repeat(400);
+        let chunks = assert_chunked(&words);
+        assert!(chunks.len() > 1 && chunks.iter().all(|c| c.ends_with("слово ")), "{:?}", chunks);
+    }
+
+    #[test]
+    fn urls_are_never_split() {
+        let url = "https://example.com/news/2026/10/central-bank-holds-rates-steady-amid-inflation-worries?utm_source=telegram";
+        for lead in [1400, 1450, 1480, 1495] {
+            let text = format!("{} {} и далее {}", "слово ".repeat(lead / 6), url, "текст ".repeat(200));
+            let chunks = assert_chunked(&text);
+            assert_eq!(chunks.iter().filter(|c| c.contains(url)).count(), 1, "lead {}", lead);
+            assert!(chunks.iter().all(|c| !c.contains("https://") || c.contains(url)), "lead {}", lead);
+        }
+        // A single unbroken run longer than a chunk is sent whole rather than cut
+        let run = "x".repeat(CHUNK_CHARS + 10);
+        assert_eq!(split_chunks(&format!("{} tail", run)), [format!("{} ", run).as_str(), "tail"]);
+    }
+
+    /// Answer `requests` translate calls on a local port with their own text, failing the
+    /// `fail_at`-th with a 500. Yields the endpoint and the texts received, in order.
+    async fn echo_endpoint(requests: usize, fail_at: Option<usize>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/translate_a/single", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut texts = Vec::new();
+            for n in 0..requests {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut head = Vec::new();
+                let mut buf = [0; 4096];
+                while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let read = socket.read(&mut buf).await.unwrap();
+                    if read == 0 { break; }
+                    head.extend_from_slice(&buf[..read]);
+                }
+                let head = String::from_utf8_lossy(&head);
+                let path = head.split_whitespace().nth(1).unwrap();
+                let url = reqwest::Url::parse(&format!("http://local{}", path)).unwrap();
+                let text = url.query_pairs().find(|(key, _)| key == "q").unwrap().1.into_owned();
+                let (status, body) = match fail_at == Some(n) {
+                    true => ("500 Internal Server Error", String::new()),
+                    false => ("200 OK", serde_json::json!([[[text, text]]]).to_string()),
+                };
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+                socket.write_all(response.as_bytes()).await.unwrap();
+                texts.push(text);
+            }
+            texts
+        });
+        (endpoint, handle)
+    }
+
+    #[tokio::test]
+    async fn long_texts_go_out_in_chunks_and_come_back_in_order() {
+        let text = russian_text(6000);
+        let chunks = split_chunks(&text);
+        assert!(chunks.len() >= 4, "{} chunks", chunks.len());
+        let (endpoint, server) = echo_endpoint(chunks.len(), None).await;
+
+        let translated = translate_text(&Client::new(), &endpoint, &text, "en").await.unwrap();
+        // The endpoint echoes, so the pieces must reassemble into the original, newlines included
+        assert_eq!(translated, text);
+        let sent = server.await.unwrap();
+        assert_eq!(sent, chunks.iter().map(|c| c.trim()).collect::<Vec<_>>());
+        assert!(sent.iter().all(|q| q.chars().count() <= CHUNK_CHARS));
+    }
+
+    #[tokio::test]
+    async fn one_failed_chunk_fails_the_whole_text() {
+        let text = russian_text(6000);
+        let (endpoint, server) = echo_endpoint(2, Some(1)).await;
+        let result = translate_text(&Client::new(), &endpoint, &text, "en").await;
+        assert!(matches!(result, Err(TranslateError::Http(_))), "{:?}", result);
+        // Nothing is sent after the failing chunk
+        assert_eq!(server.await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn garbled_translations_are_caught() {
+        let original = "Центробанк сохранил ключевую ставку на уровне 16% третье заседание подряд";
+        assert_eq!(garbled_reason(original, "Central"), Some("length ratio"));
+        assert_eq!(garbled_reason(original, &"The central bank kept its key rate. ".repeat(8)), Some("length ratio"));
+        assert_eq!(
+            garbled_reason(original, "The central bank kept the key rate at 16% for the third meeting &quot;in a row&quot;"),
+            Some("raw html entity")
+        );
+        assert_eq!(
+            garbled_reason(original, "The bank kept the rate the bank kept the rate the bank kept the rate"),
+            Some("repeated 4-gram loop")
+        );
+    }
+
+    #[test]
+    fn ordinary_translations_pass() {
+        let pairs = [
+            // Short texts skip the length ratio
+            ("ОК", "OK"),
+            ("Срочно", "Breaking news from the front line"),
+            ("Центробанк сохранил ключевую ставку на уровне 16% третье заседание подряд", "The central bank kept its key rate at 16% for the third meeting in a row"),
+            // An entity already in the original may stay
+            ("AT&amp;T и Verizon повысили тарифы для клиентов", "AT&amp;T and Verizon raised prices for customers"),
+            // Two repeats of a phrase are still prose
+            ("Нефть дорожает, нефть дорожает: трейдеры ждут решения ОПЕК+", "Oil is getting more expensive, oil is getting more expensive: traders await OPEC+"),
+        ];
+        for (original, translated) in pairs {
+            assert_eq!(garbled_reason(original, translated), None, "{}", translated);
+        }
+    }
+
+    #[test]
+    fn cache_evicts_the_least_recently_used() {
+        let cache = TranslationCache::new(2);
+        cache.insert("Нефть дорожает", "en", "Oil is getting more expensive".into());
+        cache.insert("Золото дешевеет", "en", "Gold is getting cheaper".into());
+        // Reading the oldest entry makes the other one the eviction candidate
+        assert!(cache.get("Нефть дорожает", "en").is_some());
+        cache.insert("Рубль укрепился", "en", "The ruble strengthened".into());
+
+        assert_eq!(cache.get("Золото дешевеет", "en"), None);
+        assert_eq!(cache.get("Нефть дорожает", "en").as_deref(), Some("Oil is getting more expensive"));
+        assert_eq!(cache.get("Рубль укрепился", "en").as_deref(), Some("The ruble strengthened"));
+        // The target language is part of the key
+        assert_eq!(cache.get("Рубль укрепился", "de"), None);
+        assert_eq!(cache.hit_miss(), (3, 2));
+
+        // Re-inserting a cached text replaces it without growing the cache
+        cache.insert("Рубль укрепился", "en", "The rouble firmed".into());
+        assert_eq!(cache.get("Нефть дорожает", "en").as_deref(), Some("Oil is getting more expensive"));
+        assert_eq!(cache.get("Рубль укрепился", "en").as_deref(), Some("The rouble firmed"));
+    }
+}
@@ -1,3 +1,7 @@
+use crate::consts::junk;
+use crate::network::NewsItem;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use std::collections::HashSet;
 use std::time::Duration;
 use tokio::time::sleep;
 
@@ -5,6 +9,12 @@ pub async fn fibonacci_delay(base_ms: u64) {
     sleep(Duration::from_millis(base_ms)).await;
 }
 
+/// Backoff before retry `attempt` (1-based): base × attempt plus up to half a base of jitter
+pub async fn progressive_delay(base_ms: u64, attempt: u32) {
+    let jitter = rand::random::<u64>() % (base_ms / 2 + 1);
+    sleep(Duration::from_millis(base_ms * attempt as u64 + jitter)).await;
+}
+
 pub fn clean_text(text: &str) -> String {
     let no_html = text.replace("<br>", "\n")
         .replace("<br/>", "\n")
@@ -21,21 +31,45 @@ pub fn clean_text(text: &str) -> String {
         .join("\n")
 }
 
-/// Фильтр мусора: Шоу, Спорт, Криминал (если это не новости войны)
+/// Tweet text from a Nitter feed: `clean_text` without retweet/reply prefixes and t.co or pic.twitter links
+pub fn clean_tweet(text: &str) -> String {
+    let mut text = clean_text(text);
+    for prefix in ["RT by @", "RT @", "R to @"] {
+        if let Some((_, body)) = text.strip_prefix(prefix).and_then(|rest| rest.split_once(": ")) {
+            text = body.to_string();
+            break;
+        }
+    }
+    let is_clutter = |word: &str| {
+        let word = word.trim_start_matches("https://").trim_start_matches("http://");
+        word.starts_with("t.co/") || word.starts_with("pic.twitter.com/")
+    };
+    text.lines()
+        .map(|line| line.split_whitespace().filter(|w| !is_clutter(w)).collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Фильтр мусора: пустые и слишком короткие заголовки, реклама, шоу, спорт, голые ссылки.
+/// Шаблоны живут в `consts::junk`, дополнительные задаются через JUNK_PATTERNS. Они
+/// сравниваются целыми словами и только с первой строкой: приписка «Подписывайтесь» в
+/// конце поста или «start» внутри слова новость не отбрасывают.
 pub fn is_junk(text: &str) -> bool {
     let t = text.trim().to_lowercase();
 
-    // 1. Системные сообщения Telegram
-    if t.contains("channel created") || t.contains("account created") { return true; }
-
-    // 2. Развлекательный мусор (фильтруем шоу, сериалы, спорт)
-    let junk_keywords = [
-        "football", "soccer", "sport", "match", "premier league",
-        "netflix", "series", "season", "episode", "show", "star", "celebrity",
-        "футбол", "спорт", "сериал", "шоу", "звезда", "эпизод"
-    ];
+    // 1. Пусто, слишком коротко или ни одной буквы/цифры (одинокий эмодзи)
+    if t.chars().count() < junk::min_title_chars() || !t.chars().any(char::is_alphanumeric) {
+        return true;
+    }
 
-    if junk_keywords.iter().any(|&k| t.contains(k)) {
+    // 2. Системные сообщения, реклама, развлекательный мусор
+    let headline = words(t.lines().next().unwrap_or_default());
+    let matches = |pattern: &String| {
+        let pattern = words(pattern);
+        !pattern.is_empty() && headline.windows(pattern.len()).any(|w| w == pattern.as_slice())
+    };
+    if junk::patterns().iter().any(matches) {
         return true;
     }
 
@@ -47,7 +81,261 @@ pub fn is_junk(text: &str) -> bool {
     false
 }
 
+/// Words of a lowercased line; '+' stays part of a word so "18+" is one
+fn words(line: &str) -> Vec<&str> {
+    line.split(|c: char| !(c.is_alphanumeric() || c == '+')).filter(|w| !w.is_empty()).collect()
+}
+
 pub fn truncate_text(s: &str, max_chars: usize) -> String {
     if s.chars().count() <= max_chars { return s.to_string(); }
     s.chars().take(max_chars).collect::<String>() + "..."
-}
\ No newline at end of file
+}
+
+/// Parse Telegram view counters like "842", "12.3K", "1,2K" or "1.5M"
+pub fn parse_view_count(text: &str) -> Option<u64> {
+    let t = text.trim().replace(',', ".");
+    let (number, multiplier) = match t.chars().last()? {
+        'K' | 'k' => (&t[..t.len() - 1], 1_000.0),
+        'M' | 'm' => (&t[..t.len() - 1], 1_000_000.0),
+        _ => (t.as_str(), 1.0),
+    };
+    let value: f64 = number.trim().parse().ok()?;
+    (value >= 0.0).then(|| (value * multiplier).round() as u64)
+}
+
+/// Render a count the way Telegram does: 842, 12.3K, 1.5M
+pub fn compact_count(n: u64) -> String {
+    match n {
+        0..=999 => n.to_string(),
+        1_000..=999_999 => format!("{:.1}K", n as f64 / 1_000.0).replace(".0K", "K"),
+        _ => format!("{:.1}M", n as f64 / 1_000_000.0).replace(".0M", "M"),
+    }
+}
+
+/// A scraped number like "2.654,30", "$71.23" or "(-1,1%)" given the page's decimal separator;
+/// everything but digits, sign and that separator is dropped as grouping or decoration
+pub fn parse_number(text: &str, decimal: char) -> Option<f64> {
+    let normalized: String = text
+        .chars()
+        .filter_map(|c| match c {
+            '0'..='9' | '-' | '+' => Some(c),
+            '−' => Some('-'),
+            c if c == decimal => Some('.'),
+            _ => None,
+        })
+        .collect();
+    normalized.parse().ok()
+}
+
+/// ISO 8601 / RFC 3339 timestamps, as in Telegram's datetime attribute
+pub fn parse_rfc3339(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text.trim()).ok().map(|t| t.with_timezone(&Utc))
+}
+
+/// NewsData pubDate, "2024-05-01 12:34:56" in UTC
+pub fn parse_newsdata_date(text: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(text.trim(), "%Y-%m-%d %H:%M:%S").ok().map(|t| t.and_utc())
+}
+
+/// A UTC offset typed by a user: "+3", "-5", "5.5", "+05:30" or "UTC+3", within -12..=+14 hours
+pub fn parse_utc_offset(text: &str) -> Option<FixedOffset> {
+    let t = text.trim();
+    let t = t.strip_prefix("UTC").or_else(|| t.strip_prefix("utc")).unwrap_or(t).trim();
+    let (sign, rest) = match t.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, t.strip_prefix('+').unwrap_or(t)),
+    };
+    let minutes = match rest.split_once(':') {
+        Some((h, m)) => h.parse::<i32>().ok()? * 60 + m.parse::<i32>().ok().filter(|m| (0..60).contains(m))?,
+        None => (rest.parse::<f64>().ok()? * 60.0).round() as i32,
+    } * sign;
+    (-12 * 60..=14 * 60).contains(&minutes).then(|| FixedOffset::east_opt(minutes * 60))?
+}
+
+/// "just now", "25m ago" or "2h ago" for recent items; "12:45" today and "03.05 12:45" before
+pub fn display_time(timestamp: DateTime<Utc>, now: DateTime<Utc>, offset: FixedOffset, relative_hours: i64) -> String {
+    let age = now - timestamp;
+    if age >= chrono::Duration::zero() && age < chrono::Duration::hours(relative_hours) {
+        return match age.num_minutes() {
+            0 => "just now".to_string(),
+            m @ 1..=59 => format!("{}m ago", m),
+            _ => format!("{}h ago", age.num_hours()),
+        };
+    }
+    let local = timestamp.with_timezone(&offset);
+    if local.date_naive() == now.with_timezone(&offset).date_naive() {
+        local.format("%H:%M").to_string()
+    } else {
+        local.format("%d.%m %H:%M").to_string()
+    }
+}
+
+/// "45s", "12m", "3h 5m" or "2d 4h"
+pub fn compact_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86_399 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86_400, secs % 86_400 / 3600),
+    }
+}
+
+/// Stable 64-bit FNV-1a hash, identical across builds and restarts
+pub fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Identity of an item: its link when present, otherwise the normalized title
+pub fn fingerprint(item: &NewsItem) -> u64 {
+    match &item.link {
+        Some(link) => fnv1a(link),
+        None => fnv1a(&item.title.to_lowercase().split_whitespace().collect::<Vec<_>>().join(" ")),
+    }
+}
+
+/// Lowercase word tokens of a title, punctuation stripped
+pub fn title_tokens(title: &str) -> HashSet<String> {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Jaccard similarity of two token sets, 0.0 when both are empty
+pub fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_counts_with_suffixes_and_comma_decimals() {
+        assert_eq!(parse_view_count("842"), Some(842));
+        assert_eq!(parse_view_count(" 12.3K "), Some(12_300));
+        assert_eq!(parse_view_count("1,2K"), Some(1_200));
+        assert_eq!(parse_view_count("1.5M"), Some(1_500_000));
+        assert_eq!(parse_view_count("2m"), Some(2_000_000));
+        assert_eq!(parse_view_count(""), None);
+        assert_eq!(parse_view_count("K"), None);
+        assert_eq!(parse_view_count("views"), None);
+        assert_eq!(parse_view_count("-3K"), None);
+    }
+
+    fn similarity(a: &str, b: &str) -> f64 {
+        jaccard(&title_tokens(a), &title_tokens(b))
+    }
+
+    #[test]
+    fn near_duplicate_headlines_collapse() {
+        let duplicates = [
+            ("Oil prices rise as OPEC extends supply cuts", "Oil prices rise as OPEC+ extends supply cuts!"),
+            ("Fed holds interest rates steady, signals two cuts this year", "Fed holds interest rates steady and signals two cuts this year"),
+            ("Центробанк сохранил ключевую ставку на уровне 16%", "ЦБ сохранил ключевую ставку на уровне 16%"),
+        ];
+        for (a, b) in duplicates {
+            assert!(similarity(a, b) >= crate::consts::limits::DEDUP_SIMILARITY, "{} / {}", a, b);
+        }
+        let distinct = [
+            ("Oil prices rise as OPEC extends supply cuts", "Gold prices fall as the dollar strengthens"),
+            ("Центробанк сохранил ключевую ставку", "Центробанк повысил ключевую ставку на 2 пункта"),
+        ];
+        for (a, b) in distinct {
+            assert!(similarity(a, b) < crate::consts::limits::DEDUP_SIMILARITY, "{} / {}", a, b);
+        }
+        assert_eq!(similarity("", "!!!"), 0.0);
+    }
+
+
+    #[test]
+    fn item_times_show_age_then_clock_time_in_the_offset() {
+        use chrono::TimeZone;
+        let now = Utc.with_ymd_and_hms(2030, 5, 3, 20, 30, 0).unwrap();
+        let utc3 = FixedOffset::east_opt(3 * 3600).unwrap();
+        let shown = |h: u32, m: u32, day: u32| display_time(Utc.with_ymd_and_hms(2030, 5, day, h, m, 0).unwrap(), now, utc3, 6);
+        assert_eq!(shown(20, 30, 3), "just now");
+        assert_eq!(shown(20, 5, 3), "25m ago");
+        assert_eq!(shown(18, 0, 3), "2h ago");
+        // Past the relative window: local clock time, with the date once it is another local day
+        assert_eq!(shown(9, 45, 3), "12:45");
+        assert_eq!(shown(20, 0, 2), "02.05 23:00");
+        assert_eq!(display_time(Utc.with_ymd_and_hms(2030, 5, 3, 18, 0, 0).unwrap(), now, utc3, 1), "21:00");
+        // A timestamp ahead of the clock is never "ago"; 21:30 UTC is already tomorrow in UTC+3
+        assert_eq!(display_time(Utc.with_ymd_and_hms(2030, 5, 3, 21, 30, 0).unwrap(), now, utc3, 6), "04.05 00:30");
+    }
+
+    #[test]
+    fn utc_offsets_as_users_type_them() {
+        let minutes = |text| parse_utc_offset(text).map(|o| o.local_minus_utc() / 60);
+        assert_eq!(minutes("+3"), Some(180));
+        assert_eq!(minutes("-5"), Some(-300));
+        assert_eq!(minutes("5.5"), Some(330));
+        assert_eq!(minutes("+05:30"), Some(330));
+        assert_eq!(minutes("UTC+3"), Some(180));
+        assert_eq!(minutes("utc-03:30"), Some(-210));
+        assert_eq!(minutes("0"), Some(0));
+        assert_eq!(minutes("+14"), Some(840));
+        assert_eq!(minutes("-12"), Some(-720));
+        for bad in ["+15", "-13", "+05:60", "UTC", "", "three"] {
+            assert_eq!(minutes(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn scraped_numbers_by_decimal_separator() {
+        // ru.investing.com groups with '.' and uses ',' as the decimal separator
+        assert_eq!(parse_number("2.654,30", ','), Some(2654.30));
+        assert_eq!(parse_number("(+0,52%)", ','), Some(0.52));
+        assert_eq!(parse_number("(-1,10%)", ','), Some(-1.10));
+        assert_eq!(parse_number("−3,4%", ','), Some(-3.4));
+        // oilprice.com is the other way round
+        assert_eq!(parse_number("$1,071.50", '.'), Some(1071.50));
+        assert_eq!(parse_number(" -1.45%", '.'), Some(-1.45));
+        assert_eq!(parse_number("(+0.12%)", '.'), Some(0.12));
+        for bad in ["", "n/a", "%", "--", "1.2.3"] {
+            assert_eq!(parse_number(bad, '.'), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn promo_posts_are_junk() {
+        for promo in [
+            "#реклама Лучшие курсы трейдинга со скидкой",
+            "Розыгрыш iPhone 15 среди подписчиков канала!",
+            "Подписывайтесь на наш второй канал",
+            "Only 18+ content in our new chat",
+            "Giveaway: win a brand new MacBook today",
+            "Use promo code NEWS for 20% off",
+            "Subscribe to our premium newsletter",
+            "Premier League: five things we learned",
+        ] {
+            assert!(is_junk(promo), "{}", promo);
+        }
+    }
+
+    #[test]
+    fn short_and_ordinary_headlines_are_kept() {
+        for news in [
+            "Oil falls 3%",
+            "Нефть дешевеет на 3%",
+            "OPEC+ extends supply cuts",
+            "Russia launches a series of strikes on Kyiv",
+            "Start of ceasefire talks shows early progress",
+            "Star witness testifies in the fraud trial",
+            "Курс рубля укрепился\nПодписывайтесь: t.me/example",
+            "Fed holds rates steady\nSubscribe for more updates",
+        ] {
+            assert!(!is_junk(news), "{}", news);
+        }
+        for noise in ["", "   ", "Oil up", "👇", "https://example.com/x", "🔥🔥🔥 !!!"] {
+            assert!(is_junk(noise), "{:?}", noise);
+        }
+    }
+}